@@ -6,6 +6,9 @@ use crate::env::{Index, Level};
 use crate::source::{Span, StringId};
 
 pub mod binary;
+pub mod deps;
+pub mod fold;
+pub mod layout;
 pub mod pretty;
 pub mod prim;
 pub mod semantics;
@@ -40,7 +43,7 @@ pub enum LocalInfo {
     Param,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Plicity {
     Explicit,
     Implicit,
@@ -344,6 +347,12 @@ def_prims! {
     PosType => "Pos",
     /// Type of stream references.
     RefType => "Ref",
+    /// Type of byte orders.
+    EndianType => "Endian",
+    /// Big-endian byte order.
+    EndianBig => "big_endian",
+    /// Little-endian byte order.
+    EndianLittle => "little_endian",
 
     /// Type of format descriptions.
     FormatType => "Format",
@@ -353,36 +362,52 @@ def_prims! {
     FormatU16Be => "u16be",
     /// Unsigned, 16-bit integer formats (little-endian).
     FormatU16Le => "u16le",
+    /// Unsigned, 16-bit integer formats (with a given byte order).
+    FormatU16 => "u16",
     /// Unsigned, 32-bit integer formats (big-endian).
     FormatU32Be => "u32be",
     /// Unsigned, 32-bit integer formats (little-endian).
     FormatU32Le => "u32le",
+    /// Unsigned, 32-bit integer formats (with a given byte order).
+    FormatU32 => "u32",
     /// Unsigned, 64-bit integer formats (big-endian).
     FormatU64Be => "u64be",
     /// Unsigned, 64-bit integer formats (little-endian).
     FormatU64Le => "u64le",
+    /// Unsigned, 64-bit integer formats (with a given byte order).
+    FormatU64 => "u64",
     /// Signed, two's complement, 8-bit integer formats.
     FormatS8 => "s8",
     /// Signed, two's complement, 16-bit integer formats (big-endian).
     FormatS16Be => "s16be",
     /// Signed, two's complement, 16-bit integer formats (little-endian).
     FormatS16Le => "s16le",
+    /// Signed, two's complement, 16-bit integer formats (with a given byte order).
+    FormatS16 => "s16",
     /// Signed, two's complement, 32-bit integer formats (big-endian).
     FormatS32Be => "s32be",
     /// Signed, two's complement, 32-bit integer formats (little-endian).
     FormatS32Le => "s32le",
+    /// Signed, two's complement, 32-bit integer formats (with a given byte order).
+    FormatS32 => "s32",
     /// Signed, two's complement, 64-bit integer formats (big-endian).
     FormatS64Be => "s64be",
     /// Signed, two's complement, 64-bit integer formats (little-endian).
     FormatS64Le => "s64le",
+    /// Signed, two's complement, 64-bit integer formats (with a given byte order).
+    FormatS64 => "s64",
     /// 32-bit, IEEE-754 floating point formats (big-endian).
     FormatF32Be => "f32be",
     /// 32-bit, IEEE-754 floating point formats (little-endian).
     FormatF32Le => "f32le",
+    /// 32-bit, IEEE-754 floating point formats (with a given byte order).
+    FormatF32 => "f32",
     /// 64-bit, IEEE-754 floating point formats (big-endian).
     FormatF64Be => "f64be",
     /// 64-bit, IEEE-754 floating point formats (little-endian).
     FormatF64Le => "f64le",
+    /// 64-bit, IEEE-754 floating point formats (with a given byte order).
+    FormatF64 => "f64",
     /// Repeat formats up to an unsigned 8-bit length.
     FormatRepeatLen8 => "repeat_len8",
     /// Repeat formats up to an unsigned 16-bit length.
@@ -391,6 +416,9 @@ def_prims! {
     FormatRepeatLen32 => "repeat_len32",
     /// Repeat formats up to an unsigned 64-bit length.
     FormatRepeatLen64 => "repeat_len64",
+    /// Repeat formats up to an unsigned 64-bit length, padding the start of
+    /// each element to a multiple of an alignment.
+    FormatRepeatAligned => "repeat_aligned",
     /// Repeat a format until the length of the given parse scope is reached.
     FormatRepeatUntilEnd => "repeat_until_end",
     /// Limit the format to an unsigned 8-bit byte length.
@@ -401,6 +429,20 @@ def_prims! {
     FormatLimit32 => "limit32",
     /// Limit the format to an unsigned 64-bit byte length.
     FormatLimit64 => "limit64",
+    /// Read a number of compressed bytes, and then read a format from the
+    /// decompressed data, using the decompressor registered for an algorithm.
+    FormatDecompress => "decompress",
+    /// Read a format, checking that the CRC-32 checksum of the bytes that it
+    /// was read from matches the given checksum.
+    FormatCrc32 => "crc32",
+    /// A format that reads exactly the given bytes, failing if any of them
+    /// are different.
+    FormatMagic => "magic",
+    /// Skip to the next stream position that is a multiple of an alignment,
+    /// and then read a format.
+    FormatAlign => "align",
+    /// A format that skips a number of bytes.
+    FormatPad => "pad",
     /// A format which returns the current position in the input stream.
     FormatStreamPos => "stream_pos",
     /// A format that links to another location in the binary data stream,
@@ -414,8 +456,12 @@ def_prims! {
     FormatFail => "fail",
     /// Unwrap an option, or fail to parse.
     FormatUnwrap => "unwrap",
+    /// Map a function over the representation of a format.
+    FormatMap => "map",
     /// Format representations.
     FormatRepr => "Repr",
+    /// The number of bytes that a format always reads.
+    FormatSize => "size_of",
 
     /// Reported errors.
     ReportedError => "reported_error",
@@ -575,6 +621,7 @@ def_prims! {
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd)]
 pub enum UIntStyle {
     Binary,
+    Octal,
     Decimal,
     Hexadecimal,
     /// A [four-character code](https://en.wikipedia.org/wiki/FourCC) (big-endian)
@@ -692,7 +739,7 @@ macro_rules! impl_styled_uint {
 impl_styled_uint!(u8, u16, u32, u64);
 
 pub trait UIntStyled<const N: usize>:
-    std::fmt::Display + Copy + std::fmt::LowerHex + std::fmt::Binary + ToBeBytes<N>
+    std::fmt::Display + Copy + std::fmt::LowerHex + std::fmt::Octal + std::fmt::Binary + ToBeBytes<N>
 {
 }
 
@@ -700,12 +747,14 @@ impl UIntStyle {
     pub fn format<T: UIntStyled<N>, const N: usize>(&self, number: T) -> String {
         match self {
             UIntStyle::Binary => format!("0b{number:b}"),
+            UIntStyle::Octal => format!("0o{number:o}"),
             UIntStyle::Decimal => number.to_string(),
             UIntStyle::Hexadecimal => format!("0x{number:x}"),
             UIntStyle::Ascii => {
                 let bytes = number.to_be_bytes();
                 if bytes.iter().all(|c| c.is_ascii() && !c.is_ascii_control()) {
                     let s = std::str::from_utf8(&bytes).unwrap(); // unwrap safe due to above check
+                    let s = s.replace('\\', "\\\\").replace('"', "\\\"");
                     format!("\"{s}\"")
                 } else {
                     format!("0x{number:x}")
@@ -722,6 +771,7 @@ impl UIntStyle {
             (Decimal, style) | (style, Decimal) => style,
             // When both styles are the same. Note: (Decimal, Decimal) is handled above
             (Binary, Binary) => Binary,
+            (Octal, Octal) => Octal,
             (Hexadecimal, Hexadecimal) => Hexadecimal,
             (Ascii, Ascii) => Ascii,
             // Otherwise use the default style
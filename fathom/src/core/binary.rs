@@ -4,13 +4,18 @@ use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::Debug;
+use std::ops::Range;
 use std::slice::SliceIndex;
 use std::sync::Arc;
 
 use crate::core::semantics::{self, ArcValue, Elim, Head, Value};
-use crate::core::{Const, Item, Module, Prim, Term, UIntStyle};
+use crate::core::{Const, Item, Module, Plicity, Prim, Term, UIntStyle};
 use crate::env::{EnvLen, SharedEnv, UniqueEnv};
-use crate::source::{Span, Spanned};
+use crate::source::{Span, Spanned, StringId};
+
+pub mod stream;
+pub mod walk;
+pub mod write;
 
 #[derive(Clone, Debug)]
 pub enum ReadError<'arena> {
@@ -19,7 +24,19 @@ pub enum ReadError<'arena> {
     UnknownItem,
     UnwrappedNone(Span),
     ReadFailFormat(Span),
+    ReadReportedError(Span),
     CondFailure(Span, ArcValue<'arena>),
+    UnknownDecompressor(Span, u32),
+    DecompressionFailed(Span),
+    ChecksumMismatch(Span, u32, u32),
+    MagicMismatch(Span, usize, u8, u8),
+    PositionInDecompressedData(Span),
+    UnsupportedInStream(Span),
+    /// A reference that has not been read yet was dereferenced while reading
+    /// from a seekable stream. This is handled by the
+    /// [stream reader][stream::StreamReader], which reads the reference and
+    /// then tries again.
+    UnreadRef(Span, usize, ArcValue<'arena>),
     BufferError(Span, BufferError),
 }
 
@@ -31,7 +48,17 @@ impl<'arena> fmt::Display for ReadError<'arena> {
             ReadError::UnwrappedNone(_) => f.write_str("unwrapped none"),
             ReadError::UnknownItem => f.write_str("unknown item"),
             ReadError::ReadFailFormat(_) => f.write_str("read a fail format"),
+            ReadError::ReadReportedError(_) => f.write_str("read a format containing errors"),
             ReadError::CondFailure(_, _) => f.write_str("conditional format failed"),
+            ReadError::UnknownDecompressor(_, _) => f.write_str("unknown decompressor"),
+            ReadError::DecompressionFailed(_) => f.write_str("decompression failed"),
+            ReadError::PositionInDecompressedData(_) => {
+                f.write_str("positions are not supported in decompressed data")
+            }
+            ReadError::UnsupportedInStream(_) => f.write_str("format is not supported in streams"),
+            ReadError::UnreadRef(_, _, _) => f.write_str("reference has not been read"),
+            ReadError::ChecksumMismatch(_, _, _) => f.write_str("checksum mismatch"),
+            ReadError::MagicMismatch(_, _, _, _) => f.write_str("unexpected magic bytes"),
             ReadError::BufferError(_, err) => fmt::Display::fmt(&err, f),
         }
     }
@@ -100,6 +127,15 @@ impl<'data> Buffer<'data> {
         })
     }
 
+    /// Return a buffer over `len` bytes, starting at a position relative to
+    /// the start of this buffer. Offsets in the returned buffer are measured
+    /// from the start of the window, so that formats embedded in a larger
+    /// container can be read as if they were the entire stream.
+    pub fn window(&self, start: usize, len: usize) -> Result<Buffer<'data>, BufferError> {
+        let end = usize::checked_add(start, len).ok_or(BufferError::PositionOverflow)?;
+        Ok(Buffer::from(self.get_relative(start..end)?))
+    }
+
     /// Get a slice of the bytes in the buffer, relative to the start of the
     /// buffer.
     fn get_relative<I: SliceIndex<[u8]>>(&self, index: I) -> Result<&'data I::Output, BufferError> {
@@ -202,6 +238,13 @@ impl<'data> BufferReader<'data> {
         Ok(*first)
     }
 
+    /// Read a slice of bytes and advance the offset into the buffer.
+    pub fn read_byte_slice(&mut self, len: usize) -> Result<&'data [u8], BufferError> {
+        let slice = self.get_relative(..len)?;
+        self.relative_offset += len;
+        Ok(slice)
+    }
+
     /// Read an array of bytes and advance the offset into the buffer.
     pub fn read_byte_array<const N: usize>(&mut self) -> Result<&'data [u8; N], BufferError> {
         let slice = self.get_relative(..N)?;
@@ -259,8 +302,28 @@ pub struct Context<'arena, 'data> {
     initial_buffer: Buffer<'data>,
     pending_formats: Vec<(usize, ArcValue<'arena>)>,
     cached_refs: HashMap<usize, Vec<ParsedRef<'arena>>>,
+    decompressors: HashMap<u32, Decompressor>,
+    /// Set while reading the contents of a `decompress` format
+    in_decompressed_data: bool,
+    /// Set when reading from a stream, where references can only be followed
+    /// if the stream can seek
+    in_stream: bool,
+    /// Set when reading from a stream that can seek to the positions of
+    /// references
+    stream_can_seek: bool,
+    /// The offset of the end of the data buffered by a stream reader, if more
+    /// data could still be read from the stream
+    stream_buffered_end: Option<usize>,
+    /// Set when a format that reads until the end of the data stopped at
+    /// `stream_buffered_end`, so the result depends on data that has not been
+    /// buffered yet
+    reached_stream_buffered_end: bool,
 }
 
+/// A function that decompresses a slice of data, returning `None` if the data
+/// could not be decompressed.
+pub type Decompressor = Box<dyn Fn(&[u8]) -> Option<Vec<u8>>>;
+
 pub struct ParsedRef<'arena> {
     /// The format that this reference was parsed with
     // Invariant: `format : Format`
@@ -270,6 +333,15 @@ pub struct ParsedRef<'arena> {
     pub expr: ArcValue<'arena>,
 }
 
+/// A field of a record format, along with the bytes that it was read from.
+pub struct ReadField<'arena> {
+    pub label: StringId,
+    /// The range of byte offsets that the field was read from
+    pub offsets: Range<usize>,
+    /// The expression that was parsed for this field
+    pub expr: ArcValue<'arena>,
+}
+
 impl<'arena, 'data> Context<'arena, 'data> {
     pub fn new(initial_buffer: Buffer<'data>) -> Context<'arena, 'data> {
         Context {
@@ -278,9 +350,26 @@ impl<'arena, 'data> Context<'arena, 'data> {
             initial_buffer,
             pending_formats: Vec::new(),
             cached_refs: HashMap::new(),
+            decompressors: HashMap::new(),
+            in_decompressed_data: false,
+            in_stream: false,
+            stream_can_seek: false,
+            stream_buffered_end: None,
+            reached_stream_buffered_end: false,
         }
     }
 
+    /// Register a decompressor for uses of `decompress` with the given
+    /// algorithm. This replaces any decompressor that was previously registered
+    /// for the algorithm.
+    pub fn add_decompressor(
+        &mut self,
+        algorithm: u32,
+        decompressor: impl Fn(&[u8]) -> Option<Vec<u8>> + 'static,
+    ) {
+        self.decompressors.insert(algorithm, Box::new(decompressor));
+    }
+
     fn eval_env(&mut self) -> semantics::EvalEnv<'arena, '_> {
         let elim_env = semantics::ElimEnv::new(&self.item_exprs, [][..].into());
         semantics::EvalEnv::new(elim_env, &mut self.local_exprs)
@@ -318,9 +407,61 @@ impl<'arena, 'data> Context<'arena, 'data> {
         Ok(self.cached_refs)
     }
 
+    /// Read a format from the start of the binary data, returning the value
+    /// that was read along with the number of bytes that it consumed. This
+    /// makes it possible to read several formats one after the other from the
+    /// same data. Links are not followed, limit formats consume all of the bytes
+    /// within their limit, and overlap formats consume bytes up to the end of
+    /// their longest field.
+    pub fn read_prefix(
+        mut self,
+        format: &Term<'arena>,
+    ) -> Result<(ArcValue<'arena>, usize), ReadError<'arena>> {
+        let format = self.eval_env().eval(format);
+        let mut reader = self.initial_buffer.reader();
+        let expr = self.read_format(&mut reader, &format)?;
+
+        Ok((expr, reader.relative_offset()))
+    }
+
+    /// Read the fields of a record format from the start of the binary data,
+    /// recording the offsets that each field was read from. Links in the
+    /// fields are not followed.
+    pub fn read_entrypoint_fields(
+        mut self,
+        format: &Term<'arena>,
+    ) -> Result<Vec<ReadField<'arena>>, ReadError<'arena>> {
+        let format = self.eval_env().eval(format);
+        let (labels, mut formats) = match format.as_ref() {
+            Value::FormatRecord(labels, formats) => (*labels, formats.clone()),
+            _ => return Err(ReadError::InvalidFormat(format.span())),
+        };
+
+        let mut reader = self.initial_buffer.reader();
+        let mut fields = Vec::with_capacity(labels.len());
+
+        for label in labels {
+            let (format, next_formats) = match self.elim_env().split_telescope(formats) {
+                Some(split) => split,
+                None => break,
+            };
+            let start = reader.offset()?;
+            let expr = self.read_format(&mut reader, &format)?;
+            let end = reader.offset()?;
+            fields.push(ReadField {
+                label: *label,
+                offsets: start..end,
+                expr: expr.clone(),
+            });
+            formats = next_formats(expr);
+        }
+
+        Ok(fields)
+    }
+
     fn read_format(
         &mut self,
-        reader: &mut BufferReader<'data>,
+        reader: &mut BufferReader<'_>,
         format: &ArcValue<'arena>,
     ) -> Result<ArcValue<'arena>, ReadError<'arena>> {
         match format.as_ref() {
@@ -399,7 +540,7 @@ impl<'arena, 'data> Context<'arena, 'data> {
     #[rustfmt::skip]
     fn read_prim(
         &mut self,
-        reader: &mut BufferReader<'data>,
+        reader: &mut BufferReader<'_>,
         prim: Prim,
         slice: &[Elim<'arena>],
         span: Span,
@@ -429,16 +570,26 @@ impl<'arena, 'data> Context<'arena, 'data> {
             (Prim::FormatRepeatLen16, [FunApp(_, len), FunApp(_, format)]) => self.read_repeat_len(reader, span, len, format),
             (Prim::FormatRepeatLen32, [FunApp(_, len), FunApp(_, format)]) => self.read_repeat_len(reader, span, len, format),
             (Prim::FormatRepeatLen64, [FunApp(_, len), FunApp(_, format)]) => self.read_repeat_len(reader, span, len, format),
+            (Prim::FormatRepeatAligned, [FunApp(_, len), FunApp(_, align), FunApp(_, format)]) => self.read_repeat_aligned(reader, span, len, align, format),
             (Prim::FormatRepeatUntilEnd, [FunApp(_,format)]) => self.read_repeat_until_end(reader, format),
             (Prim::FormatLimit8, [FunApp(_, limit), FunApp(_, format)]) => self.read_limit(reader, limit, format),
             (Prim::FormatLimit16, [FunApp(_, limit), FunApp(_, format)]) => self.read_limit(reader, limit, format),
             (Prim::FormatLimit32, [FunApp(_, limit), FunApp(_, format)]) => self.read_limit(reader, limit, format),
             (Prim::FormatLimit64, [FunApp(_, limit), FunApp(_, format)]) => self.read_limit(reader, limit, format),
+            (Prim::FormatDecompress, [FunApp(_, algorithm), FunApp(_, len), FunApp(_, format)]) => self.read_decompress(reader, span, algorithm, len, format),
+            (Prim::FormatCrc32, [FunApp(_, checksum), FunApp(_, format)]) => self.read_crc32(reader, span, checksum, format),
+            (Prim::FormatMagic, [FunApp(_, bytes)]) => read_magic(reader, span, bytes),
+            (Prim::FormatAlign, [FunApp(_, align), FunApp(_, format)]) => self.read_align(reader, align, format),
+            (Prim::FormatPad, [FunApp(_, len)]) => read_pad(reader, span, len),
+            (Prim::FormatLink | Prim::FormatDeref | Prim::FormatStreamPos, _) if self.in_decompressed_data => Err(ReadError::PositionInDecompressedData(span)),
+            (Prim::FormatLink | Prim::FormatDeref, _) if self.in_stream && !self.stream_can_seek => Err(ReadError::UnsupportedInStream(span)),
             (Prim::FormatLink, [FunApp(_, pos), FunApp(_, format)]) => self.read_link(span, pos, format),
-            (Prim::FormatDeref, [FunApp(_, format), FunApp(_, r#ref)]) => self.read_deref(format, r#ref),
+            (Prim::FormatDeref, [FunApp(_, format), FunApp(_, r#ref)]) => self.read_deref(span, format, r#ref),
             (Prim::FormatStreamPos, []) => read_stream_pos(reader, span),
             (Prim::FormatSucceed, [_, FunApp(_, elem)]) => Ok(elem.clone()),
             (Prim::FormatFail, []) => Err(ReadError::ReadFailFormat(span)),
+            (Prim::ReportedError, _) => Err(ReadError::ReadReportedError(span)),
+            (Prim::FormatMap, [_, FunApp(_, format), FunApp(_, map_fn)]) => self.read_map(reader, format, map_fn),
             (Prim::FormatUnwrap, [_, FunApp(_, option)]) => match option.match_prim_spine() {
                 Some((Prim::OptionSome, [_, FunApp(_, elem)])) => Ok(elem.clone()),
                 Some((Prim::OptionNone, [_])) => Err(ReadError::UnwrappedNone(span)),
@@ -450,7 +601,7 @@ impl<'arena, 'data> Context<'arena, 'data> {
 
     fn read_repeat_len(
         &mut self,
-        reader: &mut BufferReader<'data>,
+        reader: &mut BufferReader<'_>,
         span: Span,
         len: &ArcValue<'arena>,
         elem_format: &ArcValue<'arena>,
@@ -470,37 +621,66 @@ impl<'arena, 'data> Context<'arena, 'data> {
         Ok(Spanned::new(span, Arc::new(Value::ArrayLit(elem_exprs))))
     }
 
+    fn read_repeat_aligned(
+        &mut self,
+        reader: &mut BufferReader<'_>,
+        span: Span,
+        len: &ArcValue<'arena>,
+        align: &ArcValue<'arena>,
+        elem_format: &ArcValue<'arena>,
+    ) -> Result<ArcValue<'arena>, ReadError<'arena>> {
+        let len = match len.as_ref() {
+            Value::ConstLit(Const::U64(len, _)) => *len,
+            _ => return Err(ReadError::InvalidValue(len.span())),
+        };
+        let align_span = align.span();
+        let align = match align.as_ref() {
+            Value::ConstLit(Const::U64(align, _)) if *align > 0 => usize::try_from(*align)
+                .map_err(|_| BufferError::PositionOverflow.with_span(align_span))?,
+            _ => return Err(ReadError::InvalidValue(align_span)),
+        };
+
+        let elem_exprs = (0..len)
+            .map(|_| {
+                // Skip the padding before the start of the element
+                let offset = reader.offset().map_err(|err| err.with_span(align_span))?;
+                let padding = (align - offset % align) % align;
+                (reader.read_byte_slice(padding)).map_err(|err| err.with_span(align_span))?;
+
+                self.read_format(reader, elem_format)
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Spanned::new(span, Arc::new(Value::ArrayLit(elem_exprs))))
+    }
+
     fn read_repeat_until_end(
         &mut self,
-        reader: &mut BufferReader<'data>,
+        reader: &mut BufferReader<'_>,
         elem_format: &ArcValue<'arena>,
     ) -> Result<ArcValue<'arena>, ReadError<'arena>> {
-        let mut current_offset = reader.relative_offset();
         let mut elems = Vec::new();
 
-        loop {
-            match self.read_format(reader, elem_format) {
-                Ok(elem) => {
-                    elems.push(elem);
-                    current_offset = reader.relative_offset();
-                }
-                Err(ReadError::BufferError(_, BufferError::UnexpectedEndOfBuffer)) => {
-                    // unwrap shouldn't panic as we're rewinding to a known good offset
-                    // Should this be set to the end of the current buffer?
-                    reader.set_relative_offset(current_offset).unwrap();
-                    return Ok(Spanned::new(
-                        elem_format.span(),
-                        Arc::new(Value::ArrayLit(elems)),
-                    ));
-                }
-                Err(err) => return Err(err),
-            };
+        // Stop when the end of the buffer is reached between elements, so that
+        // an element that is cut short by the end of the buffer is an error
+        while reader.remaining_len() > 0 {
+            elems.push(self.read_format(reader, elem_format)?);
+        }
+        // More elements might follow in the part of the stream that has not
+        // been buffered yet
+        if !self.in_decompressed_data && reader.offset().ok() == self.stream_buffered_end {
+            self.reached_stream_buffered_end = true;
         }
+
+        Ok(Spanned::new(
+            elem_format.span(),
+            Arc::new(Value::ArrayLit(elems)),
+        ))
     }
 
     fn read_limit(
         &mut self,
-        reader: &BufferReader<'data>,
+        reader: &mut BufferReader<'_>,
         len: &ArcValue<'arena>,
         elem_format: &ArcValue<'arena>,
     ) -> Result<ArcValue<'arena>, ReadError<'arena>> {
@@ -518,8 +698,92 @@ impl<'arena, 'data> Context<'arena, 'data> {
             .remaining_buffer()
             .and_then(|buf| buf.with_remaining_len(len))
             .map_err(|err| err.with_span(len_span))?;
+        let expr = self.read_format(&mut buffer.reader(), elem_format)?;
+
+        // Continue from the end of the limited region
+        (reader.read_byte_slice(len)).map_err(|err| err.with_span(len_span))?;
 
-        self.read_format(&mut buffer.reader(), elem_format)
+        Ok(expr)
+    }
+
+    fn read_decompress(
+        &mut self,
+        reader: &mut BufferReader<'_>,
+        span: Span,
+        algorithm: &ArcValue<'arena>,
+        len: &ArcValue<'arena>,
+        elem_format: &ArcValue<'arena>,
+    ) -> Result<ArcValue<'arena>, ReadError<'arena>> {
+        let algorithm = match algorithm.as_ref() {
+            Value::ConstLit(Const::U32(algorithm, _)) => *algorithm,
+            _ => return Err(ReadError::InvalidValue(algorithm.span())),
+        };
+        let len_span = len.span();
+        let len = match len.as_ref() {
+            Value::ConstLit(Const::U64(len, _)) => usize::try_from(*len)
+                .map_err(|_| BufferError::PositionOverflow.with_span(len_span))?,
+            _ => return Err(ReadError::InvalidValue(len_span)),
+        };
+
+        let decompressor = (self.decompressors.get(&algorithm))
+            .ok_or(ReadError::UnknownDecompressor(span, algorithm))?;
+        let compressed = reader
+            .read_byte_slice(len)
+            .map_err(|err| err.with_span(len_span))?;
+        let data = decompressor(compressed).ok_or(ReadError::DecompressionFailed(span))?;
+
+        // Offsets in the decompressed data are relative to its start, and
+        // pending links and cached references are resolved against the initial
+        // buffer, so formats that depend on positions are rejected.
+        let in_decompressed_data = std::mem::replace(&mut self.in_decompressed_data, true);
+        let result = self.read_format(&mut Buffer::from(&data[..]).reader(), elem_format);
+        self.in_decompressed_data = in_decompressed_data;
+        result
+    }
+
+    fn read_crc32(
+        &mut self,
+        reader: &mut BufferReader<'_>,
+        span: Span,
+        checksum: &ArcValue<'arena>,
+        elem_format: &ArcValue<'arena>,
+    ) -> Result<ArcValue<'arena>, ReadError<'arena>> {
+        let expected = match checksum.as_ref() {
+            Value::ConstLit(Const::U32(checksum, _)) => *checksum,
+            _ => return Err(ReadError::InvalidValue(checksum.span())),
+        };
+
+        // Read the bytes covered by the format again to compute their checksum
+        let mut start = reader.clone();
+        let expr = self.read_format(reader, elem_format)?;
+        let data = start.read_byte_slice(reader.relative_offset() - start.relative_offset())?;
+
+        match crc32(data) {
+            actual if actual == expected => Ok(expr),
+            actual => Err(ReadError::ChecksumMismatch(span, expected, actual)),
+        }
+    }
+
+    fn read_align(
+        &mut self,
+        reader: &mut BufferReader<'_>,
+        align: &ArcValue<'arena>,
+        elem_format: &ArcValue<'arena>,
+    ) -> Result<ArcValue<'arena>, ReadError<'arena>> {
+        let align_span = align.span();
+        let align = match align.as_ref() {
+            Value::ConstLit(Const::U64(align, _)) if *align > 0 => usize::try_from(*align)
+                .map_err(|_| BufferError::PositionOverflow.with_span(align_span))?,
+            _ => return Err(ReadError::InvalidValue(align_span)),
+        };
+
+        let offset = reader.offset().map_err(|err| err.with_span(align_span))?;
+        let padding = (align - offset % align) % align;
+        reader
+            .read_byte_slice(padding)
+            .map_err(|err| err.with_span(align_span))?;
+
+        self.read_format(reader, elem_format)
     }
 
     fn read_link(
@@ -543,6 +807,7 @@ impl<'arena, 'data> Context<'arena, 'data> {
 
     fn read_deref(
         &mut self,
+        span: Span,
         format: &ArcValue<'arena>,
         r#ref: &ArcValue<'arena>,
     ) -> Result<ArcValue<'arena>, ReadError<'arena>> {
@@ -551,9 +816,30 @@ impl<'arena, 'data> Context<'arena, 'data> {
             _ => return Err(ReadError::InvalidValue(r#ref.span())),
         };
 
+        // References in streams are read by the stream reader, which seeks to
+        // their positions
+        if self.in_stream {
+            return match self.lookup_ref(pos, format) {
+                Some(parsed_ref) => Ok(parsed_ref.expr.clone()),
+                None => Err(ReadError::UnreadRef(span, pos, format.clone())),
+            };
+        }
+
         self.lookup_or_read_ref(pos, format)
     }
 
+    fn read_map(
+        &mut self,
+        reader: &mut BufferReader<'_>,
+        format: &ArcValue<'arena>,
+        map_fn: &ArcValue<'arena>,
+    ) -> Result<ArcValue<'arena>, ReadError<'arena>> {
+        let expr = self.read_format(reader, format)?;
+        Ok(self
+            .elim_env()
+            .fun_app(Plicity::Explicit, map_fn.clone(), expr))
+    }
+
     fn lookup_ref<'context>(
         &'context self,
         pos: usize,
@@ -593,16 +879,20 @@ impl<'arena, 'data> Context<'arena, 'data> {
             panic!("recursion found when storing cached reference {pos}");
         }
 
-        // Store the parsed reference in the reference cache
+        self.insert_ref(pos, format, expr.clone());
+
+        Ok(expr)
+    }
+
+    /// Store a parsed reference in the reference cache.
+    fn insert_ref(&mut self, pos: usize, format: &ArcValue<'arena>, expr: ArcValue<'arena>) {
         self.cached_refs
             .entry(pos)
             .or_insert_with(|| Vec::with_capacity(1))
             .push(ParsedRef {
                 format: format.clone(),
-                expr: expr.clone(),
+                expr,
             });
-
-        Ok(expr)
     }
 }
 
@@ -618,6 +908,56 @@ fn read_stream_pos<'arena>(
     ))
 }
 
+fn read_magic<'arena>(
+    reader: &mut BufferReader<'_>,
+    span: Span,
+    bytes: &ArcValue<'arena>,
+) -> Result<ArcValue<'arena>, ReadError<'arena>> {
+    let bytes = match bytes.as_ref() {
+        Value::ArrayLit(bytes) => bytes,
+        _ => return Err(ReadError::InvalidValue(bytes.span())),
+    };
+
+    for byte in bytes {
+        let expected = match byte.as_ref() {
+            Value::ConstLit(Const::U8(byte, _)) => *byte,
+            _ => return Err(ReadError::InvalidValue(byte.span())),
+        };
+        let offset = reader.offset().map_err(|err| err.with_span(span))?;
+        let found = reader.read_byte().map_err(|err| err.with_span(span))?;
+        if found != expected {
+            return Err(ReadError::MagicMismatch(span, offset, expected, found));
+        }
+    }
+
+    Ok(Spanned::new(
+        span,
+        Arc::new(Value::RecordLit(&[], Vec::new())),
+    ))
+}
+
+fn read_pad<'arena>(
+    reader: &mut BufferReader<'_>,
+    span: Span,
+    len: &ArcValue<'arena>,
+) -> Result<ArcValue<'arena>, ReadError<'arena>> {
+    let len_span = len.span();
+    let len = match len.as_ref() {
+        Value::ConstLit(Const::U64(len, _)) => {
+            usize::try_from(*len).map_err(|_| BufferError::PositionOverflow.with_span(len_span))?
+        }
+        _ => return Err(ReadError::InvalidValue(len_span)),
+    };
+    reader
+        .read_byte_slice(len)
+        .map_err(|err| err.with_span(span))?;
+
+    Ok(Spanned::new(
+        span,
+        Arc::new(Value::RecordLit(&[], Vec::new())),
+    ))
+}
+
 fn read_const<'arena, 'data, T>(
     reader: &mut BufferReader<'data>,
     span: Span,
@@ -642,9 +982,7 @@ fn read_s8(reader: &mut BufferReader<'_>) -> Result<i8, BufferError> {
 /// Generates a function that reads a multi-byte primitive.
 macro_rules! read_multibyte_prim {
     ($read_multibyte_prim:ident, $from_bytes:ident, $T:ident) => {
-        fn $read_multibyte_prim<'data>(
-            reader: &mut BufferReader<'data>,
-        ) -> Result<$T, BufferError> {
+        fn $read_multibyte_prim(reader: &mut BufferReader<'_>) -> Result<$T, BufferError> {
             Ok($T::$from_bytes(*reader.read_byte_array()?))
         }
     };
@@ -666,3 +1004,687 @@ read_multibyte_prim!(read_f32le, from_le_bytes, f32);
 read_multibyte_prim!(read_f32be, from_be_bytes, f32);
 read_multibyte_prim!(read_f64le, from_le_bytes, f64);
 read_multibyte_prim!(read_f64be, from_be_bytes, f64);
+
+/// Compute the CRC-32 checksum of some data, using the polynomial from
+/// ISO 3309, as used in formats like PNG, ZIP and gzip.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffff_u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                0 => crc >> 1,
+                _ => (crc >> 1) ^ 0xedb88320,
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use scoped_arena::Scope;
+
+    use super::*;
+    use crate::source::StringInterner;
+
+    fn app<'arena>(head: &'arena Term<'arena>, arg: &'arena Term<'arena>) -> Term<'arena> {
+        Term::FunApp(Span::Empty, Plicity::Explicit, head, arg)
+    }
+
+    /// The fields of a record that was read, panicking if it is not a record.
+    fn record_fields<'arena>(expr: &ArcValue<'arena>) -> Vec<ArcValue<'arena>> {
+        match expr.as_ref() {
+            Value::RecordLit(_, exprs) => exprs.clone(),
+            _ => panic!("expected a record"),
+        }
+    }
+
+    #[test]
+    fn read_window() {
+        let mut interner = StringInterner::new();
+        let labels = ["a", "b", "c"].map(|label| interner.get_or_intern(label));
+        let scope = Scope::new();
+        let u16be = Term::Prim(Span::Empty, Prim::FormatU16Be);
+        let u8 = Term::Prim(Span::Empty, Prim::FormatU8);
+
+        // The window excludes the bytes on either side of it
+        let data = [0xff, 0xff, 0x00, 0x01, 0x00, 0x02, 0xff];
+        let buffer = Buffer::from(&data[..]).window(2, 4).unwrap();
+        assert_eq!(buffer.start_offset(), 0);
+        assert_eq!(buffer.remaining_len(), 4);
+
+        // { a <- u16be, b <- u16be }
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope_from_iter(labels[..2].iter().copied()),
+            scope.to_scope_from_iter([u16be.clone(), u16be.clone()]),
+        );
+        let refs = Context::new(buffer).read_entrypoint(&format).unwrap();
+        let exprs = record_fields(&refs[&0][0].expr);
+        assert!(matches!(
+            exprs[0].as_ref(),
+            Value::ConstLit(Const::U16(1, _))
+        ));
+        assert!(matches!(
+            exprs[1].as_ref(),
+            Value::ConstLit(Const::U16(2, _))
+        ));
+
+        // { a <- u16be, b <- u16be, c <- u8 }
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope_from_iter([u16be.clone(), u16be, u8]),
+        );
+        assert!(matches!(
+            Context::new(buffer).read_entrypoint(&format),
+            Err(ReadError::BufferError(
+                _,
+                BufferError::UnexpectedEndOfBuffer
+            )),
+        ));
+        assert!(matches!(
+            Buffer::from(&data[..]).window(4, 4),
+            Err(BufferError::UnexpectedEndOfBuffer),
+        ));
+    }
+
+    #[test]
+    fn read_repeat_aligned() {
+        let mut interner = StringInterner::new();
+        let labels = ["tag", "value"].map(|label| interner.get_or_intern(label));
+        let scope = Scope::new();
+
+        // repeat_aligned 2 align { tag <- u8, value <- u16be }
+        let format = |align| {
+            let u64_lit = |n| {
+                scope.to_scope(Term::ConstLit(
+                    Span::Empty,
+                    Const::U64(n, UIntStyle::Decimal),
+                ))
+            };
+            let head = scope.to_scope(Term::Prim(Span::Empty, Prim::FormatRepeatAligned));
+            let head = scope.to_scope(app(head, u64_lit(2)));
+            let head = scope.to_scope(app(head, u64_lit(align)));
+            let record_format = Term::FormatRecord(
+                Span::Empty,
+                scope.to_scope(labels),
+                scope.to_scope_from_iter([
+                    Term::Prim(Span::Empty, Prim::FormatU8),
+                    Term::Prim(Span::Empty, Prim::FormatU16Be),
+                ]),
+            );
+            app(head, scope.to_scope(record_format))
+        };
+
+        // A byte of padding is inserted between the two records
+        let data = [0x01, 0x00, 0x0a, 0xff, 0x02, 0x00, 0x0b];
+        let refs = (Context::new(Buffer::from(&data[..])).read_entrypoint(&format(4))).unwrap();
+        let elem_exprs = match refs[&0][0].expr.as_ref() {
+            Value::ArrayLit(exprs) => exprs.clone(),
+            _ => panic!("expected an array"),
+        };
+        let fields = elem_exprs.iter().map(|expr| {
+            let exprs = record_fields(expr);
+            match (exprs[0].as_ref(), exprs[1].as_ref()) {
+                (Value::ConstLit(Const::U8(tag, _)), Value::ConstLit(Const::U16(value, _))) => {
+                    (*tag, *value)
+                }
+                _ => panic!("expected constants"),
+            }
+        });
+        assert_eq!(fields.collect::<Vec<_>>(), [(0x01, 0x0a), (0x02, 0x0b)]);
+
+        // Alignments must be positive
+        assert!(matches!(
+            Context::new(Buffer::from(&data[..])).read_entrypoint(&format(0)),
+            Err(ReadError::InvalidValue(_)),
+        ));
+    }
+
+    #[test]
+    fn read_stream_pos_field() {
+        let mut interner = StringInterner::new();
+        let labels = ["tag", "pos", "value"].map(|label| interner.get_or_intern(label));
+        let scope = Scope::new();
+
+        // { tag <- u8, pos <- stream_pos, value <- u32be }
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope_from_iter([
+                Term::Prim(Span::Empty, Prim::FormatU8),
+                Term::Prim(Span::Empty, Prim::FormatStreamPos),
+                Term::Prim(Span::Empty, Prim::FormatU32Be),
+            ]),
+        );
+
+        let data = [0xff, 0xff, 0x00, 0x00, 0x00, 0x02];
+        let buffer = Buffer::from(&data[..]).window(1, 5).unwrap();
+        let refs = Context::new(buffer).read_entrypoint(&format).unwrap();
+        let exprs = record_fields(&refs[&0][0].expr);
+        // The position is the offset of the next field, and takes up no bytes
+        assert!(matches!(exprs[1].as_ref(), Value::ConstLit(Const::Pos(1))));
+        assert!(matches!(
+            exprs[2].as_ref(),
+            Value::ConstLit(Const::U32(2, _))
+        ));
+    }
+
+    #[test]
+    fn read_link() {
+        use crate::env::Index;
+
+        let mut interner = StringInterner::new();
+        let labels =
+            ["start", "offset", "data", "trailer"].map(|label| interner.get_or_intern(label));
+        let scope = Scope::new();
+        let scoped_app = |head, arg| app(scope.to_scope(head), scope.to_scope(arg));
+
+        // link (pos_add_u8 start offset) u16be
+        let pos = scoped_app(
+            scoped_app(
+                Term::Prim(Span::Empty, Prim::PosAddU8),
+                Term::LocalVar(Span::Empty, Index::last().prev()),
+            ),
+            Term::LocalVar(Span::Empty, Index::last()),
+        );
+        let link = scoped_app(
+            scoped_app(Term::Prim(Span::Empty, Prim::FormatLink), pos),
+            Term::Prim(Span::Empty, Prim::FormatU16Be),
+        );
+        // { start <- stream_pos, offset <- u8, data <- link ..., trailer <- u8 }
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope_from_iter([
+                Term::Prim(Span::Empty, Prim::FormatStreamPos),
+                Term::Prim(Span::Empty, Prim::FormatU8),
+                link,
+                Term::Prim(Span::Empty, Prim::FormatU8),
+            ]),
+        );
+
+        let data = [0x02, 0xee, 0x12, 0x34];
+        let refs = Context::new(Buffer::from(&data[..]))
+            .read_entrypoint(&format)
+            .unwrap();
+        let exprs = record_fields(&refs[&0][0].expr);
+        // Links do not consume any bytes from the current stream
+        assert!(matches!(exprs[2].as_ref(), Value::ConstLit(Const::Ref(2))));
+        assert!(matches!(
+            exprs[3].as_ref(),
+            Value::ConstLit(Const::U8(0xee, _))
+        ));
+        assert!(matches!(
+            refs[&2][0].expr.as_ref(),
+            Value::ConstLit(Const::U16(0x1234, _))
+        ));
+
+        // Links past the end of the buffer
+        let data = [0x04, 0xee, 0x12, 0x34];
+        assert!(matches!(
+            Context::new(Buffer::from(&data[..])).read_entrypoint(&format),
+            Err(ReadError::BufferError(..)),
+        ));
+    }
+
+    #[test]
+    fn read_tag_dispatch() {
+        use crate::env::Index;
+
+        let mut interner = StringInterner::new();
+        let labels = ["tag", "payload"].map(|label| interner.get_or_intern(label));
+        let scope = Scope::new();
+
+        // match tag { 1 => u16be, _ => u32be }
+        let payload = Term::ConstMatch(
+            Span::Empty,
+            scope.to_scope(Term::LocalVar(Span::Empty, Index::last())),
+            scope.to_scope([(
+                Const::U8(1, UIntStyle::Decimal),
+                Term::Prim(Span::Empty, Prim::FormatU16Be),
+            )]),
+            Some((
+                None,
+                scope.to_scope(Term::Prim(Span::Empty, Prim::FormatU32Be)),
+            )),
+        );
+        // { tag <- u8, payload <- match tag { ... } }
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope([Term::Prim(Span::Empty, Prim::FormatU8), payload]),
+        );
+        let read_payload = |data: &[u8]| {
+            let refs = Context::new(Buffer::from(data))
+                .read_entrypoint(&format)
+                .unwrap();
+            record_fields(&refs[&0][0].expr)[1].clone()
+        };
+
+        assert!(matches!(
+            read_payload(&[0x01, 0x12, 0x34]).as_ref(),
+            Value::ConstLit(Const::U16(0x1234, _))
+        ));
+        assert!(matches!(
+            read_payload(&[0x02, 0x00, 0x00, 0x12, 0x34]).as_ref(),
+            Value::ConstLit(Const::U32(0x1234, _))
+        ));
+    }
+
+    #[test]
+    fn read_prefix() {
+        let mut interner = StringInterner::new();
+        let labels = ["a", "b"].map(|label| interner.get_or_intern(label));
+        let scope = Scope::new();
+        let data = [0x00, 0x01, 0x02, 0x03, 0x04];
+
+        // { a <- u16be, b <- u8 }
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope([
+                Term::Prim(Span::Empty, Prim::FormatU16Be),
+                Term::Prim(Span::Empty, Prim::FormatU8),
+            ]),
+        );
+        let (expr, consumed) =
+            (Context::new(Buffer::from(&data[..])).read_prefix(&format)).unwrap();
+        assert!(matches!(expr.as_ref(), Value::RecordLit(_, exprs) if exprs.len() == 2));
+        assert_eq!(consumed, 3);
+
+        // The remaining data can be read with another format
+        let buffer = Buffer::from(&data[..])
+            .window(consumed, data.len() - consumed)
+            .unwrap();
+        let u16be = Term::Prim(Span::Empty, Prim::FormatU16Be);
+        let (expr, consumed) = Context::new(buffer).read_prefix(&u16be).unwrap();
+        assert!(matches!(
+            expr.as_ref(),
+            Value::ConstLit(Const::U16(0x0304, _))
+        ));
+        assert_eq!(consumed, 2);
+
+        // overlap { a <- u16be, b <- u8 }
+        let format = Term::FormatOverlap(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope([
+                Term::Prim(Span::Empty, Prim::FormatU16Be),
+                Term::Prim(Span::Empty, Prim::FormatU8),
+            ]),
+        );
+        let (_, consumed) = (Context::new(Buffer::from(&data[..])).read_prefix(&format)).unwrap();
+        assert_eq!(consumed, 2);
+
+        // { a <- limit8 4 u16be, b <- u8 }
+        let limit = Term::FunApp(
+            Span::Empty,
+            Plicity::Explicit,
+            scope.to_scope(Term::FunApp(
+                Span::Empty,
+                Plicity::Explicit,
+                scope.to_scope(Term::Prim(Span::Empty, Prim::FormatLimit8)),
+                scope.to_scope(Term::ConstLit(
+                    Span::Empty,
+                    Const::U8(4, UIntStyle::Decimal),
+                )),
+            )),
+            scope.to_scope(Term::Prim(Span::Empty, Prim::FormatU16Be)),
+        );
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope([limit, Term::Prim(Span::Empty, Prim::FormatU8)]),
+        );
+        let (expr, consumed) =
+            (Context::new(Buffer::from(&data[..])).read_prefix(&format)).unwrap();
+        let exprs = record_fields(&expr);
+        assert!(matches!(
+            exprs[0].as_ref(),
+            Value::ConstLit(Const::U16(0x0001, _))
+        ));
+        // The field after the limit is read from the end of the limited region
+        assert!(matches!(
+            exprs[1].as_ref(),
+            Value::ConstLit(Const::U8(4, _))
+        ));
+        assert_eq!(consumed, 5);
+    }
+
+    #[test]
+    fn read_repeat_until_end() {
+        let scope = Scope::new();
+        let repeat_until_end = |elem| {
+            Term::FunApp(
+                Span::Empty,
+                Plicity::Explicit,
+                scope.to_scope(Term::Prim(Span::Empty, Prim::FormatRepeatUntilEnd)),
+                scope.to_scope(Term::Prim(Span::Empty, elem)),
+            )
+        };
+        let data = [0x01, 0x02, 0x03];
+
+        // repeat_until_end u8
+        let refs = Context::new(Buffer::from(&data[..]))
+            .read_entrypoint(&repeat_until_end(Prim::FormatU8))
+            .unwrap();
+        let elems = match refs[&0][0].expr.as_ref() {
+            Value::ArrayLit(elems) => elems.clone(),
+            _ => panic!("expected an array"),
+        };
+        assert_eq!(elems.len(), 3);
+        for (elem, n) in Iterator::zip(elems.iter(), 1..) {
+            assert!(matches!(elem.as_ref(), Value::ConstLit(Const::U8(m, _)) if *m == n));
+        }
+
+        // repeat_until_end u16be, with a trailing partial element
+        assert!(matches!(
+            Context::new(Buffer::from(&data[..]))
+                .read_entrypoint(&repeat_until_end(Prim::FormatU16Be)),
+            Err(ReadError::BufferError(
+                _,
+                BufferError::UnexpectedEndOfBuffer
+            )),
+        ));
+    }
+
+    #[test]
+    fn read_crc32() {
+        use crate::env::Index;
+
+        let mut interner = StringInterner::new();
+        let labels = ["checksum", "data"].map(|label| interner.get_or_intern(label));
+        let scope = Scope::new();
+
+        // { checksum <- u32be, data <- crc32 checksum (repeat_len8 9 u8) }
+        let len = Const::U8(9, UIntStyle::Decimal);
+        let data_format = app(
+            scope.to_scope(app(
+                scope.to_scope(Term::Prim(Span::Empty, Prim::FormatCrc32)),
+                scope.to_scope(Term::LocalVar(Span::Empty, Index::last())),
+            )),
+            scope.to_scope(app(
+                scope.to_scope(app(
+                    scope.to_scope(Term::Prim(Span::Empty, Prim::FormatRepeatLen8)),
+                    scope.to_scope(Term::ConstLit(Span::Empty, len)),
+                )),
+                scope.to_scope(Term::Prim(Span::Empty, Prim::FormatU8)),
+            )),
+        );
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope([Term::Prim(Span::Empty, Prim::FormatU32Be), data_format]),
+        );
+
+        let mut data = *b"\xcb\xf4\x39\x26123456789";
+        let context = Context::new(Buffer::from(&data[..]));
+        let refs = context.read_entrypoint(&format).unwrap();
+        assert!(matches!(
+            refs[&0][0].expr.as_ref(),
+            Value::RecordLit(_, exprs) if exprs.len() == 2,
+        ));
+
+        data[8] = b'0';
+        let context = Context::new(Buffer::from(&data[..]));
+        assert!(matches!(
+            context.read_entrypoint(&format),
+            Err(ReadError::ChecksumMismatch(_, 0xcbf43926, actual)) if actual != 0xcbf43926,
+        ));
+    }
+
+    #[test]
+    fn read_trailing_crc32() {
+        use crate::env::Index;
+
+        let mut interner = StringInterner::new();
+        let labels =
+            ["start", "data", "checksum", "verified"].map(|label| interner.get_or_intern(label));
+        let scope = Scope::new();
+        let scoped_app = |head, arg| app(scope.to_scope(head), scope.to_scope(arg));
+        let prim = |prim| Term::Prim(Span::Empty, prim);
+
+        // repeat_len8 9 u8
+        let data_format = || {
+            let len = Term::ConstLit(Span::Empty, Const::U8(9, UIntStyle::Decimal));
+            scoped_app(
+                scoped_app(prim(Prim::FormatRepeatLen8), len),
+                prim(Prim::FormatU8),
+            )
+        };
+        // link start (crc32 checksum (repeat_len8 9 u8))
+        let verified_format = scoped_app(
+            scoped_app(
+                prim(Prim::FormatLink),
+                Term::LocalVar(Span::Empty, Index::last().prev().prev()),
+            ),
+            scoped_app(
+                scoped_app(
+                    prim(Prim::FormatCrc32),
+                    Term::LocalVar(Span::Empty, Index::last()),
+                ),
+                data_format(),
+            ),
+        );
+        // { start <- stream_pos, data <- ..., checksum <- u32be, verified <- ... }
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope_from_iter([
+                prim(Prim::FormatStreamPos),
+                data_format(),
+                prim(Prim::FormatU32Be),
+                verified_format,
+            ]),
+        );
+
+        // The data is read again by the link once its checksum is known
+        let mut data = *b"123456789\xcb\xf4\x39\x26";
+        let context = Context::new(Buffer::from(&data[..]));
+        assert!(context.read_entrypoint(&format).is_ok());
+
+        data[0] = b'0';
+        let context = Context::new(Buffer::from(&data[..]));
+        assert!(matches!(
+            context.read_entrypoint(&format),
+            Err(ReadError::ChecksumMismatch(_, 0xcbf43926, actual)) if actual != 0xcbf43926,
+        ));
+    }
+
+    #[test]
+    fn read_magic() {
+        let scope = Scope::new();
+        let png_magic = *b"\x89PNG\r\n\x1a\n";
+
+        // magic "\x89PNG\r\n\x1a\n"
+        let bytes =
+            png_magic.map(|byte| Term::ConstLit(Span::Empty, Const::U8(byte, UIntStyle::Ascii)));
+        let format = Term::FunApp(
+            Span::Empty,
+            Plicity::Explicit,
+            scope.to_scope(Term::Prim(Span::Empty, Prim::FormatMagic)),
+            scope.to_scope(Term::ArrayLit(Span::Empty, scope.to_scope(bytes))),
+        );
+
+        let mut data = [0; 9];
+        data[..8].copy_from_slice(&png_magic);
+        let (expr, consumed) =
+            (Context::new(Buffer::from(&data[..])).read_prefix(&format)).unwrap();
+        assert!(matches!(expr.as_ref(), Value::RecordLit(&[], exprs) if exprs.is_empty()));
+        assert_eq!(consumed, 8);
+
+        // The offset of the first byte that does not match is reported
+        data[1] = b'p';
+        assert!(matches!(
+            Context::new(Buffer::from(&data[..])).read_prefix(&format),
+            Err(ReadError::MagicMismatch(_, 1, b'P', b'p')),
+        ));
+        // Mismatches are reported before the end of the data is reached
+        assert!(matches!(
+            Context::new(Buffer::from(&data[..4])).read_prefix(&format),
+            Err(ReadError::MagicMismatch(_, 1, b'P', b'p')),
+        ));
+        data[1] = b'P';
+        assert!(matches!(
+            Context::new(Buffer::from(&data[..4])).read_prefix(&format),
+            Err(ReadError::BufferError(
+                _,
+                BufferError::UnexpectedEndOfBuffer
+            )),
+        ));
+    }
+
+    #[test]
+    fn read_align() {
+        let mut interner = StringInterner::new();
+        let labels = ["tag", "value"].map(|label| interner.get_or_intern(label));
+        let scope = Scope::new();
+        let four = Term::ConstLit(Span::Empty, Const::U64(4, UIntStyle::Decimal));
+
+        // { tag <- u8, value <- align 4 u32be }
+        let value_format = app(
+            scope.to_scope(app(
+                scope.to_scope(Term::Prim(Span::Empty, Prim::FormatAlign)),
+                scope.to_scope(four.clone()),
+            )),
+            scope.to_scope(Term::Prim(Span::Empty, Prim::FormatU32Be)),
+        );
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope([Term::Prim(Span::Empty, Prim::FormatU8), value_format]),
+        );
+
+        let data = [0x01, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x02];
+        let (expr, consumed) =
+            (Context::new(Buffer::from(&data[..])).read_prefix(&format)).unwrap();
+        let exprs = record_fields(&expr);
+        assert!(matches!(
+            exprs[0].as_ref(),
+            Value::ConstLit(Const::U8(1, _))
+        ));
+        assert!(matches!(
+            exprs[1].as_ref(),
+            Value::ConstLit(Const::U32(2, _))
+        ));
+        assert_eq!(consumed, 8);
+
+        // The padding must be present in the data
+        assert!(matches!(
+            Context::new(Buffer::from(&data[..2])).read_prefix(&format),
+            Err(ReadError::BufferError(
+                _,
+                BufferError::UnexpectedEndOfBuffer
+            )),
+        ));
+
+        // align 0 u32be
+        let zero = Term::ConstLit(Span::Empty, Const::U64(0, UIntStyle::Decimal));
+        let zero_align_format = app(
+            scope.to_scope(app(
+                scope.to_scope(Term::Prim(Span::Empty, Prim::FormatAlign)),
+                scope.to_scope(zero),
+            )),
+            scope.to_scope(Term::Prim(Span::Empty, Prim::FormatU32Be)),
+        );
+        assert!(matches!(
+            Context::new(Buffer::from(&data[..])).read_prefix(&zero_align_format),
+            Err(ReadError::InvalidValue(_)),
+        ));
+
+        // { tag <- u8, padding <- pad 4, value <- u8 }
+        let labels = ["tag", "padding", "value"].map(|label| interner.get_or_intern(label));
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope([
+                Term::Prim(Span::Empty, Prim::FormatU8),
+                app(
+                    scope.to_scope(Term::Prim(Span::Empty, Prim::FormatPad)),
+                    scope.to_scope(four),
+                ),
+                Term::Prim(Span::Empty, Prim::FormatU8),
+            ]),
+        );
+        let (expr, consumed) =
+            (Context::new(Buffer::from(&data[..6])).read_prefix(&format)).unwrap();
+        let exprs = record_fields(&expr);
+        assert!(matches!(exprs[1].as_ref(), Value::RecordLit(&[], _)));
+        assert!(matches!(
+            exprs[2].as_ref(),
+            Value::ConstLit(Const::U8(0x00, _))
+        ));
+        assert_eq!(consumed, 6);
+    }
+
+    #[test]
+    fn read_decompress() {
+        let mut interner = StringInterner::new();
+        let labels = ["data", "trailer"].map(|label| interner.get_or_intern(label));
+        let scope = Scope::new();
+        let identity = u32::from_be_bytes(*b"copy");
+
+        // decompress algorithm 2 elem
+        let decompress = |algorithm, elem| {
+            let head = scope.to_scope(Term::Prim(Span::Empty, Prim::FormatDecompress));
+            let algorithm = Const::U32(algorithm, UIntStyle::Decimal);
+            let head = scope.to_scope(app(
+                head,
+                scope.to_scope(Term::ConstLit(Span::Empty, algorithm)),
+            ));
+            let len = Const::U64(2, UIntStyle::Decimal);
+            let head = scope.to_scope(app(head, scope.to_scope(Term::ConstLit(Span::Empty, len))));
+            app(head, scope.to_scope(Term::Prim(Span::Empty, elem)))
+        };
+        // { data <- decompress algorithm 2 u16be, trailer <- u8 }
+        let format = |algorithm| {
+            Term::FormatRecord(
+                Span::Empty,
+                scope.to_scope(labels),
+                scope.to_scope_from_iter([
+                    decompress(algorithm, Prim::FormatU16Be),
+                    Term::Prim(Span::Empty, Prim::FormatU8),
+                ]),
+            )
+        };
+
+        let data = [0x12, 0x34, 0xff];
+        let mut context = Context::new(Buffer::from(&data[..]));
+        context.add_decompressor(identity, |data| Some(data.to_vec()));
+        let refs = context.read_entrypoint(&format(identity)).unwrap();
+        let exprs = record_fields(&refs[&0][0].expr);
+        assert!(matches!(
+            exprs[0].as_ref(),
+            Value::ConstLit(Const::U16(0x1234, _))
+        ));
+        // Only the compressed bytes are consumed from the outer buffer
+        assert!(matches!(
+            exprs[1].as_ref(),
+            Value::ConstLit(Const::U8(0xff, _))
+        ));
+
+        let mut context = Context::new(Buffer::from(&data[..]));
+        context.add_decompressor(identity, |_| None);
+        assert!(matches!(
+            context.read_entrypoint(&format(identity)),
+            Err(ReadError::DecompressionFailed(_)),
+        ));
+
+        let context = Context::new(Buffer::from(&data[..]));
+        assert!(matches!(
+            context.read_entrypoint(&format(identity)),
+            Err(ReadError::UnknownDecompressor(_, algorithm)) if algorithm == identity,
+        ));
+
+        // Positions in the decompressed data do not refer to the initial buffer
+        let mut context = Context::new(Buffer::from(&data[..]));
+        context.add_decompressor(identity, |data| Some(data.to_vec()));
+        assert!(matches!(
+            context.read_prefix(&decompress(identity, Prim::FormatStreamPos)),
+            Err(ReadError::PositionInDecompressedData(_)),
+        ));
+    }
+}
@@ -0,0 +1,588 @@
+//! Reading formats from streams of binary data, without loading all of the
+//! data into memory up front.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+use super::{Buffer, BufferError, Context, ParsedRef, ReadError};
+use crate::core::semantics::{ArcValue, Value};
+use crate::core::{layout, Module, Term};
+use crate::env::EnvLen;
+use crate::source::Spanned;
+
+#[derive(Debug)]
+pub enum StreamError<'arena> {
+    /// The data could not be read from the stream.
+    Io(io::Error),
+    /// The data that was read from the stream did not match the format.
+    Read(ReadError<'arena>),
+}
+
+impl<'arena> fmt::Display for StreamError<'arena> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Io(err) => fmt::Display::fmt(&err, f),
+            StreamError::Read(err) => fmt::Display::fmt(&err, f),
+        }
+    }
+}
+
+impl<'arena> std::error::Error for StreamError<'arena> {}
+
+impl<'arena> From<io::Error> for StreamError<'arena> {
+    fn from(err: io::Error) -> StreamError<'arena> {
+        StreamError::Io(err)
+    }
+}
+
+impl<'arena> From<ReadError<'arena>> for StreamError<'arena> {
+    fn from(err: ReadError<'arena>) -> StreamError<'arena> {
+        StreamError::Read(err)
+    }
+}
+
+/// The minimum number of bytes to buffer at a time when reading fields whose
+/// size depends on the data being read.
+const CHUNK_LEN: usize = 4096;
+
+/// Reads formats one after the other from a stream of binary data.
+///
+/// Only the bytes needed for the current field are buffered when the size of
+/// the field can be determined statically. Fields whose size depends on the
+/// data being read are buffered in chunks of at least 4096 bytes, doubling
+/// the buffered data and reading the field again whenever it runs past the
+/// end. Bytes that a field does not consume are kept for subsequent
+/// reads, including when reading fails.
+///
+/// Streams that only implement [`Read`] can only be read forwards, so reading
+/// a link or deref format fails with [`ReadError::UnsupportedInStream`].
+/// Streams that also implement [`Seek`] can be read with
+/// [`StreamReader::new_seekable`], which follows references by seeking to
+/// their positions.
+pub struct StreamReader<'arena, R> {
+    context: Context<'arena, 'static>,
+    source: R,
+    /// Seek the source to a position, if it supports seeking
+    seek: Option<fn(&mut R, u64) -> io::Result<u64>>,
+    /// The offset of the next byte to be read, from the start of the stream
+    offset: usize,
+    /// Bytes that were taken from the source but have not been consumed yet
+    lookahead: Vec<u8>,
+    /// Set when the end of the source has been reached
+    at_end: bool,
+}
+
+impl<'arena, R: Read> StreamReader<'arena, R> {
+    pub fn new(source: R) -> StreamReader<'arena, R> {
+        let mut context = Context::new(Buffer::from(&[][..]));
+        context.in_stream = true;
+
+        StreamReader {
+            context,
+            source,
+            seek: None,
+            offset: 0,
+            lookahead: Vec::new(),
+            at_end: false,
+        }
+    }
+
+    /// Register a decompressor for uses of `decompress` with the given
+    /// algorithm. See [`Context::add_decompressor`].
+    pub fn add_decompressor(
+        &mut self,
+        algorithm: u32,
+        decompressor: impl Fn(&[u8]) -> Option<Vec<u8>> + 'static,
+    ) {
+        self.context.add_decompressor(algorithm, decompressor);
+    }
+
+    pub fn add_module(&mut self, module: &Module<'arena>) {
+        self.context.add_module(module);
+    }
+
+    /// The number of bytes that have been consumed from the stream.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Read a format from the current position in the stream.
+    pub fn read_format(
+        &mut self,
+        format: &Term<'arena>,
+    ) -> Result<ArcValue<'arena>, StreamError<'arena>> {
+        let format = self.context.eval_env().eval(format);
+
+        match format.as_ref() {
+            // Read the fields of records one at a time, so that only the
+            // fields with dynamic sizes need to be buffered in chunks
+            Value::FormatRecord(labels, formats) => {
+                let mut formats = formats.clone();
+                let mut exprs = Vec::with_capacity(formats.len());
+
+                while let Some((format, next_formats)) =
+                    self.context.elim_env().split_telescope(formats)
+                {
+                    let expr = self.read_value(&format)?;
+                    exprs.push(expr.clone());
+                    formats = next_formats(expr);
+                }
+
+                Ok(Spanned::new(
+                    format.span(),
+                    Arc::new(Value::RecordLit(labels, exprs)),
+                ))
+            }
+            _ => self.read_value(&format),
+        }
+    }
+
+    fn read_value(
+        &mut self,
+        format: &ArcValue<'arena>,
+    ) -> Result<ArcValue<'arena>, StreamError<'arena>> {
+        let size = layout::format_size(self.context.elim_env(), EnvLen::new(), format);
+        match size.and_then(|size| usize::try_from(size).ok()) {
+            Some(size) => self.fill_lookahead(size)?,
+            None => self.fill_lookahead(CHUNK_LEN)?,
+        }
+
+        loop {
+            self.context.stream_buffered_end =
+                (!self.at_end).then(|| self.offset + self.lookahead.len());
+            self.context.reached_stream_buffered_end = false;
+
+            let mut reader = Buffer::new(self.offset, &self.lookahead).reader();
+            let result = self.context.read_format(&mut reader, format);
+            let consumed = reader.relative_offset();
+
+            match result {
+                Ok(_) if self.context.reached_stream_buffered_end => self.fill_more_lookahead()?,
+                Ok(expr) => {
+                    self.offset += consumed;
+                    self.lookahead.drain(..consumed);
+                    return Ok(expr);
+                }
+                Err(ReadError::BufferError(_, BufferError::UnexpectedEndOfBuffer))
+                    if !self.at_end =>
+                {
+                    self.fill_more_lookahead()?;
+                }
+                Err(ReadError::UnreadRef(_, pos, ref_format)) if self.seek.is_some() => {
+                    self.read_ref(pos, &ref_format)?;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Read from the source until at least `len` bytes are buffered, or the
+    /// end of the source is reached. Bytes are only buffered once they have
+    /// been read, in case `len` was read from untrusted data.
+    fn fill_lookahead(&mut self, len: usize) -> io::Result<()> {
+        let remaining_len = len.saturating_sub(self.lookahead.len()) as u64;
+        if remaining_len > 0 && !self.at_end {
+            let read_len = (&mut self.source)
+                .take(remaining_len)
+                .read_to_end(&mut self.lookahead)?;
+            self.at_end = (read_len as u64) < remaining_len;
+        }
+        Ok(())
+    }
+
+    /// Double the number of buffered bytes.
+    fn fill_more_lookahead(&mut self) -> io::Result<()> {
+        let len = self.lookahead.len();
+        self.fill_lookahead(len + usize::max(len, CHUNK_LEN))
+    }
+
+    /// Read a reference by seeking to its position, caching the value that
+    /// was read. The stream is returned to its previous position afterwards,
+    /// even if reading the reference failed.
+    fn read_ref(
+        &mut self,
+        pos: usize,
+        format: &ArcValue<'arena>,
+    ) -> Result<ArcValue<'arena>, StreamError<'arena>> {
+        if let Some(parsed_ref) = self.context.lookup_ref(pos, format) {
+            return Ok(parsed_ref.expr.clone());
+        }
+        let seek = self
+            .seek
+            .expect("references are only read from seekable streams");
+
+        let offset = std::mem::replace(&mut self.offset, pos);
+        let lookahead = std::mem::take(&mut self.lookahead);
+        let at_end = std::mem::replace(&mut self.at_end, false);
+
+        let result = match seek(&mut self.source, pos as u64) {
+            Ok(_) => self.read_value(format),
+            Err(err) => Err(err.into()),
+        };
+
+        // Resume from the end of the previously buffered bytes
+        seek(&mut self.source, (offset + lookahead.len()) as u64)?;
+        self.offset = offset;
+        self.lookahead = lookahead;
+        self.at_end = at_end;
+
+        let expr = result?;
+        self.context.insert_ref(pos, format, expr.clone());
+        Ok(expr)
+    }
+}
+
+impl<'arena, R: Read + Seek> StreamReader<'arena, R> {
+    /// Construct a reader over a stream that can seek, which follows links
+    /// and dereferences to positions measured from the start of the stream.
+    /// The stream should be at its start.
+    pub fn new_seekable(source: R) -> StreamReader<'arena, R> {
+        let mut reader = StreamReader::new(source);
+        reader.context.stream_can_seek = true;
+        let seek: fn(&mut R, u64) -> io::Result<u64> =
+            |source, pos| source.seek(SeekFrom::Start(pos));
+        reader.seek = Some(seek);
+        reader
+    }
+
+    /// Read a format from the current position in the stream, and then follow
+    /// the links that it contains, in the same way as
+    /// [`Context::read_entrypoint`].
+    pub fn read_entrypoint(
+        mut self,
+        format: &Term<'arena>,
+    ) -> Result<HashMap<usize, Vec<ParsedRef<'arena>>>, StreamError<'arena>> {
+        let offset = self.offset;
+        let format = self.context.eval_env().eval(format);
+        self.context.pending_formats.push((offset, format));
+
+        while let Some((pos, format)) = self.context.pending_formats.pop() {
+            self.read_ref(pos, &format)?;
+        }
+
+        Ok(self.context.cached_refs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::io::Cursor;
+
+    use scoped_arena::Scope;
+
+    use super::*;
+    use crate::core::semantics::ElimEnv;
+    use crate::core::{Const, Plicity, Prim, UIntStyle};
+    use crate::env::{self, UniqueEnv};
+    use crate::source::{Span, StringInterner};
+
+    fn app<'arena>(
+        scope: &'arena Scope<'arena>,
+        plicity: Plicity,
+        head: Term<'arena>,
+        arg: Term<'arena>,
+    ) -> Term<'arena> {
+        Term::FunApp(
+            Span::Empty,
+            plicity,
+            scope.to_scope(head),
+            scope.to_scope(arg),
+        )
+    }
+
+    fn is_equal(value0: &ArcValue<'_>, value1: &ArcValue<'_>) -> bool {
+        let item_exprs = UniqueEnv::new();
+        (ElimEnv::new(&item_exprs, [][..].into()).conversion_env(EnvLen::new()))
+            .is_equal(value0, value1)
+    }
+
+    /// A source that produces each of its results in turn, and then ends.
+    struct Results(VecDeque<io::Result<Vec<u8>>>);
+
+    impl Read for Results {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.pop_front() {
+                None => Ok(0),
+                Some(Err(err)) => Err(err),
+                Some(Ok(mut data)) => {
+                    let len = usize::min(buf.len(), data.len());
+                    buf[..len].copy_from_slice(&data[..len]);
+                    if len < data.len() {
+                        self.0.push_front(Ok(data.split_off(len)));
+                    }
+                    Ok(len)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn read_cursor() {
+        let mut interner = StringInterner::new();
+        let labels = ["magic", "len", "data", "trailer"].map(|label| interner.get_or_intern(label));
+        let scope = Scope::new();
+        let data = [0x00, 0x01, 0x02, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e];
+
+        // { magic <- u16be, len <- u8, data <- repeat_len8 len u8, trailer <- u8 }
+        let data_format = app(
+            &scope,
+            Plicity::Explicit,
+            app(
+                &scope,
+                Plicity::Explicit,
+                Term::Prim(Span::Empty, Prim::FormatRepeatLen8),
+                Term::LocalVar(Span::Empty, env::Index::last()),
+            ),
+            Term::Prim(Span::Empty, Prim::FormatU8),
+        );
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope([
+                Term::Prim(Span::Empty, Prim::FormatU16Be),
+                Term::Prim(Span::Empty, Prim::FormatU8),
+                data_format,
+                Term::Prim(Span::Empty, Prim::FormatU8),
+            ]),
+        );
+
+        let mut stream = StreamReader::new(Cursor::new(data));
+        let expr = stream.read_format(&format).unwrap();
+        assert_eq!(stream.offset(), 6);
+
+        // The stream and slice readers produce the same values
+        let (slice_expr, consumed) =
+            (Context::new(Buffer::from(&data[..])).read_prefix(&format)).unwrap();
+        assert!(is_equal(&expr, &slice_expr));
+        assert_eq!(consumed, 6);
+
+        // The remaining bytes are still available to later reads
+        let u16be = Term::Prim(Span::Empty, Prim::FormatU16Be);
+        let expr = stream.read_format(&u16be).unwrap();
+        assert!(matches!(
+            expr.as_ref(),
+            Value::ConstLit(Const::U16(0x0d0e, _))
+        ));
+        assert_eq!(stream.offset(), 8);
+
+        // Reading past the end of the stream is a format error, not an IO error
+        assert!(matches!(
+            stream.read_format(&u16be),
+            Err(StreamError::Read(ReadError::BufferError(
+                _,
+                BufferError::UnexpectedEndOfBuffer,
+            ))),
+        ));
+    }
+
+    #[test]
+    fn read_truncated() {
+        let scope = Scope::new();
+
+        // repeat_len64 0x10000000000 u8
+        let len = Const::U64(1 << 40, UIntStyle::Hexadecimal);
+        let format = app(
+            &scope,
+            Plicity::Explicit,
+            app(
+                &scope,
+                Plicity::Explicit,
+                Term::Prim(Span::Empty, Prim::FormatRepeatLen64),
+                Term::ConstLit(Span::Empty, len),
+            ),
+            Term::Prim(Span::Empty, Prim::FormatU8),
+        );
+
+        // The buffer only grows to the length of the data in the stream
+        let mut stream = StreamReader::new(Cursor::new([0x00, 0x01]));
+        assert!(matches!(
+            stream.read_format(&format),
+            Err(StreamError::Read(ReadError::BufferError(
+                _,
+                BufferError::UnexpectedEndOfBuffer,
+            ))),
+        ));
+    }
+
+    #[test]
+    fn read_chunks() {
+        let mut interner = StringInterner::new();
+        let labels = ["header", "len", "data"].map(|label| interner.get_or_intern(label));
+        let scope = Scope::new();
+        let mut data = (0..3 * CHUNK_LEN).map(|i| i as u8).collect::<Vec<_>>();
+        data[0] = 16;
+
+        // { header <- { len <- u8, data <- repeat_len8 len u8 } }
+        let header_format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope_from_iter(labels[1..].iter().copied()),
+            scope.to_scope([
+                Term::Prim(Span::Empty, Prim::FormatU8),
+                app(
+                    &scope,
+                    Plicity::Explicit,
+                    app(
+                        &scope,
+                        Plicity::Explicit,
+                        Term::Prim(Span::Empty, Prim::FormatRepeatLen8),
+                        Term::LocalVar(Span::Empty, env::Index::last()),
+                    ),
+                    Term::Prim(Span::Empty, Prim::FormatU8),
+                ),
+            ]),
+        );
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope_from_iter(labels[..1].iter().copied()),
+            scope.to_scope([header_format]),
+        );
+
+        // The header has a dynamic size, but only one chunk is buffered to read it
+        let mut source = Cursor::new(&data[..]);
+        {
+            let mut stream = StreamReader::new(&mut source);
+            stream.read_format(&format).unwrap();
+            assert_eq!(stream.offset(), 17);
+        }
+        assert_eq!(source.position(), CHUNK_LEN as u64);
+
+        // Formats that read until the end continue past the end of each chunk
+        let format = app(
+            &scope,
+            Plicity::Explicit,
+            Term::Prim(Span::Empty, Prim::FormatRepeatUntilEnd),
+            Term::Prim(Span::Empty, Prim::FormatU8),
+        );
+        let mut stream = StreamReader::new(Cursor::new(&data[..]));
+        let expr = stream.read_format(&format).unwrap();
+        assert!(matches!(expr.as_ref(), Value::ArrayLit(exprs) if exprs.len() == data.len()));
+        assert_eq!(stream.offset(), data.len());
+    }
+
+    #[test]
+    fn read_io_error() {
+        let u16be = Term::Prim(Span::Empty, Prim::FormatU16Be);
+        let results = [
+            Ok(vec![0x01]),
+            Err(io::Error::new(io::ErrorKind::Other, "disconnected")),
+            Ok(vec![0x02]),
+        ];
+
+        // Failures of the source are reported as IO errors
+        let mut stream = StreamReader::new(Results(VecDeque::from(results)));
+        assert!(matches!(
+            stream.read_format(&u16be),
+            Err(StreamError::Io(err)) if err.kind() == io::ErrorKind::Other,
+        ));
+
+        // The bytes read before the failure are kept for the next read
+        let expr = stream.read_format(&u16be).unwrap();
+        assert!(matches!(
+            expr.as_ref(),
+            Value::ConstLit(Const::U16(0x0102, _))
+        ));
+        assert_eq!(stream.offset(), 2);
+    }
+
+    #[test]
+    fn read_format_error() {
+        let fail = Term::Prim(Span::Empty, Prim::FormatFail);
+        let u8 = Term::Prim(Span::Empty, Prim::FormatU8);
+
+        // Data that does not match the format is reported as a read error
+        let mut stream = StreamReader::new(Cursor::new([0x01]));
+        assert!(matches!(
+            stream.read_format(&fail),
+            Err(StreamError::Read(ReadError::ReadFailFormat(_))),
+        ));
+
+        // The buffered bytes are kept for the next read
+        let expr = stream.read_format(&u8).unwrap();
+        assert!(matches!(expr.as_ref(), Value::ConstLit(Const::U8(0x01, _))));
+        assert_eq!(stream.offset(), 1);
+    }
+
+    #[test]
+    fn read_link() {
+        let scope = Scope::new();
+
+        // link 0 u8
+        let format = app(
+            &scope,
+            Plicity::Explicit,
+            app(
+                &scope,
+                Plicity::Explicit,
+                Term::Prim(Span::Empty, Prim::FormatLink),
+                Term::ConstLit(Span::Empty, Const::Pos(0)),
+            ),
+            Term::Prim(Span::Empty, Prim::FormatU8),
+        );
+
+        let mut stream = StreamReader::new(Cursor::new([0x00]));
+        assert!(matches!(
+            stream.read_format(&format),
+            Err(StreamError::Read(ReadError::UnsupportedInStream(_))),
+        ));
+    }
+
+    #[test]
+    fn read_seekable() {
+        let mut interner = StringInterner::new();
+        let labels = ["table", "value", "rest"].map(|label| interner.get_or_intern(label));
+        let scope = Scope::new();
+        let data = [0xaa, 0xbb, 0x12, 0x34];
+
+        // { table <- link 2 u16be, value <- deref table, rest <- u16be }
+        let u16be = || Term::Prim(Span::Empty, Prim::FormatU16Be);
+        let link = app(
+            &scope,
+            Plicity::Explicit,
+            app(
+                &scope,
+                Plicity::Explicit,
+                Term::Prim(Span::Empty, Prim::FormatLink),
+                Term::ConstLit(Span::Empty, Const::Pos(2)),
+            ),
+            u16be(),
+        );
+        let deref = app(
+            &scope,
+            Plicity::Explicit,
+            app(
+                &scope,
+                Plicity::Implicit,
+                Term::Prim(Span::Empty, Prim::FormatDeref),
+                u16be(),
+            ),
+            Term::LocalVar(Span::Empty, env::Index::last()),
+        );
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope([link, deref, u16be()]),
+        );
+
+        // Dereferences seek to the linked data, and then resume the stream
+        let mut stream = StreamReader::new_seekable(Cursor::new(data));
+        let expr = stream.read_format(&format).unwrap();
+        let (slice_expr, _) = (Context::new(Buffer::from(&data[..])).read_prefix(&format)).unwrap();
+        assert!(is_equal(&expr, &slice_expr));
+        assert_eq!(stream.offset(), 2);
+
+        // Links are followed when reading an entrypoint
+        let refs = StreamReader::new_seekable(Cursor::new(data))
+            .read_entrypoint(&format)
+            .unwrap();
+        let slice_refs = (Context::new(Buffer::from(&data[..])).read_entrypoint(&format)).unwrap();
+        assert!(is_equal(&refs[&0][0].expr, &slice_refs[&0][0].expr));
+        assert!(is_equal(&refs[&2][0].expr, &slice_refs[&2][0].expr));
+        assert!(matches!(
+            refs[&2][0].expr.as_ref(),
+            Value::ConstLit(Const::U16(0x1234, _))
+        ));
+    }
+}
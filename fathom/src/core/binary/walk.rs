@@ -0,0 +1,146 @@
+//! Traversals of values that were read from binary data.
+//!
+//! These make it possible to visit every part of a read value without writing
+//! a recursive match over each kind of value, which is useful for generic
+//! transformations like validation or anonymisation.
+
+use std::fmt::Write;
+
+use crate::core::semantics::{ArcValue, Value};
+use crate::source::{StringId, StringInterner};
+
+/// A step from a value to one of the values that it contains.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PathStep {
+    /// A field of a record, identified by its label.
+    Field(StringId),
+    /// An element of an array, identified by its index.
+    Index(usize),
+}
+
+/// The path from the root of a value to one of the values that it contains.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValuePath {
+    pub steps: Vec<PathStep>,
+}
+
+impl ValuePath {
+    fn push(&self, step: PathStep) -> ValuePath {
+        let mut steps = Vec::with_capacity(self.steps.len() + 1);
+        steps.extend_from_slice(&self.steps);
+        steps.push(step);
+        ValuePath { steps }
+    }
+
+    /// Render the path with fields separated by dots and array indices in
+    /// brackets, for example `tables[2].offset`.
+    pub fn display(&self, interner: &StringInterner) -> String {
+        let mut output = String::new();
+        for step in &self.steps {
+            match step {
+                PathStep::Field(label) => {
+                    if !output.is_empty() {
+                        output.push('.');
+                    }
+                    output.push_str(interner.resolve(*label).unwrap());
+                }
+                PathStep::Index(index) => write!(output, "[{index}]").unwrap(),
+            }
+        }
+        output
+    }
+}
+
+/// An iterator over a value and all of the values that it contains.
+pub struct Walk<'a, 'arena> {
+    stack: Vec<(ValuePath, &'a ArcValue<'arena>)>,
+}
+
+/// Iterate over a value and all of the values that it contains, along with
+/// their paths from the root value. Values are visited before the values they
+/// contain, with record fields visited in order, and array elements visited by
+/// index.
+pub fn walk<'a, 'arena>(value: &'a ArcValue<'arena>) -> Walk<'a, 'arena> {
+    Walk {
+        stack: vec![(ValuePath::default(), value)],
+    }
+}
+
+impl<'a, 'arena> Iterator for Walk<'a, 'arena> {
+    type Item = (ValuePath, &'a ArcValue<'arena>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, value) = self.stack.pop()?;
+
+        // Children are pushed in reverse so that they are popped in order
+        match value.as_ref() {
+            Value::RecordLit(labels, exprs) => {
+                for (label, expr) in Iterator::zip(labels.iter(), exprs.iter()).rev() {
+                    self.stack.push((path.push(PathStep::Field(*label)), expr));
+                }
+            }
+            Value::ArrayLit(exprs) => {
+                for (index, expr) in exprs.iter().enumerate().rev() {
+                    self.stack.push((path.push(PathStep::Index(index)), expr));
+                }
+            }
+            _ => {}
+        }
+
+        Some((path, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::core::{Const, UIntStyle};
+    use crate::source::Spanned;
+
+    #[test]
+    fn walk_nested_record() {
+        let mut interner = StringInterner::new();
+        let [header, len, data] =
+            ["header", "len", "data"].map(|label| interner.get_or_intern(label));
+        let u8_value =
+            |n| Spanned::empty(Arc::new(Value::ConstLit(Const::U8(n, UIntStyle::Decimal))));
+
+        // { header = { len = 2 }, data = [3, 4] }
+        let header_labels = [len];
+        let labels = [header, data];
+        let value = Spanned::empty(Arc::new(Value::RecordLit(
+            &labels,
+            vec![
+                Spanned::empty(Arc::new(Value::RecordLit(
+                    &header_labels,
+                    vec![u8_value(2)],
+                ))),
+                Spanned::empty(Arc::new(Value::ArrayLit(vec![u8_value(3), u8_value(4)]))),
+            ],
+        )));
+
+        let nodes = walk(&value)
+            .map(|(path, value)| {
+                let n = match value.as_ref() {
+                    Value::ConstLit(Const::U8(n, _)) => Some(*n),
+                    _ => None,
+                };
+                (path.display(&interner), n)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            nodes,
+            [
+                (String::new(), None),
+                ("header".to_owned(), None),
+                ("header.len".to_owned(), Some(2)),
+                ("data".to_owned(), None),
+                ("data[0]".to_owned(), Some(3)),
+                ("data[1]".to_owned(), Some(4)),
+            ],
+        );
+    }
+}
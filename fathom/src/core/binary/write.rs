@@ -0,0 +1,428 @@
+//! Writing values back to binary data, using the binary semantics of formats.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::core::semantics::{self, ArcValue, Elim, Head, Value};
+use crate::core::{Const, Item, Module, Prim, Term};
+use crate::env::{EnvLen, SharedEnv, UniqueEnv};
+use crate::source::Span;
+
+#[derive(Debug)]
+pub enum WriteError<'arena> {
+    /// The format could not be written.
+    InvalidFormat(Span),
+    /// The value does not match the shape of the format.
+    InvalidValue(Span),
+    /// The value does not satisfy the condition of a conditional format.
+    CondFailure(Span, ArcValue<'arena>),
+    Io(io::Error),
+}
+
+impl<'arena> fmt::Display for WriteError<'arena> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::InvalidFormat(_) => f.write_str("invalid format"),
+            WriteError::InvalidValue(_) => f.write_str("invalid value"),
+            WriteError::CondFailure(_, _) => f.write_str("conditional format failed"),
+            WriteError::Io(err) => fmt::Display::fmt(&err, f),
+        }
+    }
+}
+
+impl<'arena> std::error::Error for WriteError<'arena> {}
+
+impl<'arena> From<io::Error> for WriteError<'arena> {
+    fn from(err: io::Error) -> WriteError<'arena> {
+        WriteError::Io(err)
+    }
+}
+
+/// Write the bytes of a numeric constant.
+macro_rules! write_const {
+    ($writer:expr, $expr:expr, $Const:ident, $to_bytes:ident) => {
+        match $expr.as_ref() {
+            Value::ConstLit(Const::$Const(num, ..)) => Ok($writer.write_all(&num.$to_bytes())?),
+            _ => Err(WriteError::InvalidValue($expr.span())),
+        }
+    };
+}
+
+pub struct Context<'arena> {
+    item_exprs: UniqueEnv<ArcValue<'arena>>,
+    local_exprs: SharedEnv<ArcValue<'arena>>,
+}
+
+impl<'arena> Context<'arena> {
+    pub fn new() -> Context<'arena> {
+        Context {
+            item_exprs: UniqueEnv::new(),
+            local_exprs: SharedEnv::new(),
+        }
+    }
+
+    fn eval_env(&mut self) -> semantics::EvalEnv<'arena, '_> {
+        let elim_env = semantics::ElimEnv::new(&self.item_exprs, [][..].into());
+        semantics::EvalEnv::new(elim_env, &mut self.local_exprs)
+    }
+
+    fn elim_env(&self) -> semantics::ElimEnv<'arena, '_> {
+        semantics::ElimEnv::new(&self.item_exprs, [][..].into())
+    }
+
+    pub fn add_module(&mut self, module: &Module<'arena>) {
+        self.item_exprs.reserve(module.items.len());
+        for item in module.items {
+            match item {
+                Item::Def { expr, .. } => {
+                    let expr = self.eval_env().eval(expr);
+                    self.item_exprs.push(expr);
+                }
+            }
+        }
+    }
+
+    /// Write an expression to the `writer`, with the binary representation
+    /// described by the `format`. Nothing is written if the expression does
+    /// not match the format.
+    pub fn write_entrypoint(
+        &mut self,
+        writer: &mut impl Write,
+        format: &Term<'arena>,
+        expr: &ArcValue<'arena>,
+    ) -> Result<(), WriteError<'arena>> {
+        let format = self.eval_env().eval(format);
+
+        // Later fields can fail to be written after earlier fields have been
+        // serialized, so buffer the output until the whole value is written.
+        let mut buffer = Vec::new();
+        self.write_format(&mut buffer, &format, expr)?;
+        Ok(writer.write_all(&buffer)?)
+    }
+
+    fn write_format(
+        &mut self,
+        writer: &mut impl Write,
+        format: &ArcValue<'arena>,
+        expr: &ArcValue<'arena>,
+    ) -> Result<(), WriteError<'arena>> {
+        match format.as_ref() {
+            Value::Stuck(Head::Prim(prim), slice) => {
+                self.write_prim(writer, *prim, slice, format.span(), expr)
+            }
+            Value::FormatRecord(labels, formats) => {
+                let exprs = match expr.as_ref() {
+                    Value::RecordLit(expr_labels, exprs) if expr_labels == labels => exprs,
+                    _ => return Err(WriteError::InvalidValue(expr.span())),
+                };
+
+                let mut formats = formats.clone();
+                for expr in exprs {
+                    let (format, next_formats) = (self.elim_env().split_telescope(formats))
+                        .ok_or_else(|| WriteError::InvalidFormat(format.span()))?;
+                    self.write_format(writer, &format, expr)?;
+                    formats = next_formats(expr.clone());
+                }
+
+                Ok(())
+            }
+            Value::FormatCond(_label, format, cond) => {
+                let cond_res = self.elim_env().apply_closure(cond, expr.clone());
+
+                match cond_res.as_ref() {
+                    Value::ConstLit(Const::Bool(true)) => self.write_format(writer, format, expr),
+                    Value::ConstLit(Const::Bool(false)) => {
+                        Err(WriteError::CondFailure(cond.span(), expr.clone()))
+                    }
+                    _ => Err(WriteError::InvalidValue(expr.span())),
+                }
+            }
+
+            // Overlapping fields share the same bytes, and would need to be
+            // checked for consistency before they can be written.
+            Value::FormatOverlap(..)
+            | Value::Stuck(Head::LocalVar(_), _)
+            | Value::Stuck(Head::MetaVar(_), _)
+            | Value::Universe
+            | Value::FunType(..)
+            | Value::FunLit(..)
+            | Value::RecordType(_, _)
+            | Value::RecordLit(_, _)
+            | Value::ArrayLit(_)
+            | Value::ConstLit(_) => Err(WriteError::InvalidFormat(format.span())),
+        }
+    }
+
+    #[rustfmt::skip]
+    fn write_prim(
+        &mut self,
+        writer: &mut impl Write,
+        prim: Prim,
+        slice: &[Elim<'arena>],
+        span: Span,
+        expr: &ArcValue<'arena>,
+    ) -> Result<(), WriteError<'arena>> {
+        use crate::core::semantics::Elim::FunApp;
+
+        match (prim, slice) {
+            (Prim::FormatU8, []) => write_const!(writer, expr, U8, to_be_bytes),
+            (Prim::FormatU16Be, []) => write_const!(writer, expr, U16, to_be_bytes),
+            (Prim::FormatU16Le, []) => write_const!(writer, expr, U16, to_le_bytes),
+            (Prim::FormatU32Be, []) => write_const!(writer, expr, U32, to_be_bytes),
+            (Prim::FormatU32Le, []) => write_const!(writer, expr, U32, to_le_bytes),
+            (Prim::FormatU64Be, []) => write_const!(writer, expr, U64, to_be_bytes),
+            (Prim::FormatU64Le, []) => write_const!(writer, expr, U64, to_le_bytes),
+            (Prim::FormatS8, []) => write_const!(writer, expr, S8, to_be_bytes),
+            (Prim::FormatS16Be, []) => write_const!(writer, expr, S16, to_be_bytes),
+            (Prim::FormatS16Le, []) => write_const!(writer, expr, S16, to_le_bytes),
+            (Prim::FormatS32Be, []) => write_const!(writer, expr, S32, to_be_bytes),
+            (Prim::FormatS32Le, []) => write_const!(writer, expr, S32, to_le_bytes),
+            (Prim::FormatS64Be, []) => write_const!(writer, expr, S64, to_be_bytes),
+            (Prim::FormatS64Le, []) => write_const!(writer, expr, S64, to_le_bytes),
+            (Prim::FormatF32Be, []) => write_const!(writer, expr, F32, to_be_bytes),
+            (Prim::FormatF32Le, []) => write_const!(writer, expr, F32, to_le_bytes),
+            (Prim::FormatF64Be, []) => write_const!(writer, expr, F64, to_be_bytes),
+            (Prim::FormatF64Le, []) => write_const!(writer, expr, F64, to_le_bytes),
+            (Prim::FormatRepeatLen8, [FunApp(_, len), FunApp(_, format)]) => self.write_repeat_len(writer, len, format, expr),
+            (Prim::FormatRepeatLen16, [FunApp(_, len), FunApp(_, format)]) => self.write_repeat_len(writer, len, format, expr),
+            (Prim::FormatRepeatLen32, [FunApp(_, len), FunApp(_, format)]) => self.write_repeat_len(writer, len, format, expr),
+            (Prim::FormatRepeatLen64, [FunApp(_, len), FunApp(_, format)]) => self.write_repeat_len(writer, len, format, expr),
+            (Prim::FormatMagic, [FunApp(_, bytes)]) => self.write_magic(writer, bytes),
+            (Prim::FormatPad, [FunApp(_, len)]) => self.write_pad(writer, len),
+            (Prim::FormatSucceed, [_, FunApp(_, elem)]) => self.write_succeed(elem, expr),
+            _ => Err(WriteError::InvalidFormat(span)),
+        }
+    }
+
+    /// Succeed formats compute their values, so nothing needs to be written,
+    /// but the value must be the one that would have been computed.
+    fn write_succeed(
+        &mut self,
+        elem: &ArcValue<'arena>,
+        expr: &ArcValue<'arena>,
+    ) -> Result<(), WriteError<'arena>> {
+        match self
+            .elim_env()
+            .conversion_env(EnvLen::new())
+            .is_equal(elem, expr)
+        {
+            true => Ok(()),
+            false => Err(WriteError::InvalidValue(expr.span())),
+        }
+    }
+
+    fn write_magic(
+        &mut self,
+        writer: &mut impl Write,
+        bytes: &ArcValue<'arena>,
+    ) -> Result<(), WriteError<'arena>> {
+        match bytes.as_ref() {
+            Value::ArrayLit(bytes) => {
+                for byte in bytes {
+                    write_const!(writer, byte, U8, to_be_bytes)?;
+                }
+                Ok(())
+            }
+            _ => Err(WriteError::InvalidValue(bytes.span())),
+        }
+    }
+
+    fn write_pad(
+        &mut self,
+        writer: &mut impl Write,
+        len: &ArcValue<'arena>,
+    ) -> Result<(), WriteError<'arena>> {
+        match len.as_ref() {
+            Value::ConstLit(Const::U64(len, _)) => {
+                io::copy(&mut io::repeat(0).take(*len), writer)?;
+                Ok(())
+            }
+            _ => Err(WriteError::InvalidValue(len.span())),
+        }
+    }
+
+    fn write_repeat_len(
+        &mut self,
+        writer: &mut impl Write,
+        len: &ArcValue<'arena>,
+        elem_format: &ArcValue<'arena>,
+        expr: &ArcValue<'arena>,
+    ) -> Result<(), WriteError<'arena>> {
+        let len = match len.as_ref() {
+            Value::ConstLit(Const::U8(len, _)) => u64::from(*len),
+            Value::ConstLit(Const::U16(len, _)) => u64::from(*len),
+            Value::ConstLit(Const::U32(len, _)) => u64::from(*len),
+            Value::ConstLit(Const::U64(len, _)) => *len,
+            _ => return Err(WriteError::InvalidValue(len.span())),
+        };
+
+        match expr.as_ref() {
+            Value::ArrayLit(elem_exprs) if elem_exprs.len() as u64 == len => (elem_exprs.iter())
+                .try_for_each(|elem| self.write_format(writer, elem_format, elem)),
+            _ => Err(WriteError::InvalidValue(expr.span())),
+        }
+    }
+}
+
+impl<'arena> Default for Context<'arena> {
+    fn default() -> Context<'arena> {
+        Context::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use scoped_arena::Scope;
+
+    use super::*;
+    use crate::core::binary::{self, Buffer};
+    use crate::core::{Plicity, UIntStyle};
+    use crate::env::{self, EnvLen};
+    use crate::source::{Spanned, StringInterner};
+
+    /// `{ len <- u8, data <- repeat_len8 len u16be, flag <- { x <- s8 | x != 0 } }`
+    fn record_format<'arena>(
+        scope: &'arena Scope<'arena>,
+        interner: &mut StringInterner,
+    ) -> Term<'arena> {
+        let labels = ["len", "data", "flag"].map(|label| interner.get_or_intern(label));
+        let prim = |prim| scope.to_scope(Term::Prim(Span::Empty, prim)) as &_;
+        let app = |head_expr, arg_expr| {
+            scope.to_scope(Term::FunApp(
+                Span::Empty,
+                Plicity::Explicit,
+                head_expr,
+                arg_expr,
+            )) as &_
+        };
+        let var = scope.to_scope(Term::LocalVar(Span::Empty, env::Index::last())) as &_;
+        let zero = scope.to_scope(Term::ConstLit(Span::Empty, Const::S8(0))) as &_;
+
+        Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope([
+                Term::Prim(Span::Empty, Prim::FormatU8),
+                app(
+                    app(prim(Prim::FormatRepeatLen8), var),
+                    prim(Prim::FormatU16Be),
+                )
+                .clone(),
+                Term::FormatCond(
+                    Span::Empty,
+                    interner.get_or_intern("x"),
+                    prim(Prim::FormatS8),
+                    app(app(prim(Prim::S8Neq), var), zero),
+                ),
+            ]),
+        )
+    }
+
+    fn read<'arena>(format: &Term<'arena>, data: &[u8]) -> ArcValue<'arena> {
+        let context = binary::Context::new(Buffer::from(data));
+        let mut refs = context.read_entrypoint(format).unwrap();
+        refs.remove(&0).unwrap().remove(0).expr
+    }
+
+    #[test]
+    fn round_trip() {
+        let scope = Scope::new();
+        let mut interner = StringInterner::new();
+        let format = record_format(&scope, &mut interner);
+
+        let data = [2, 0x12, 0x34, 0x56, 0x78, 0xff];
+        let expr = read(&format, &data);
+
+        let mut written = Vec::new();
+        let mut context = Context::new();
+        context
+            .write_entrypoint(&mut written, &format, &expr)
+            .unwrap();
+        assert_eq!(written, data);
+
+        let read_expr = read(&format, &written);
+        assert!(context
+            .elim_env()
+            .conversion_env(EnvLen::new())
+            .is_equal(&read_expr, &expr));
+    }
+
+    #[test]
+    fn mismatched_length() {
+        let scope = Scope::new();
+        let mut interner = StringInterner::new();
+        let format = record_format(&scope, &mut interner);
+
+        let expr = read(&format, &[2, 0x12, 0x34, 0x56, 0x78, 0xff]);
+        let expr = match expr.as_ref() {
+            Value::RecordLit(labels, exprs) => {
+                let mut exprs = exprs.clone();
+                exprs[0] =
+                    Spanned::empty(Arc::new(Value::ConstLit(Const::U8(3, UIntStyle::Decimal))));
+                Spanned::empty(Arc::new(Value::RecordLit(*labels, exprs)))
+            }
+            _ => unreachable!(),
+        };
+
+        let mut context = Context::new();
+        let result = context.write_entrypoint(&mut Vec::new(), &format, &expr);
+        assert!(matches!(result, Err(WriteError::InvalidValue(_))));
+    }
+
+    #[test]
+    fn cond_failure() {
+        let scope = Scope::new();
+        let mut interner = StringInterner::new();
+        let format = record_format(&scope, &mut interner);
+
+        let expr = read(&format, &[0, 0xff]);
+        let expr = match expr.as_ref() {
+            Value::RecordLit(labels, exprs) => {
+                let mut exprs = exprs.clone();
+                exprs[2] = Spanned::empty(Arc::new(Value::ConstLit(Const::S8(0))));
+                Spanned::empty(Arc::new(Value::RecordLit(*labels, exprs)))
+            }
+            _ => unreachable!(),
+        };
+
+        // The fields before the failing condition are not written
+        let mut written = Vec::new();
+        let mut context = Context::new();
+        let result = context.write_entrypoint(&mut written, &format, &expr);
+        assert!(matches!(result, Err(WriteError::CondFailure(..))));
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn succeed_value() {
+        let scope = Scope::new();
+        let u8_lit = |n| Term::ConstLit(Span::Empty, Const::U8(n, UIntStyle::Decimal));
+        let u8_value =
+            |n| Spanned::empty(Arc::new(Value::ConstLit(Const::U8(n, UIntStyle::Decimal))));
+
+        // succeed U8 3
+        let format = Term::FunApp(
+            Span::Empty,
+            Plicity::Explicit,
+            scope.to_scope(Term::FunApp(
+                Span::Empty,
+                Plicity::Explicit,
+                scope.to_scope(Term::Prim(Span::Empty, Prim::FormatSucceed)),
+                scope.to_scope(Term::Prim(Span::Empty, Prim::U8Type)),
+            )),
+            scope.to_scope(u8_lit(3)),
+        );
+
+        let mut written = Vec::new();
+        let mut context = Context::new();
+        context
+            .write_entrypoint(&mut written, &format, &u8_value(3))
+            .unwrap();
+        assert!(written.is_empty());
+
+        let result = context.write_entrypoint(&mut written, &format, &u8_value(4));
+        assert!(matches!(result, Err(WriteError::InvalidValue(_))));
+    }
+}
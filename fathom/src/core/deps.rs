@@ -0,0 +1,413 @@
+//! Dependency graphs of module items.
+//!
+//! These are built by scanning the terms of each item for
+//! [item variables][Term::ItemVar], and are useful for tooling that needs to
+//! understand how the items of a module relate to each other.
+
+use crate::core::{Item, Module, Prim, Term};
+use crate::env::{self, Level};
+use crate::source::{Span, StringId};
+
+/// A graph mapping each item of a module to the items that it references.
+/// Items are identified by their position in the module.
+pub struct Graph {
+    labels: Vec<StringId>,
+    dependencies: Vec<Vec<usize>>,
+}
+
+/// A cycle of item references, starting and ending with the same item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cycle {
+    pub items: Vec<usize>,
+}
+
+/// The places where an item is referenced as a format or as a type.
+#[derive(Debug, Clone, Default)]
+pub struct ItemUses {
+    /// References that are used as the format of a field.
+    pub format_spans: Vec<Span>,
+    /// References that are coerced to their representation type.
+    pub type_spans: Vec<Span>,
+}
+
+/// The state of an item during a topological sort.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Mark {
+    Unvisited,
+    Visiting,
+    Visited,
+}
+
+impl Graph {
+    /// The number of items in the graph.
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// The label of an item.
+    pub fn label(&self, item: usize) -> StringId {
+        self.labels[item]
+    }
+
+    /// The items referenced by an item, in ascending order.
+    pub fn dependencies(&self, item: usize) -> &[usize] {
+        &self.dependencies[item]
+    }
+
+    /// The items that need to be recomputed when the given items change: the
+    /// changed items themselves, along with every item that references them
+    /// directly or indirectly. Items are returned in ascending order.
+    pub fn affected_items(&self, changed: &[usize]) -> Vec<usize> {
+        let mut affected = vec![false; self.len()];
+        let mut stack = changed.to_vec();
+
+        while let Some(item) = stack.pop() {
+            if !std::mem::replace(&mut affected[item], true) {
+                let dependents = (0..self.len()).filter(|i| self.dependencies[*i].contains(&item));
+                stack.extend(dependents);
+            }
+        }
+
+        (0..self.len()).filter(|item| affected[*item]).collect()
+    }
+
+    /// Order the items so that each item comes after the items it references,
+    /// returning the first cycle found if no such order exists.
+    pub fn topological_order(&self) -> Result<Vec<usize>, Cycle> {
+        let mut marks = vec![Mark::Unvisited; self.len()];
+        let mut stack = Vec::new();
+        let mut order = Vec::with_capacity(self.len());
+
+        for item in 0..self.len() {
+            self.visit(item, &mut marks, &mut stack, &mut order)?;
+        }
+
+        Ok(order)
+    }
+
+    fn visit(
+        &self,
+        item: usize,
+        marks: &mut [Mark],
+        stack: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), Cycle> {
+        match marks[item] {
+            Mark::Visited => Ok(()),
+            Mark::Visiting => {
+                let start = stack.iter().position(|i| *i == item).unwrap();
+                let mut items = stack[start..].to_vec();
+                items.push(item);
+                Err(Cycle { items })
+            }
+            Mark::Unvisited => {
+                marks[item] = Mark::Visiting;
+                stack.push(item);
+                for dep in &self.dependencies[item] {
+                    self.visit(*dep, marks, stack, order)?;
+                }
+                stack.pop();
+                marks[item] = Mark::Visited;
+                order.push(item);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Build the dependency graph of a module.
+pub fn dependency_graph(module: &Module<'_>) -> Graph {
+    let levels = item_levels(module);
+    let mut labels = Vec::with_capacity(module.items.len());
+    let mut dependencies = Vec::with_capacity(module.items.len());
+
+    for item in module.items {
+        let mut item_levels = Vec::new();
+        match item {
+            Item::Def {
+                label,
+                r#type,
+                expr,
+            } => {
+                labels.push(*label);
+                term_deps(r#type, &mut item_levels);
+                term_deps(expr, &mut item_levels);
+            }
+        }
+
+        let mut item_deps: Vec<usize> = (item_levels.iter())
+            .filter_map(|level| levels.binary_search(level).ok())
+            .collect();
+        item_deps.sort_unstable();
+        item_deps.dedup();
+        dependencies.push(item_deps);
+    }
+
+    Graph {
+        labels,
+        dependencies,
+    }
+}
+
+/// Find the places where each item of a module is referenced as a format or as
+/// a type. Items referenced in both ways are often a sign that a format was
+/// used where its representation type was intended, or the other way around.
+pub fn item_uses(module: &Module<'_>) -> Vec<ItemUses> {
+    let levels = item_levels(module);
+    let mut uses = Vec::new();
+
+    for item in module.items {
+        match item {
+            Item::Def { r#type, expr, .. } => {
+                term_uses(r#type, &mut uses);
+                term_uses(expr, &mut uses);
+            }
+        }
+    }
+
+    let mut item_uses = vec![ItemUses::default(); module.items.len()];
+    for (r#use, level, span) in uses {
+        if let Ok(item) = levels.binary_search(&level) {
+            match r#use {
+                Use::Format => item_uses[item].format_spans.push(span),
+                Use::Type => item_uses[item].type_spans.push(span),
+            }
+        }
+    }
+
+    item_uses
+}
+
+/// The levels of the item variables that refer to each item. Item variables
+/// refer to the items in the order they appear in the module.
+fn item_levels(module: &Module<'_>) -> Vec<Level> {
+    env::levels().take(module.items.len()).collect()
+}
+
+fn term_deps(term: &Term<'_>, deps: &mut Vec<Level>) {
+    match term {
+        Term::ItemVar(_, var) => deps.push(*var),
+        _ => for_each_subterm(term, |term| term_deps(term, deps)),
+    }
+}
+
+enum Use {
+    Format,
+    Type,
+}
+
+fn term_uses(term: &Term<'_>, uses: &mut Vec<(Use, Level, Span)>) {
+    match term {
+        Term::FormatRecord(_, _, formats) | Term::FormatOverlap(_, _, formats) => {
+            for format in formats.iter() {
+                if let Term::ItemVar(span, var) = format {
+                    uses.push((Use::Format, *var, *span));
+                }
+            }
+        }
+        Term::FormatCond(_, _, Term::ItemVar(span, var), _) => {
+            uses.push((Use::Format, *var, *span));
+        }
+        Term::FunApp(_, _, Term::Prim(_, Prim::FormatRepr), Term::ItemVar(span, var)) => {
+            uses.push((Use::Type, *var, *span));
+        }
+        _ => {}
+    }
+
+    for_each_subterm(term, |term| term_uses(term, uses));
+}
+
+/// Call `f` on each of the immediate subterms of a term.
+fn for_each_subterm<'arena>(term: &Term<'arena>, mut f: impl FnMut(&Term<'arena>)) {
+    match term {
+        Term::ItemVar(_, _)
+        | Term::LocalVar(_, _)
+        | Term::MetaVar(_, _)
+        | Term::InsertedMeta(_, _, _)
+        | Term::Universe(_)
+        | Term::Prim(_, _)
+        | Term::ConstLit(_, _) => {}
+
+        Term::Ann(_, expr, r#type) => {
+            f(expr);
+            f(r#type);
+        }
+        Term::Let(_, _, def_type, def_expr, body_expr) => {
+            f(def_type);
+            f(def_expr);
+            f(body_expr);
+        }
+        Term::FunType(.., param_type, body_type) => {
+            f(param_type);
+            f(body_type);
+        }
+        Term::FunLit(.., body_expr) => f(body_expr),
+        Term::FunApp(.., head_expr, arg_expr) => {
+            f(head_expr);
+            f(arg_expr);
+        }
+        Term::RecordType(_, _, terms)
+        | Term::RecordLit(_, _, terms)
+        | Term::ArrayLit(_, terms)
+        | Term::FormatRecord(_, _, terms)
+        | Term::FormatOverlap(_, _, terms) => terms.iter().for_each(f),
+        Term::RecordProj(_, head_expr, _) => f(head_expr),
+        Term::FormatCond(_, _, format, pred) => {
+            f(format);
+            f(pred);
+        }
+        Term::ConstMatch(_, head_expr, branches, default_branch) => {
+            f(head_expr);
+            branches.iter().for_each(|(_, term)| f(term));
+            if let Some((_, term)) = default_branch {
+                f(term);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Plicity;
+    use crate::source::StringInterner;
+
+    fn item_var(level: usize) -> Term<'static> {
+        Term::ItemVar(Span::Empty, env::levels().nth(level).unwrap())
+    }
+
+    fn def<'arena>(label: StringId, expr: &'arena Term<'arena>) -> Item<'arena> {
+        Item::Def {
+            label,
+            r#type: &Term::Prim(Span::Empty, Prim::FormatType),
+            expr,
+        }
+    }
+
+    #[test]
+    fn linear_dependencies() {
+        let mut interner = StringInterner::new();
+        let (a, b, c) = (
+            interner.get_or_intern("a"),
+            interner.get_or_intern("b"),
+            interner.get_or_intern("c"),
+        );
+
+        let u8_format = Term::Prim(Span::Empty, Prim::FormatU8);
+        let (a_var, b_var) = (item_var(0), item_var(1));
+        let items = [def(a, &u8_format), def(b, &a_var), def(c, &b_var)];
+        let graph = dependency_graph(&Module { items: &items });
+
+        assert_eq!(graph.dependencies(0), &[] as &[usize]);
+        assert_eq!(graph.dependencies(1), &[0]);
+        assert_eq!(graph.dependencies(2), &[1]);
+        assert_eq!(graph.topological_order(), Ok(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn mixed_item_uses() {
+        let mut interner = StringInterner::new();
+        let (tag, header, table) = (
+            interner.get_or_intern("tag"),
+            interner.get_or_intern("header"),
+            interner.get_or_intern("table"),
+        );
+
+        // def tag = u32be;
+        // def header = { tag <- tag };
+        // def table : Repr tag -> Format = fun _ => header;
+        let u32_format = Term::Prim(Span::Empty, Prim::FormatU32Be);
+        let tag_var = item_var(0);
+        let header_labels = [tag];
+        let header_format =
+            Term::FormatRecord(Span::Empty, &header_labels, std::slice::from_ref(&tag_var));
+        let repr_tag = Term::FunApp(
+            Span::Empty,
+            Plicity::Explicit,
+            &Term::Prim(Span::Empty, Prim::FormatRepr),
+            &tag_var,
+        );
+        let table_type = Term::FunType(
+            Span::Empty,
+            Plicity::Explicit,
+            None,
+            &repr_tag,
+            &Term::Prim(Span::Empty, Prim::FormatType),
+        );
+        let header_var = item_var(1);
+        let table_expr = Term::FunLit(Span::Empty, Plicity::Explicit, None, &header_var);
+        let items = [
+            def(tag, &u32_format),
+            def(header, &header_format),
+            Item::Def {
+                label: table,
+                r#type: &table_type,
+                expr: &table_expr,
+            },
+        ];
+        let uses = item_uses(&Module { items: &items });
+
+        assert_eq!(uses[0].format_spans.len(), 1);
+        assert_eq!(uses[0].type_spans.len(), 1);
+        // `header` is only referenced as the body of a function
+        assert!(uses[1].format_spans.is_empty());
+        assert!(uses[1].type_spans.is_empty());
+        assert!(uses[2].format_spans.is_empty());
+    }
+
+    #[test]
+    fn affected_items() {
+        let mut interner = StringInterner::new();
+        let (a, b, c, d) = (
+            interner.get_or_intern("a"),
+            interner.get_or_intern("b"),
+            interner.get_or_intern("c"),
+            interner.get_or_intern("d"),
+        );
+
+        // def a = u8;
+        // def b = a;
+        // def c = u16be;
+        // def d = b;
+        let u8_format = Term::Prim(Span::Empty, Prim::FormatU8);
+        let u16_format = Term::Prim(Span::Empty, Prim::FormatU16Be);
+        let (a_var, b_var) = (item_var(0), item_var(1));
+        let items = [
+            def(a, &u8_format),
+            def(b, &a_var),
+            def(c, &u16_format),
+            def(d, &b_var),
+        ];
+        let graph = dependency_graph(&Module { items: &items });
+
+        assert_eq!(graph.affected_items(&[1]), [1, 3]);
+        assert_eq!(graph.affected_items(&[0]), [0, 1, 3]);
+        assert_eq!(graph.affected_items(&[2]), [2]);
+        assert_eq!(graph.affected_items(&[]), [] as [usize; 0]);
+    }
+
+    #[test]
+    fn cyclic_dependencies() {
+        let mut interner = StringInterner::new();
+        let (a, b, c) = (
+            interner.get_or_intern("a"),
+            interner.get_or_intern("b"),
+            interner.get_or_intern("c"),
+        );
+
+        let (a_var, b_var, c_var) = (item_var(0), item_var(1), item_var(2));
+        let items = [def(a, &c_var), def(b, &a_var), def(c, &b_var)];
+        let graph = dependency_graph(&Module { items: &items });
+
+        assert_eq!(
+            graph.topological_order(),
+            Err(Cycle {
+                items: vec![0, 2, 1, 0]
+            }),
+        );
+    }
+}
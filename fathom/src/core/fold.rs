@@ -0,0 +1,256 @@
+//! Transformations of core terms.
+//!
+//! Transformations are written by implementing [`Folder`], overriding the
+//! methods for the parts of the tree that should change. The default methods
+//! rebuild the tree unchanged, preserving the spans of the original terms, and
+//! can be called from overridden methods using the `walk_*` functions.
+
+use scoped_arena::Scope;
+
+use crate::core::{Item, Module, Term};
+use crate::source::Span;
+
+/// A transformation of core modules and terms, rebuilding them in the arena
+/// returned by [`Folder::scope`].
+pub trait Folder<'out> {
+    /// The arena that the transformed terms will be allocated in.
+    fn scope(&self) -> &'out Scope<'out>;
+
+    fn fold_module(&mut self, module: &Module<'_>) -> Module<'out> {
+        walk_module(self, module)
+    }
+
+    fn fold_item(&mut self, item: &Item<'_>) -> Item<'out> {
+        walk_item(self, item)
+    }
+
+    fn fold_term(&mut self, term: &Term<'_>) -> Term<'out> {
+        walk_term(self, term)
+    }
+
+    fn fold_span(&mut self, span: Span) -> Span {
+        span
+    }
+}
+
+/// Rebuild a module, folding each of its items.
+pub fn walk_module<'out, F: Folder<'out> + ?Sized>(
+    folder: &mut F,
+    module: &Module<'_>,
+) -> Module<'out> {
+    let scope = folder.scope();
+    let items = module.items.iter().map(|item| folder.fold_item(item));
+
+    Module {
+        items: scope.to_scope_from_iter(items),
+    }
+}
+
+/// Rebuild an item, folding each of its terms.
+pub fn walk_item<'out, F: Folder<'out> + ?Sized>(folder: &mut F, item: &Item<'_>) -> Item<'out> {
+    let scope = folder.scope();
+
+    match item {
+        Item::Def {
+            label,
+            r#type,
+            expr,
+        } => Item::Def {
+            label: *label,
+            r#type: scope.to_scope(folder.fold_term(r#type)),
+            expr: scope.to_scope(folder.fold_term(expr)),
+        },
+    }
+}
+
+/// Rebuild a term, folding each of its subterms.
+pub fn walk_term<'out, F: Folder<'out> + ?Sized>(folder: &mut F, term: &Term<'_>) -> Term<'out> {
+    let scope = folder.scope();
+
+    match term {
+        Term::ItemVar(span, var) => Term::ItemVar(folder.fold_span(*span), *var),
+        Term::LocalVar(span, var) => Term::LocalVar(folder.fold_span(*span), *var),
+        Term::MetaVar(span, var) => Term::MetaVar(folder.fold_span(*span), *var),
+        Term::InsertedMeta(span, var, local_infos) => Term::InsertedMeta(
+            folder.fold_span(*span),
+            *var,
+            scope.to_scope_from_iter(local_infos.iter().copied()),
+        ),
+        Term::Ann(span, expr, r#type) => Term::Ann(
+            folder.fold_span(*span),
+            scope.to_scope(folder.fold_term(expr)),
+            scope.to_scope(folder.fold_term(r#type)),
+        ),
+        Term::Let(span, def_name, def_type, def_expr, body_expr) => Term::Let(
+            folder.fold_span(*span),
+            *def_name,
+            scope.to_scope(folder.fold_term(def_type)),
+            scope.to_scope(folder.fold_term(def_expr)),
+            scope.to_scope(folder.fold_term(body_expr)),
+        ),
+        Term::Universe(span) => Term::Universe(folder.fold_span(*span)),
+        Term::FunType(span, plicity, param_name, param_type, body_type) => Term::FunType(
+            folder.fold_span(*span),
+            *plicity,
+            *param_name,
+            scope.to_scope(folder.fold_term(param_type)),
+            scope.to_scope(folder.fold_term(body_type)),
+        ),
+        Term::FunLit(span, plicity, param_name, body_expr) => Term::FunLit(
+            folder.fold_span(*span),
+            *plicity,
+            *param_name,
+            scope.to_scope(folder.fold_term(body_expr)),
+        ),
+        Term::FunApp(span, plicity, head_expr, arg_expr) => Term::FunApp(
+            folder.fold_span(*span),
+            *plicity,
+            scope.to_scope(folder.fold_term(head_expr)),
+            scope.to_scope(folder.fold_term(arg_expr)),
+        ),
+        Term::RecordType(span, labels, types) => Term::RecordType(
+            folder.fold_span(*span),
+            scope.to_scope_from_iter(labels.iter().copied()),
+            scope.to_scope_from_iter(types.iter().map(|r#type| folder.fold_term(r#type))),
+        ),
+        Term::RecordLit(span, labels, exprs) => Term::RecordLit(
+            folder.fold_span(*span),
+            scope.to_scope_from_iter(labels.iter().copied()),
+            scope.to_scope_from_iter(exprs.iter().map(|expr| folder.fold_term(expr))),
+        ),
+        Term::RecordProj(span, head_expr, label) => Term::RecordProj(
+            folder.fold_span(*span),
+            scope.to_scope(folder.fold_term(head_expr)),
+            *label,
+        ),
+        Term::ArrayLit(span, elem_exprs) => Term::ArrayLit(
+            folder.fold_span(*span),
+            scope.to_scope_from_iter(elem_exprs.iter().map(|expr| folder.fold_term(expr))),
+        ),
+        Term::FormatRecord(span, labels, formats) => Term::FormatRecord(
+            folder.fold_span(*span),
+            scope.to_scope_from_iter(labels.iter().copied()),
+            scope.to_scope_from_iter(formats.iter().map(|format| folder.fold_term(format))),
+        ),
+        Term::FormatCond(span, name, format, pred) => Term::FormatCond(
+            folder.fold_span(*span),
+            *name,
+            scope.to_scope(folder.fold_term(format)),
+            scope.to_scope(folder.fold_term(pred)),
+        ),
+        Term::FormatOverlap(span, labels, formats) => Term::FormatOverlap(
+            folder.fold_span(*span),
+            scope.to_scope_from_iter(labels.iter().copied()),
+            scope.to_scope_from_iter(formats.iter().map(|format| folder.fold_term(format))),
+        ),
+        Term::Prim(span, prim) => Term::Prim(folder.fold_span(*span), *prim),
+        Term::ConstLit(span, r#const) => Term::ConstLit(folder.fold_span(*span), *r#const),
+        Term::ConstMatch(span, head_expr, branches, default_branch) => {
+            let span = folder.fold_span(*span);
+            let head_expr = scope.to_scope(folder.fold_term(head_expr));
+            let branches =
+                (branches.iter()).map(|(r#const, expr)| (*r#const, folder.fold_term(expr)));
+            let branches = scope.to_scope_from_iter(branches);
+            let default_branch = default_branch
+                .map(|(name, expr)| (name, scope.to_scope(folder.fold_term(expr)) as &_));
+
+            Term::ConstMatch(span, head_expr, branches, default_branch)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Const, Plicity, Prim, UIntStyle};
+    use crate::env::Index;
+    use crate::source::StringInterner;
+
+    struct IdentityFolder<'out> {
+        scope: &'out Scope<'out>,
+    }
+
+    impl<'out> Folder<'out> for IdentityFolder<'out> {
+        fn scope(&self) -> &'out Scope<'out> {
+            self.scope
+        }
+    }
+
+    #[test]
+    fn identity_folder() {
+        let mut interner = StringInterner::new();
+        let (x, y) = (interner.get_or_intern("x"), interner.get_or_intern("y"));
+        let local_var = Term::LocalVar(Span::Empty, Index::last());
+
+        let scope = Scope::new();
+        let pred = Term::ConstMatch(
+            Span::Empty,
+            scope.to_scope(local_var.clone()),
+            scope.to_scope_from_iter([(
+                Const::U8(0, UIntStyle::Decimal),
+                Term::ConstLit(Span::Empty, Const::Bool(false)),
+            )]),
+            Some((
+                None,
+                scope.to_scope(Term::FunLit(
+                    Span::Empty,
+                    Plicity::Explicit,
+                    Some(y),
+                    scope.to_scope(local_var),
+                )) as &_,
+            )),
+        );
+        let format = Term::FormatCond(
+            Span::Empty,
+            x,
+            scope.to_scope(Term::Prim(Span::Empty, Prim::FormatU8)),
+            scope.to_scope(pred),
+        );
+        let items = scope.to_scope_from_iter([
+            Item::Def {
+                label: x,
+                r#type: scope.to_scope(Term::Prim(Span::Empty, Prim::U8Type)),
+                expr: scope.to_scope(Term::ConstLit(
+                    Span::Empty,
+                    Const::U8(3, UIntStyle::Decimal),
+                )),
+            },
+            Item::Def {
+                label: y,
+                r#type: scope.to_scope(Term::Prim(Span::Empty, Prim::FormatType)),
+                expr: scope.to_scope(Term::FormatRecord(
+                    Span::Empty,
+                    scope.to_scope_from_iter([x]),
+                    scope.to_scope_from_iter([format]),
+                )),
+            },
+        ]);
+        let module = Module { items };
+
+        let folded_scope = Scope::new();
+        let folded_module = IdentityFolder {
+            scope: &folded_scope,
+        }
+        .fold_module(&module);
+
+        assert_eq!(folded_module.items.len(), module.items.len());
+        for (folded_item, item) in Iterator::zip(folded_module.items.iter(), module.items.iter()) {
+            let (
+                Item::Def {
+                    label,
+                    r#type,
+                    expr,
+                },
+                Item::Def {
+                    label: folded_label,
+                    r#type: folded_type,
+                    expr: folded_expr,
+                },
+            ) = (item, folded_item);
+
+            assert_eq!(label, folded_label);
+            assert_eq!(format!("{:?}", r#type), format!("{:?}", folded_type));
+            assert_eq!(format!("{:?}", expr), format!("{:?}", folded_expr));
+        }
+    }
+}
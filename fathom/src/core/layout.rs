@@ -0,0 +1,279 @@
+//! Static layouts of formats.
+//!
+//! These are used to find the sizes of formats and the offsets of fields in
+//! record formats in cases where they do not depend on the data being read.
+
+use std::sync::Arc;
+
+use crate::core::semantics::{ArcValue, Elim, ElimEnv, Value};
+use crate::core::{Const, Prim};
+use crate::env::EnvLen;
+use crate::source::{Spanned, StringId};
+
+/// The layout of a field in a record format.
+#[derive(Debug, Clone)]
+pub struct FieldLayout<'arena> {
+    /// The byte offset of the field from the start of the record, if it does
+    /// not depend on the data being read.
+    pub offset: Option<u64>,
+    /// The host representation of the field.
+    pub host_type: ArcValue<'arena>,
+}
+
+/// Returns the value of a constant unsigned integer, if there is one.
+pub fn const_u64(value: &ArcValue<'_>) -> Option<u64> {
+    match value.as_ref() {
+        Value::ConstLit(Const::U8(n, _)) => Some(u64::from(*n)),
+        Value::ConstLit(Const::U16(n, _)) => Some(u64::from(*n)),
+        Value::ConstLit(Const::U32(n, _)) => Some(u64::from(*n)),
+        Value::ConstLit(Const::U64(n, _)) => Some(*n),
+        _ => None,
+    }
+}
+
+/// Returns the number of bytes that a format always reads, if this can be
+/// determined statically. `local_len` is the length of the environment that
+/// the format was evaluated in.
+pub fn format_size(env: ElimEnv<'_, '_>, local_len: EnvLen, format: &ArcValue<'_>) -> Option<u64> {
+    use Elim::FunApp;
+
+    match format.as_ref() {
+        Value::FormatRecord(_, formats) | Value::FormatOverlap(_, formats) => {
+            let is_overlap = matches!(format.as_ref(), Value::FormatOverlap(..));
+            let mut formats = formats.clone();
+            let mut local_len = local_len;
+            let mut size = 0;
+
+            while let Some((format, next_formats)) = env.split_telescope(formats) {
+                let format_size = format_size(env, local_len, &format)?;
+                size = match is_overlap {
+                    true => u64::max(size, format_size),
+                    false => u64::checked_add(size, format_size)?,
+                };
+                formats = next_formats(field_var(&mut local_len));
+            }
+
+            Some(size)
+        }
+        Value::FormatCond(_, format, _) => format_size(env, local_len, format),
+        _ => match format.match_prim_spine()? {
+            (Prim::FormatU8 | Prim::FormatS8, []) => Some(1),
+            (Prim::FormatU16Be | Prim::FormatU16Le | Prim::FormatS16Be | Prim::FormatS16Le, []) => {
+                Some(2)
+            }
+            (
+                Prim::FormatU32Be
+                | Prim::FormatU32Le
+                | Prim::FormatS32Be
+                | Prim::FormatS32Le
+                | Prim::FormatF32Be
+                | Prim::FormatF32Le,
+                [],
+            ) => Some(4),
+            (
+                Prim::FormatU64Be
+                | Prim::FormatU64Le
+                | Prim::FormatS64Be
+                | Prim::FormatS64Le
+                | Prim::FormatF64Be
+                | Prim::FormatF64Le,
+                [],
+            ) => Some(8),
+            (
+                Prim::FormatRepeatLen8
+                | Prim::FormatRepeatLen16
+                | Prim::FormatRepeatLen32
+                | Prim::FormatRepeatLen64,
+                [FunApp(_, len), FunApp(_, elem)],
+            ) => u64::checked_mul(const_u64(len)?, format_size(env, local_len, elem)?),
+            (Prim::FormatCrc32, [_, FunApp(_, elem)]) => format_size(env, local_len, elem),
+            (Prim::FormatPad, [FunApp(_, len)]) => const_u64(len),
+            (Prim::FormatMagic, [FunApp(_, bytes)]) => match bytes.as_ref() {
+                Value::ArrayLit(bytes) => u64::try_from(bytes.len()).ok(),
+                _ => None,
+            },
+            (Prim::FormatStreamPos, []) | (Prim::FormatSucceed, [_, _]) => Some(0),
+            _ => None,
+        },
+    }
+}
+
+/// Returns the layout of the field with the given label in a record format.
+///
+/// The host types of fields that depend on earlier fields refer to those
+/// fields as local variables, bound after the `local_len` variables in the
+/// environment that the format was evaluated in.
+pub fn field_layout<'arena>(
+    env: ElimEnv<'arena, '_>,
+    local_len: EnvLen,
+    format: &ArcValue<'arena>,
+    label: StringId,
+) -> Option<FieldLayout<'arena>> {
+    let (labels, mut formats) = match format.as_ref() {
+        Value::FormatRecord(labels, formats) => (*labels, formats.clone()),
+        _ => return None,
+    };
+    let mut local_len = local_len;
+    let mut offset = Some(0);
+
+    for field_label in labels {
+        let (format, next_formats) = env.split_telescope(formats)?;
+        if *field_label == label {
+            let host_type = env.format_repr(&format);
+            return Some(FieldLayout { offset, host_type });
+        }
+
+        let size = format_size(env, local_len, &format);
+        offset = Option::zip(offset, size).and_then(|(offset, size)| offset.checked_add(size));
+        formats = next_formats(field_var(&mut local_len));
+    }
+
+    None
+}
+
+/// Bind a variable that stands in for the value of a field that has not been
+/// read, so that formats depending on it remain stuck.
+fn field_var<'arena>(local_len: &mut EnvLen) -> ArcValue<'arena> {
+    let var = Spanned::empty(Arc::new(Value::local_var(local_len.next_level())));
+    local_len.push();
+    var
+}
+
+#[cfg(test)]
+mod tests {
+    use scoped_arena::Scope;
+
+    use super::*;
+    use crate::core::semantics::tests::{elim_env, eval};
+    use crate::core::{Plicity, Term, UIntStyle};
+    use crate::env::{self, UniqueEnv};
+    use crate::source::{Span, StringInterner};
+
+    #[test]
+    fn field_offsets() {
+        let mut interner = StringInterner::new();
+        let labels @ [magic, len, data, trailer] =
+            ["magic", "len", "data", "trailer"].map(|label| interner.get_or_intern(label));
+
+        // { magic <- u32be, len <- u8, data <- repeat_len8 len u8, trailer <- u16be }
+        let scope = Scope::new();
+        let data_format = Term::FunApp(
+            Span::Empty,
+            Plicity::Explicit,
+            scope.to_scope(Term::FunApp(
+                Span::Empty,
+                Plicity::Explicit,
+                scope.to_scope(Term::Prim(Span::Empty, Prim::FormatRepeatLen8)),
+                scope.to_scope(Term::LocalVar(Span::Empty, env::Index::last())),
+            )),
+            scope.to_scope(Term::Prim(Span::Empty, Prim::FormatU8)),
+        );
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope([
+                Term::Prim(Span::Empty, Prim::FormatU32Be),
+                Term::Prim(Span::Empty, Prim::FormatU8),
+                data_format,
+                Term::Prim(Span::Empty, Prim::FormatU16Be),
+            ]),
+        );
+
+        let env = elim_env([][..].into());
+        let format = eval(env, &format);
+
+        let layout = |label| field_layout(env, EnvLen::new(), &format, label).unwrap();
+
+        let magic_layout = layout(magic);
+        assert_eq!(magic_layout.offset, Some(0));
+        assert!(matches!(
+            magic_layout.host_type.match_prim_spine(),
+            Some((Prim::U32Type, [])),
+        ));
+
+        let len_layout = layout(len);
+        assert_eq!(len_layout.offset, Some(4));
+        assert!(matches!(
+            len_layout.host_type.match_prim_spine(),
+            Some((Prim::U8Type, [])),
+        ));
+
+        // The offset of a field after a variable length field is unknown
+        let trailer_layout = layout(trailer);
+        assert_eq!(trailer_layout.offset, None);
+        assert!(matches!(
+            trailer_layout.host_type.match_prim_spine(),
+            Some((Prim::U16Type, [])),
+        ));
+
+        assert_eq!(layout(data).offset, Some(5));
+        assert!(field_layout(env, EnvLen::new(), &format, interner.get_or_intern("x")).is_none());
+        assert_eq!(format_size(env, EnvLen::new(), &format), None);
+    }
+
+    #[test]
+    fn item_formats() {
+        let mut interner = StringInterner::new();
+        let labels @ [_, version] = ["id", "version"].map(|label| interner.get_or_intern(label));
+
+        // def uuid = repeat_len8 16 u8;
+        let scope = Scope::new();
+        let uuid_format = Term::FunApp(
+            Span::Empty,
+            Plicity::Explicit,
+            scope.to_scope(Term::FunApp(
+                Span::Empty,
+                Plicity::Explicit,
+                scope.to_scope(Term::Prim(Span::Empty, Prim::FormatRepeatLen8)),
+                scope.to_scope(Term::ConstLit(
+                    Span::Empty,
+                    Const::U8(16, UIntStyle::Decimal),
+                )),
+            )),
+            scope.to_scope(Term::Prim(Span::Empty, Prim::FormatU8)),
+        );
+        // { id <- uuid, version <- u16be }
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope([
+                Term::ItemVar(Span::Empty, env::levels().next().unwrap()),
+                Term::Prim(Span::Empty, Prim::FormatU16Be),
+            ]),
+        );
+
+        let mut item_exprs = UniqueEnv::new();
+        let uuid_format = eval(elim_env(&item_exprs), &uuid_format);
+        item_exprs.push(uuid_format);
+
+        // Item variables are unfolded to their definitions during evaluation
+        let env = elim_env(&item_exprs);
+        let format = eval(env, &format);
+
+        assert_eq!(format_size(env, EnvLen::new(), &format), Some(18));
+        let version_layout = field_layout(env, EnvLen::new(), &format, version).unwrap();
+        assert_eq!(version_layout.offset, Some(16));
+    }
+
+    #[test]
+    fn empty_formats() {
+        let mut interner = StringInterner::new();
+        let labels = [interner.get_or_intern("padding")];
+
+        // { padding <- () }
+        let scope = Scope::new();
+        let unit_format = Term::FormatRecord(Span::Empty, &[], &[]);
+        let format = Term::FormatRecord(
+            Span::Empty,
+            scope.to_scope(labels),
+            scope.to_scope([unit_format.clone()]),
+        );
+
+        let env = elim_env([][..].into());
+        let unit_format = eval(env, &unit_format);
+        let format = eval(env, &format);
+
+        assert_eq!(format_size(env, EnvLen::new(), &unit_format), Some(0));
+        assert_eq!(format_size(env, EnvLen::new(), &format), Some(0));
+    }
+}
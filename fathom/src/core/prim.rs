@@ -4,9 +4,9 @@ use std::sync::Arc;
 use fxhash::FxHashMap;
 use scoped_arena::Scope;
 
-use crate::core::semantics::{ArcValue, Elim, ElimEnv, Head, Value};
-use crate::core::{self, Const, Plicity, Prim, UIntStyle};
-use crate::env::{self, SharedEnv, UniqueEnv};
+use crate::core::semantics::{ArcValue, Elim, ElimEnv, Head, Telescope, Value};
+use crate::core::{self, layout, Const, Plicity, Prim, UIntStyle};
+use crate::env::{self, EnvLen, SharedEnv, UniqueEnv};
 use crate::source::{Span, Spanned, StringId, StringInterner};
 
 /// Environment of primitives
@@ -24,6 +24,17 @@ impl<'arena> Env<'arena> {
         Some((*prim, r#type))
     }
 
+    /// The names of the primitives in the context.
+    pub fn names(&self) -> impl Iterator<Item = StringId> + '_ {
+        self.entries.keys().copied()
+    }
+
+    /// Remove the primitives that do not satisfy the predicate, preventing
+    /// them from being referenced by name.
+    pub fn retain(&mut self, mut f: impl FnMut(Prim) -> bool) {
+        self.entries.retain(|_, (prim, _)| f(*prim));
+    }
+
     pub fn default(
         interner: &RefCell<StringInterner>,
         scope: &'arena Scope<'arena>,
@@ -54,6 +65,7 @@ impl<'arena> Env<'arena> {
         const ARRAY32_TYPE: Term<'_> = Term::Prim(Span::Empty, Array32Type);
         const ARRAY64_TYPE: Term<'_> = Term::Prim(Span::Empty, Array64Type);
         const POS_TYPE: Term<'_> = Term::Prim(Span::Empty, PosType);
+        const ENDIAN_TYPE: Term<'_> = Term::Prim(Span::Empty, EndianType);
 
         let mut env = EnvBuilder::new(interner, scope);
 
@@ -77,35 +89,69 @@ impl<'arena> Env<'arena> {
         env.define_prim_fun(Array64Type, [&U64_TYPE, &UNIVERSE], &UNIVERSE);
         env.define_prim(PosType, &UNIVERSE);
         env.define_prim_fun(RefType, [&FORMAT_TYPE], &UNIVERSE);
+        env.define_prim(EndianType, &UNIVERSE);
+        env.define_prim(EndianBig, &ENDIAN_TYPE);
+        env.define_prim(EndianLittle, &ENDIAN_TYPE);
         env.define_prim(FormatType, &UNIVERSE);
 
         env.define_prim(FormatU8, &FORMAT_TYPE);
         env.define_prim(FormatU16Be, &FORMAT_TYPE);
         env.define_prim(FormatU16Le, &FORMAT_TYPE);
+        env.define_prim_fun(FormatU16, [&ENDIAN_TYPE], &FORMAT_TYPE);
         env.define_prim(FormatU32Be, &FORMAT_TYPE);
         env.define_prim(FormatU32Le, &FORMAT_TYPE);
+        env.define_prim_fun(FormatU32, [&ENDIAN_TYPE], &FORMAT_TYPE);
         env.define_prim(FormatU64Be, &FORMAT_TYPE);
         env.define_prim(FormatU64Le, &FORMAT_TYPE);
+        env.define_prim_fun(FormatU64, [&ENDIAN_TYPE], &FORMAT_TYPE);
         env.define_prim(FormatS8, &FORMAT_TYPE);
         env.define_prim(FormatS16Be, &FORMAT_TYPE);
         env.define_prim(FormatS16Le, &FORMAT_TYPE);
+        env.define_prim_fun(FormatS16, [&ENDIAN_TYPE], &FORMAT_TYPE);
         env.define_prim(FormatS32Be, &FORMAT_TYPE);
         env.define_prim(FormatS32Le, &FORMAT_TYPE);
+        env.define_prim_fun(FormatS32, [&ENDIAN_TYPE], &FORMAT_TYPE);
         env.define_prim(FormatS64Be, &FORMAT_TYPE);
         env.define_prim(FormatS64Le, &FORMAT_TYPE);
+        env.define_prim_fun(FormatS64, [&ENDIAN_TYPE], &FORMAT_TYPE);
         env.define_prim(FormatF32Be, &FORMAT_TYPE);
         env.define_prim(FormatF32Le, &FORMAT_TYPE);
+        env.define_prim_fun(FormatF32, [&ENDIAN_TYPE], &FORMAT_TYPE);
         env.define_prim(FormatF64Be, &FORMAT_TYPE);
         env.define_prim(FormatF64Le, &FORMAT_TYPE);
+        env.define_prim_fun(FormatF64, [&ENDIAN_TYPE], &FORMAT_TYPE);
         env.define_prim_fun(FormatRepeatLen8, [&U8_TYPE, &FORMAT_TYPE], &FORMAT_TYPE);
         env.define_prim_fun(FormatRepeatLen16, [&U16_TYPE, &FORMAT_TYPE], &FORMAT_TYPE);
         env.define_prim_fun(FormatRepeatLen32, [&U32_TYPE, &FORMAT_TYPE], &FORMAT_TYPE);
         env.define_prim_fun(FormatRepeatLen64, [&U64_TYPE, &FORMAT_TYPE], &FORMAT_TYPE);
+        env.define_prim_fun(
+            FormatRepeatAligned,
+            [&U64_TYPE, &U64_TYPE, &FORMAT_TYPE],
+            &FORMAT_TYPE,
+        );
         env.define_prim_fun(FormatRepeatUntilEnd, [&FORMAT_TYPE], &FORMAT_TYPE);
         env.define_prim_fun(FormatLimit8, [&U8_TYPE, &FORMAT_TYPE], &FORMAT_TYPE);
         env.define_prim_fun(FormatLimit16, [&U16_TYPE, &FORMAT_TYPE], &FORMAT_TYPE);
         env.define_prim_fun(FormatLimit32, [&U32_TYPE, &FORMAT_TYPE], &FORMAT_TYPE);
         env.define_prim_fun(FormatLimit64, [&U64_TYPE, &FORMAT_TYPE], &FORMAT_TYPE);
+        env.define_prim_fun(
+            FormatDecompress,
+            [&U32_TYPE, &U64_TYPE, &FORMAT_TYPE],
+            &FORMAT_TYPE,
+        );
+        env.define_prim_fun(FormatCrc32, [&U32_TYPE, &FORMAT_TYPE], &FORMAT_TYPE);
+        env.define_prim_fun(
+            FormatMagic,
+            [&Term::FunApp(
+                Span::Empty,
+                Plicity::Explicit,
+                &Term::Prim(Span::Empty, ArrayType),
+                &U8_TYPE,
+            )],
+            &FORMAT_TYPE,
+        );
+        env.define_prim_fun(FormatAlign, [&U64_TYPE, &FORMAT_TYPE], &FORMAT_TYPE);
+        env.define_prim_fun(FormatPad, [&U64_TYPE], &FORMAT_TYPE);
         env.define_prim_fun(FormatLink, [&POS_TYPE, &FORMAT_TYPE], &FORMAT_TYPE);
         env.define_prim(
             FormatDeref,
@@ -163,7 +209,43 @@ impl<'arena> Env<'arena> {
                 ),
             ),
         );
+        env.define_prim(
+            FormatMap,
+            // fun (@A : Type) (f : Format) -> (Repr f   -> A  ) -> Format
+            // fun (@A : Type) (f : Format) -> (Repr f@0 -> A@2) -> Format
+            scope.to_scope(core::Term::FunType(
+                Span::Empty,
+                Plicity::Implicit,
+                env.name("A"),
+                &UNIVERSE,
+                scope.to_scope(core::Term::FunType(
+                    Span::Empty,
+                    Plicity::Explicit,
+                    env.name("f"),
+                    &FORMAT_TYPE,
+                    scope.to_scope(core::Term::FunType(
+                        Span::Empty,
+                        Plicity::Explicit,
+                        None,
+                        &Term::FunType(
+                            Span::Empty,
+                            Plicity::Explicit,
+                            None,
+                            &Term::FunApp(
+                                Span::Empty,
+                                Plicity::Explicit,
+                                &Term::Prim(Span::Empty, FormatRepr),
+                                &VAR0,
+                            ),
+                            &VAR2,
+                        ),
+                        &FORMAT_TYPE,
+                    )),
+                )),
+            )),
+        );
         env.define_prim_fun(FormatRepr, [&FORMAT_TYPE], &UNIVERSE);
+        env.define_prim_fun(FormatSize, [&FORMAT_TYPE], &U64_TYPE);
 
         // fun (@A : Type) -> Void -> A
         env.define_prim(
@@ -598,35 +680,61 @@ macro_rules! const_step {
     };
 }
 
+/// Select between a big-endian and a little-endian format, based on a byte
+/// order argument.
+macro_rules! endian_step {
+    ($BeFormat:ident, $LeFormat:ident) => {
+        step!(_, [endian] => match endian.match_prim_spine() {
+            Some((Prim::EndianBig, [])) => Spanned::empty(Arc::new(Value::prim(Prim::$BeFormat, []))),
+            Some((Prim::EndianLittle, [])) => Spanned::empty(Arc::new(Value::prim(Prim::$LeFormat, []))),
+            _ => return None,
+        })
+    };
+}
+
 #[rustfmt::skip]
 pub fn repr(prim: Prim) -> Step {
     match prim {
         Prim::FormatU8 => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::U8Type, [])))),
         Prim::FormatU16Be => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::U16Type, [])))),
         Prim::FormatU16Le => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::U16Type, [])))),
+        Prim::FormatU16 => step!(_, [_] => Spanned::empty(Arc::new(Value::prim(Prim::U16Type, [])))),
         Prim::FormatU32Be => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::U32Type, [])))),
         Prim::FormatU32Le => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::U32Type, [])))),
+        Prim::FormatU32 => step!(_, [_] => Spanned::empty(Arc::new(Value::prim(Prim::U32Type, [])))),
         Prim::FormatU64Be => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::U64Type, [])))),
         Prim::FormatU64Le => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::U64Type, [])))),
+        Prim::FormatU64 => step!(_, [_] => Spanned::empty(Arc::new(Value::prim(Prim::U64Type, [])))),
         Prim::FormatS8 => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::S8Type, [])))),
         Prim::FormatS16Be => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::S16Type, [])))),
         Prim::FormatS16Le => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::S16Type, [])))),
+        Prim::FormatS16 => step!(_, [_] => Spanned::empty(Arc::new(Value::prim(Prim::S16Type, [])))),
         Prim::FormatS32Be => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::S32Type, [])))),
         Prim::FormatS32Le => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::S32Type, [])))),
+        Prim::FormatS32 => step!(_, [_] => Spanned::empty(Arc::new(Value::prim(Prim::S32Type, [])))),
         Prim::FormatS64Be => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::S64Type, [])))),
         Prim::FormatS64Le => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::S64Type, [])))),
+        Prim::FormatS64 => step!(_, [_] => Spanned::empty(Arc::new(Value::prim(Prim::S64Type, [])))),
         Prim::FormatF32Be => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::F32Type, [])))),
         Prim::FormatF32Le => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::F32Type, [])))),
+        Prim::FormatF32 => step!(_, [_] => Spanned::empty(Arc::new(Value::prim(Prim::F32Type, [])))),
         Prim::FormatF64Be => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::F64Type, [])))),
         Prim::FormatF64Le => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::F64Type, [])))),
+        Prim::FormatF64 => step!(_, [_] => Spanned::empty(Arc::new(Value::prim(Prim::F64Type, [])))),
         Prim::FormatRepeatLen8 => step!(env, [len, elem] => Spanned::empty(Arc::new(Value::prim(Prim::Array8Type, [len.clone(), env.format_repr(elem)])))),
         Prim::FormatRepeatLen16 => step!(env, [len, elem] => Spanned::empty(Arc::new(Value::prim(Prim::Array16Type, [len.clone(), env.format_repr(elem)])))),
         Prim::FormatRepeatLen32 => step!(env, [len, elem] => Spanned::empty(Arc::new(Value::prim(Prim::Array32Type, [len.clone(), env.format_repr(elem)])))),
         Prim::FormatRepeatLen64 => step!(env, [len, elem] => Spanned::empty(Arc::new(Value::prim(Prim::Array64Type, [len.clone(), env.format_repr(elem)])))),
+        Prim::FormatRepeatAligned => step!(env, [len, _, elem] => Spanned::empty(Arc::new(Value::prim(Prim::Array64Type, [len.clone(), env.format_repr(elem)])))),
         Prim::FormatLimit8 => step!(env, [_, elem] => env.format_repr(elem)),
         Prim::FormatLimit16 => step!(env, [_, elem] => env.format_repr(elem)),
         Prim::FormatLimit32 => step!(env, [_, elem] => env.format_repr(elem)),
         Prim::FormatLimit64 => step!(env, [_, elem] => env.format_repr(elem)),
+        Prim::FormatDecompress => step!(env, [_, _, elem] => env.format_repr(elem)),
+        Prim::FormatCrc32 => step!(env, [_, elem] => env.format_repr(elem)),
+        Prim::FormatMagic => step!(_, [_] => Spanned::empty(Arc::new(Value::RecordType(&[], Telescope::new(SharedEnv::new(), &[]))))),
+        Prim::FormatAlign => step!(env, [_, elem] => env.format_repr(elem)),
+        Prim::FormatPad => step!(_, [_] => Spanned::empty(Arc::new(Value::RecordType(&[], Telescope::new(SharedEnv::new(), &[]))))),
         Prim::FormatRepeatUntilEnd => step!(env, [elem] => Spanned::empty(Arc::new(Value::prim(Prim::ArrayType, [env.format_repr(elem)])))),
         Prim::FormatLink => step!(_, [_, elem] => Spanned::empty(Arc::new(Value::prim(Prim::RefType, [elem.clone()])))),
         Prim::FormatDeref => step!(env, [elem, _] => env.format_repr(elem)),
@@ -634,6 +742,7 @@ pub fn repr(prim: Prim) -> Step {
         Prim::FormatSucceed => step!(_, [elem, _] => elem.clone()),
         Prim::FormatFail => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::VoidType, [])))),
         Prim::FormatUnwrap => step!(_, [elem, _] => elem.clone()),
+        Prim::FormatMap => step!(_, [elem, _, _] => elem.clone()),
         Prim::ReportedError => step!(_, [] => Spanned::empty(Arc::new(Value::prim(Prim::ReportedError, [])))),
         _ => |_, _| None,
     }
@@ -650,6 +759,19 @@ pub fn step(prim: Prim) -> Step {
         Prim::Absurd => step!(_, [_, _] => panic!("Constructed an element of `Void`")),
 
         Prim::FormatRepr => step!(env, [format] => env.format_repr(format)),
+        Prim::FormatSize => step!(env, [format] => {
+            let size = layout::format_size(*env, EnvLen::new(), format)?;
+            Spanned::empty(Arc::new(Value::ConstLit(Const::U64(size, UIntStyle::Decimal))))
+        }),
+
+        Prim::FormatU16 => endian_step!(FormatU16Be, FormatU16Le),
+        Prim::FormatU32 => endian_step!(FormatU32Be, FormatU32Le),
+        Prim::FormatU64 => endian_step!(FormatU64Be, FormatU64Le),
+        Prim::FormatS16 => endian_step!(FormatS16Be, FormatS16Le),
+        Prim::FormatS32 => endian_step!(FormatS32Be, FormatS32Le),
+        Prim::FormatS64 => endian_step!(FormatS64Be, FormatS64Le),
+        Prim::FormatF32 => endian_step!(FormatF32Be, FormatF32Le),
+        Prim::FormatF64 => endian_step!(FormatF64Be, FormatF64Le),
 
         Prim::BoolEq => const_step!([x: Bool, y: Bool] => Const::Bool(x == y)),
         Prim::BoolNeq => const_step!([x: Bool, y: Bool] => Const::Bool(x != y)),
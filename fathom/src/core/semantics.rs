@@ -1,6 +1,7 @@
 //! The semantics of the core language, implemented using [normalization by
 //! evaluation](https://en.wikipedia.org/wiki/Normalization_by_evaluation).
 
+use std::cell::Cell;
 use std::panic::panic_any;
 use std::sync::Arc;
 
@@ -239,6 +240,60 @@ impl Error {
     }
 }
 
+/// A limit on the number of reduction steps that can be performed during
+/// evaluation, preventing pathological terms from reducing forever.
+///
+/// Once the fuel has run out, any further reductions are replaced with
+/// [reported errors][Prim::ReportedError].
+#[derive(Debug)]
+pub struct Fuel {
+    remaining: Cell<u64>,
+    exhausted: Cell<bool>,
+}
+
+impl Fuel {
+    /// The default number of reduction steps.
+    pub const DEFAULT_STEPS: u64 = 10_000_000;
+
+    pub fn new(steps: u64) -> Fuel {
+        Fuel {
+            remaining: Cell::new(steps),
+            exhausted: Cell::new(false),
+        }
+    }
+
+    /// Returns `true` if a reduction was prevented by a lack of fuel.
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted.get()
+    }
+
+    /// Refill the fuel to the given number of reduction steps.
+    pub fn refill(&self, steps: u64) {
+        self.remaining.set(steps);
+        self.exhausted.set(false);
+    }
+
+    /// Consume a reduction step, returning `false` if no fuel remains.
+    fn consume(&self) -> bool {
+        match self.remaining.get() {
+            0 => {
+                self.exhausted.set(true);
+                false
+            }
+            remaining => {
+                self.remaining.set(remaining - 1);
+                true
+            }
+        }
+    }
+}
+
+impl Default for Fuel {
+    fn default() -> Fuel {
+        Fuel::new(Fuel::DEFAULT_STEPS)
+    }
+}
+
 /// Evaluation environment.
 ///
 /// Like the [`ElimEnv`], this allows for the running of computations, but
@@ -403,6 +458,7 @@ impl<'arena, 'env> EvalEnv<'arena, 'env> {
 pub struct ElimEnv<'arena, 'env> {
     item_exprs: &'env SliceEnv<ArcValue<'arena>>,
     meta_exprs: &'env SliceEnv<Option<ArcValue<'arena>>>,
+    fuel: Option<&'env Fuel>,
 }
 
 impl<'arena, 'env> ElimEnv<'arena, 'env> {
@@ -413,9 +469,23 @@ impl<'arena, 'env> ElimEnv<'arena, 'env> {
         ElimEnv {
             item_exprs,
             meta_exprs,
+            fuel: None,
         }
     }
 
+    /// Limit the number of reduction steps performed in this environment.
+    pub fn with_fuel(self, fuel: &'env Fuel) -> ElimEnv<'arena, 'env> {
+        ElimEnv {
+            fuel: Some(fuel),
+            ..self
+        }
+    }
+
+    /// Consume a reduction step, returning `false` if the fuel has run out.
+    fn consume_fuel(&self) -> bool {
+        self.fuel.map_or(true, Fuel::consume)
+    }
+
     pub fn eval_env(
         &self,
         local_exprs: &'env mut SharedEnv<ArcValue<'arena>>,
@@ -519,10 +589,14 @@ impl<'arena, 'env> ElimEnv<'arena, 'env> {
         mut head_expr: ArcValue<'arena>,
         arg_expr: ArcValue<'arena>,
     ) -> ArcValue<'arena> {
+        let span = head_expr.span();
         match Arc::make_mut(&mut head_expr) {
             // Beta-reduction
             Value::FunLit(fun_plicity, _, body_expr) => {
                 assert_eq!(arg_plicity, *fun_plicity, "Plicities must be equal");
+                if !self.consume_fuel() {
+                    return exhausted_value(span);
+                }
                 // FIXME: use span from head/arg exprs?
                 self.apply_closure(body_expr, arg_expr)
             }
@@ -571,7 +645,9 @@ impl<'arena, 'env> ElimEnv<'arena, 'env> {
         mut head_expr: ArcValue<'arena>,
         mut branches: Branches<'arena, Const>,
     ) -> ArcValue<'arena> {
+        let span = head_expr.span();
         match Arc::make_mut(&mut head_expr) {
+            Value::ConstLit(_) if !self.consume_fuel() => exhausted_value(span),
             Value::ConstLit(r#const) => {
                 // Try each branch
                 for (branch_const, body_expr) in branches.pattern_branches {
@@ -626,6 +702,11 @@ impl<'arena, 'env> ElimEnv<'arena, 'env> {
     }
 }
 
+/// The value that reductions are replaced with once the fuel has run out.
+fn exhausted_value<'arena>(span: Span) -> ArcValue<'arena> {
+    Spanned::new(span, Arc::new(Value::prim(Prim::ReportedError, [])))
+}
+
 /// Quotation environment.
 ///
 /// This environment keeps track of the length of the local environment,
@@ -1303,7 +1384,7 @@ impl<'arena, 'env> ConversionEnv<'arena, 'env> {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
     #[allow(dead_code)]
@@ -1348,6 +1429,171 @@ mod tests {
         }
     }
 
+    /// An elimination environment with the given items, and no metavariables.
+    pub(crate) fn elim_env<'arena, 'env>(
+        item_exprs: &'env SliceEnv<ArcValue<'arena>>,
+    ) -> ElimEnv<'arena, 'env> {
+        ElimEnv::new(item_exprs, [][..].into())
+    }
+
+    /// Evaluate a closed term.
+    pub(crate) fn eval<'arena>(env: ElimEnv<'arena, '_>, term: &Term<'arena>) -> ArcValue<'arena> {
+        env.eval_env(&mut SharedEnv::new()).eval(term)
+    }
+
+    #[test]
+    fn fuel_limits_reductions() {
+        use crate::core::UIntStyle;
+
+        // (fun x => x) ((fun x => x) (... ((fun x => x) 1)))
+        let scope = Scope::new();
+        let id_expr = Term::FunLit(
+            Span::Empty,
+            Plicity::Explicit,
+            None,
+            scope.to_scope(Term::LocalVar(Span::Empty, Index::last())),
+        );
+        let id_expr = &*scope.to_scope(id_expr);
+        let one = Term::ConstLit(Span::Empty, Const::U8(1, UIntStyle::Decimal));
+        let term = (0..16).fold(one, |arg_expr, _| {
+            Term::FunApp(
+                Span::Empty,
+                Plicity::Explicit,
+                id_expr,
+                scope.to_scope(arg_expr),
+            )
+        });
+
+        let eval_with_fuel = |fuel: &Fuel| eval(elim_env([][..].into()).with_fuel(fuel), &term);
+
+        let fuel = Fuel::new(16);
+        let value = eval_with_fuel(&fuel);
+        assert!(!fuel.is_exhausted());
+        assert!(matches!(value.as_ref(), Value::ConstLit(Const::U8(1, _))));
+
+        let fuel = Fuel::new(15);
+        let value = eval_with_fuel(&fuel);
+        assert!(fuel.is_exhausted());
+        assert!(matches!(
+            value.match_prim_spine(),
+            Some((Prim::ReportedError, [])),
+        ));
+    }
+
+    #[test]
+    fn normalize_fun_apps() {
+        use crate::core::UIntStyle;
+
+        let scope = Scope::new();
+        let fun_app = |head_expr, arg_expr| {
+            Term::FunApp(
+                Span::Empty,
+                Plicity::Explicit,
+                scope.to_scope(head_expr),
+                scope.to_scope(arg_expr),
+            )
+        };
+        let u8_lit = |n| Term::ConstLit(Span::Empty, Const::U8(n, UIntStyle::Decimal));
+        let prim = |prim| Term::Prim(Span::Empty, prim);
+
+        // u8_add 1 2
+        let add_term = fun_app(fun_app(prim(Prim::U8Add), u8_lit(1)), u8_lit(2));
+        // repeat_len8 (u8_add 1 2) u8
+        let repeat_term = fun_app(
+            fun_app(prim(Prim::FormatRepeatLen8), add_term.clone()),
+            prim(Prim::FormatU8),
+        );
+
+        let mut local_exprs = SharedEnv::new();
+        let mut eval_env = elim_env([][..].into()).eval_env(&mut local_exprs);
+
+        // Primitive operations on literals are computed
+        assert!(matches!(
+            eval_env.normalize(&scope, &add_term),
+            Term::ConstLit(_, Const::U8(3, _)),
+        ));
+        // Stuck applications are quoted back with normalized arguments
+        assert!(matches!(
+            eval_env.normalize(&scope, &repeat_term),
+            Term::FunApp(
+                _,
+                Plicity::Explicit,
+                Term::FunApp(
+                    _,
+                    Plicity::Explicit,
+                    Term::Prim(_, Prim::FormatRepeatLen8),
+                    Term::ConstLit(_, Const::U8(3, _)),
+                ),
+                Term::Prim(_, Prim::FormatU8),
+            ),
+        ));
+    }
+
+    #[test]
+    fn fun_types_equal_up_to_renaming() {
+        use crate::source::StringInterner;
+
+        let mut interner = StringInterner::new();
+        let (a, b) = (interner.get_or_intern("a"), interner.get_or_intern("b"));
+
+        let scope = Scope::new();
+        let universe = &*scope.to_scope(Term::Universe(Span::Empty));
+        let fun_type = |name, body_type| {
+            Term::FunType(
+                Span::Empty,
+                Plicity::Explicit,
+                Some(name),
+                universe,
+                scope.to_scope(body_type),
+            )
+        };
+        // fun (a : Type) -> a
+        let a_type = fun_type(a, Term::LocalVar(Span::Empty, Index::last()));
+        // fun (b : Type) -> b
+        let b_type = fun_type(b, Term::LocalVar(Span::Empty, Index::last()));
+        // fun (a : Type) -> U8
+        let u8_type = fun_type(a, Term::Prim(Span::Empty, Prim::U8Type));
+
+        let env = elim_env([][..].into());
+        let [a_type, b_type, u8_type] = [a_type, b_type, u8_type].map(|term| eval(env, &term));
+
+        let mut conversion_env = env.conversion_env(EnvLen::new());
+        assert!(conversion_env.is_equal(&a_type, &b_type));
+        assert!(!conversion_env.is_equal(&a_type, &u8_type));
+        assert!(!conversion_env.is_equal(&b_type, &u8_type));
+    }
+
+    #[test]
+    fn item_vars_share_values() {
+        use crate::env::{levels, UniqueEnv};
+
+        // [alias, alias, ..., alias]
+        let scope = Scope::new();
+        let alias_level = levels().next().unwrap();
+        let alias_var = Term::ItemVar(Span::Empty, alias_level);
+        let term = Term::ArrayLit(
+            Span::Empty,
+            scope.to_scope_from_iter(std::iter::repeat(alias_var).take(100)),
+        );
+
+        let mut item_exprs = UniqueEnv::new();
+        item_exprs.push(Spanned::empty(Arc::new(Value::prim(Prim::FormatU32Be, []))));
+
+        // Items are evaluated once, and then shared by every reference to them
+        let value = eval(elim_env(&item_exprs), &term);
+        let exprs = match value.as_ref() {
+            Value::ArrayLit(exprs) => exprs,
+            _ => panic!("expected an array literal"),
+        };
+        assert_eq!(exprs.len(), 100);
+        for expr in exprs {
+            assert!(Arc::ptr_eq(
+                expr,
+                item_exprs.get_level(alias_level).unwrap()
+            ));
+        }
+    }
+
     #[test]
     #[cfg(target_pointer_width = "64")]
     fn value_size() {
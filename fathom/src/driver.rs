@@ -1,14 +1,19 @@
 use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
 use std::io::Read;
+use std::ops::Range;
 use std::path::Path;
+use std::rc::Rc;
 
-use codespan_reporting::diagnostic::{Diagnostic, Label, Severity};
+use codespan_reporting::diagnostic::{Diagnostic, Label, LabelStyle, Severity};
 use codespan_reporting::files::SimpleFiles;
 use codespan_reporting::term::termcolor::{BufferedStandardStream, ColorChoice, WriteColor};
 
 use crate::core::binary::{self, BufferError, ReadError};
 use crate::files::{FileId, Files};
-use crate::source::{ByteRange, ProgramSource, SourceTooBig, Span, StringInterner, MAX_SOURCE_LEN};
+use crate::source::{
+    ByteRange, ProgramSource, SourceTooBig, Span, StringId, StringInterner, MAX_SOURCE_LEN,
+};
 use crate::surface::elaboration::ItemEnv;
 use crate::surface::{self, elaboration};
 use crate::{core, BUG_REPORT_URL};
@@ -28,6 +33,23 @@ impl Status {
     }
 }
 
+/// Binary data that has either been read into memory or mapped from a file.
+pub enum Bytes {
+    Read(Vec<u8>),
+    Mapped(memmap2::Mmap),
+}
+
+impl std::ops::Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Read(bytes) => bytes,
+            Bytes::Mapped(mmap) => mmap,
+        }
+    }
+}
+
 pub struct Driver<'surface, 'core> {
     files: Files<String, ProgramSource>,
     interner: RefCell<StringInterner>,
@@ -36,11 +58,18 @@ pub struct Driver<'surface, 'core> {
 
     allow_errors: bool,
     seen_errors: RefCell<bool>,
+    dedup_diagnostics: bool,
+    report_mixed_item_uses: bool,
+    report_unused_items: bool,
+    seen_diagnostics: RefCell<HashSet<DiagnosticKey>>,
     codespan_config: codespan_reporting::term::Config,
     diagnostic_writer: RefCell<Box<dyn WriteColor>>,
 
     emit_width: usize,
+    emit_annotated: bool,
     emit_writer: RefCell<Box<dyn WriteColor>>,
+
+    decompressors: Vec<(u32, Rc<dyn Fn(&[u8]) -> Option<Vec<u8>>>)>,
 }
 
 impl<'surface, 'core> Driver<'surface, 'core> {
@@ -53,6 +82,10 @@ impl<'surface, 'core> Driver<'surface, 'core> {
 
             allow_errors: false,
             seen_errors: RefCell::new(false),
+            dedup_diagnostics: false,
+            report_mixed_item_uses: false,
+            report_unused_items: false,
+            seen_diagnostics: RefCell::new(HashSet::new()),
             codespan_config: codespan_reporting::term::Config::default(),
             diagnostic_writer: RefCell::new(Box::new(BufferedStandardStream::stderr(
                 if atty::is(atty::Stream::Stderr) {
@@ -63,6 +96,7 @@ impl<'surface, 'core> Driver<'surface, 'core> {
             ))),
 
             emit_width: usize::MAX,
+            emit_annotated: false,
             emit_writer: RefCell::new(Box::new(BufferedStandardStream::stdout(
                 if atty::is(atty::Stream::Stdout) {
                     ColorChoice::Auto
@@ -70,6 +104,8 @@ impl<'surface, 'core> Driver<'surface, 'core> {
                     ColorChoice::Never
                 },
             ))),
+
+            decompressors: Vec::new(),
         }
     }
 
@@ -121,11 +157,42 @@ impl<'surface, 'core> Driver<'surface, 'core> {
         }));
     }
 
+    /// Register a decompressor for uses of `decompress` with the given
+    /// algorithm when reading binary data. This replaces any decompressor that
+    /// was previously registered for the algorithm.
+    pub fn add_decompressor(
+        &mut self,
+        algorithm: u32,
+        decompressor: impl Fn(&[u8]) -> Option<Vec<u8>> + 'static,
+    ) {
+        self.decompressors.retain(|(other, _)| *other != algorithm);
+        self.decompressors.push((algorithm, Rc::new(decompressor)));
+    }
+
     /// Set to true if we should attempt to continue after encountering errors
     pub fn set_allow_errors(&mut self, allow_errors: bool) {
         self.allow_errors = allow_errors;
     }
 
+    /// Set to true if identical diagnostics should only be reported once.
+    /// Diagnostics are considered identical if they have the same code,
+    /// message, and primary label.
+    pub fn set_dedup_diagnostics(&mut self, dedup_diagnostics: bool) {
+        self.dedup_diagnostics = dedup_diagnostics;
+    }
+
+    /// Set to true if notes should be reported for items that are used both
+    /// as formats and as types when elaborating modules.
+    pub fn set_report_mixed_item_uses(&mut self, report_mixed_item_uses: bool) {
+        self.report_mixed_item_uses = report_mixed_item_uses;
+    }
+
+    /// Set to true if warnings should be reported for items that are never
+    /// referenced when elaborating modules.
+    pub fn set_report_unused_items(&mut self, report_unused_items: bool) {
+        self.report_unused_items = report_unused_items;
+    }
+
     /// Set the writer to use when rendering diagnostics
     pub fn set_diagnostic_writer(&mut self, stream: impl 'static + WriteColor) {
         self.diagnostic_writer = RefCell::new(Box::new(stream) as Box<dyn WriteColor>);
@@ -136,6 +203,12 @@ impl<'surface, 'core> Driver<'surface, 'core> {
         self.emit_width = emit_width;
     }
 
+    /// Set to true if the offsets and raw bytes of each field should be
+    /// emitted alongside the data that was read
+    pub fn set_emit_annotated(&mut self, emit_annotated: bool) {
+        self.emit_annotated = emit_annotated;
+    }
+
     /// Set the writer to use when emitting data and intermediate languages
     pub fn set_emit_writer(&mut self, stream: impl 'static + WriteColor) {
         self.emit_writer = RefCell::new(Box::new(stream) as Box<dyn WriteColor>);
@@ -203,6 +276,124 @@ impl<'surface, 'core> Driver<'surface, 'core> {
         }
     }
 
+    /// Map the bytes of a given file into memory, falling back to reading all
+    /// the bytes in the file if it could not be mapped.
+    pub fn map_bytes_path(&mut self, path: &Path) -> Option<Bytes> {
+        match std::fs::File::open(path) {
+            // SAFETY: The file could be modified by another process while it
+            //         is mapped. This would break the assumption that the
+            //         underlying slice is immutable, but we accept this risk
+            //         given the file is only ever read from.
+            Ok(file) => match unsafe { memmap2::Mmap::map(&file) } {
+                Ok(mmap) => Some(Bytes::Mapped(mmap)),
+                Err(_) => self
+                    .read_bytes(path.display().to_string(), file)
+                    .map(Bytes::Read),
+            },
+            Err(error) => {
+                self.emit_read_diagnostic(path.display(), error);
+                None
+            }
+        }
+    }
+
+    /// Parse and elaborate a module, returning the diagnostics that prevented
+    /// it from being loaded instead of emitting them. The module is not
+    /// elaborated if it could not be parsed. If the module was loaded, any
+    /// warnings that were reported are returned alongside it.
+    pub fn load_module(
+        &self,
+        file_id: FileId,
+    ) -> Result<(core::Module<'_>, Diagnostics), LoadError> {
+        let (_, module, diagnostics) = self.load_surface_and_core_module(file_id)?;
+        Ok((module, diagnostics))
+    }
+
+    /// Load a module, returning the documentation comments of the items that
+    /// were elaborated, keyed by the names of the items. The fields of items
+    /// that are defined as record formats are documented in
+    /// [`surface::docs::ItemDoc::fields`].
+    pub fn module_docs(
+        &self,
+        file_id: FileId,
+    ) -> Result<BTreeMap<String, surface::docs::ItemDoc>, LoadError> {
+        let (surface_module, module, _) = self.load_surface_and_core_module(file_id)?;
+        let source = self.files.get(file_id).unwrap().source();
+        let item_docs = surface::docs::module_docs(&self.interner, source, &surface_module);
+
+        let labels = (module.items.iter())
+            .map(|core::Item::Def { label, .. }| *label)
+            .collect::<HashSet<_>>();
+        let interner = self.interner.borrow();
+
+        Ok((item_docs.into_iter())
+            .filter(|item_doc| labels.contains(&item_doc.label))
+            .map(|item_doc| {
+                let name = interner.resolve(item_doc.label).unwrap().to_owned();
+                (name, item_doc)
+            })
+            .collect())
+    }
+
+    /// Parse and elaborate a module, returning the diagnostics that were
+    /// reported instead of emitting them. The module is elaborated even if
+    /// errors were reported, with the erroneous parts elaborated to errors.
+    pub fn elaborate_module(&self, file_id: FileId) -> (core::Module<'_>, Diagnostics) {
+        let mut diagnostics = Diagnostics::new();
+        let surface_module = self.parse_module_into(file_id, &mut diagnostics);
+        let module = self.elab_module_into(file_id, &surface_module, &mut diagnostics);
+        (module, diagnostics)
+    }
+
+    fn load_surface_and_core_module(
+        &self,
+        file_id: FileId,
+    ) -> Result<
+        (
+            surface::Module<'_, ByteRange>,
+            core::Module<'_>,
+            Diagnostics,
+        ),
+        LoadError,
+    > {
+        let mut diagnostics = Diagnostics::new();
+        let surface_module = self.parse_module_into(file_id, &mut diagnostics);
+        if diagnostics.has_errors() {
+            return Err(LoadError::Parse(diagnostics));
+        }
+
+        let module = self.elab_module_into(file_id, &surface_module, &mut diagnostics);
+        match diagnostics.has_errors() {
+            true => Err(LoadError::Check(diagnostics)),
+            false => Ok((surface_module, module, diagnostics)),
+        }
+    }
+
+    fn parse_module_into(
+        &self,
+        file_id: FileId,
+        diagnostics: &mut Diagnostics,
+    ) -> surface::Module<'_, ByteRange> {
+        let source = self.files.get(file_id).unwrap().source();
+        let (surface_module, messages) =
+            surface::Module::parse(&self.interner, &self.surface_scope, source);
+        diagnostics.extend(messages.into_iter().map(|m| m.to_diagnostic(file_id)));
+        surface_module
+    }
+
+    fn elab_module_into(
+        &self,
+        file_id: FileId,
+        surface_module: &surface::Module<'_, ByteRange>,
+        diagnostics: &mut Diagnostics,
+    ) -> core::Module<'_> {
+        let mut context =
+            elaboration::Context::new(file_id, &self.interner, &self.core_scope, ItemEnv::new());
+        context.elab_module(&self.core_scope, surface_module, &mut |m| {
+            diagnostics.push(m.to_diagnostic(&self.interner));
+        })
+    }
+
     pub fn elaborate_and_emit_module(&mut self, file_id: FileId, pretty_core: bool) -> Status {
         let mut context =
             elaboration::Context::new(file_id, &self.interner, &self.core_scope, ItemEnv::new());
@@ -211,6 +402,16 @@ impl<'surface, 'core> Driver<'surface, 'core> {
         let module = context.elab_module(&self.core_scope, &surface_module, &mut |m| {
             self.emit_diagnostic(m.to_diagnostic(&self.interner));
         });
+        if self.report_mixed_item_uses {
+            context.report_mixed_item_uses(&module, &mut |m| {
+                self.emit_diagnostic(m.to_diagnostic(&self.interner));
+            });
+        }
+        if self.report_unused_items {
+            context.report_unused_items(&module, &mut |m| {
+                self.emit_diagnostic(m.to_diagnostic(&self.interner));
+            });
+        }
 
         // Return early if we’ve seen any errors, unless `allow_errors` is enabled
         if *self.seen_errors.borrow() && !self.allow_errors {
@@ -229,7 +430,7 @@ impl<'surface, 'core> Driver<'surface, 'core> {
         Status::Ok
     }
 
-    pub fn elaborate_and_emit_term(&mut self, file_id: FileId) -> Status {
+    pub fn elaborate_and_emit_term(&mut self, file_id: FileId, pretty_core: bool) -> Status {
         let mut context =
             elaboration::Context::new(file_id, &self.interner, &self.core_scope, ItemEnv::new());
 
@@ -243,6 +444,14 @@ impl<'surface, 'core> Driver<'surface, 'core> {
         if *self.seen_errors.borrow() && !self.allow_errors {
             return Status::Error;
         }
+        if pretty_core {
+            // Avoid annotating already annotated terms a second time
+            let mut expr = &term;
+            while let core::Term::Ann(_, inner_expr, _) = expr {
+                expr = *inner_expr;
+            }
+            self.emit_core_term(&core::Term::Ann(Span::Empty, expr, &r#type));
+        }
 
         self.surface_scope.reset(); // Reuse the surface scope for distillation
         let mut context = context.distillation_context(&self.surface_scope);
@@ -269,8 +478,18 @@ impl<'surface, 'core> Driver<'surface, 'core> {
             return Status::Error;
         }
 
-        let term = context.eval_env().normalize(&self.core_scope, &term);
-        let r#type = context.eval_env().normalize(&self.core_scope, &r#type);
+        // Report if normalization ran out of fuel
+        let range = surface_term.range();
+        let term = context.normalize_term(&self.core_scope, range, &term, &mut |m| {
+            self.emit_diagnostic(m.to_diagnostic(&self.interner));
+        });
+        let r#type = context.normalize_term(&self.core_scope, range, &r#type, &mut |m| {
+            self.emit_diagnostic(m.to_diagnostic(&self.interner));
+        });
+
+        if *self.seen_errors.borrow() && !self.allow_errors {
+            return Status::Error;
+        }
 
         self.surface_scope.reset(); // Reuse the surface scope for distillation
         let mut context = context.distillation_context(&self.surface_scope);
@@ -292,6 +511,10 @@ impl<'surface, 'core> Driver<'surface, 'core> {
 
         let initial_buffer = binary::Buffer::from(buffer_data);
         let mut binary_context = binary::Context::new(initial_buffer);
+        for (algorithm, decompressor) in &self.decompressors {
+            let decompressor = decompressor.clone();
+            binary_context.add_decompressor(*algorithm, move |data| decompressor(data));
+        }
         let mut item_env = ItemEnv::new();
 
         // Parse and elaborate a module if one was provided
@@ -323,6 +546,40 @@ impl<'surface, 'core> Driver<'surface, 'core> {
             return Status::Error;
         }
 
+        if self.emit_annotated {
+            let fields = match binary_context.read_entrypoint_fields(&format) {
+                Ok(fields) => fields,
+                Err(err) => {
+                    self.emit_diagnostic(self.read_error_to_diagnostic(err, &mut elab_context));
+                    return Status::Error;
+                }
+            };
+
+            // Render each field alongside the bytes it was read from
+            let bytes = (fields.iter())
+                .map(|field| field_bytes(&buffer_data[field.offsets.clone()]))
+                .collect::<Vec<_>>();
+            let bytes_width = bytes.iter().map(String::len).max().unwrap_or(0);
+
+            for (field, bytes) in fields.iter().zip(bytes) {
+                self.surface_scope.reset(); // Reuse the surface scope for distillation
+
+                let expr = elab_context
+                    .quote_env()
+                    .quote(&self.core_scope, &field.expr);
+                let expr = (elab_context.distillation_context(&self.surface_scope)).check(&expr);
+                let prefix = format!(
+                    "{:08x}  {:<width$}  ",
+                    field.offsets.start,
+                    bytes,
+                    width = bytes_width
+                );
+                self.emit_field(prefix, field.label, &expr);
+            }
+
+            return Status::Ok;
+        }
+
         let refs = match binary_context.read_entrypoint(&format) {
             Ok(refs) => refs,
             Err(err) => {
@@ -381,6 +638,14 @@ impl<'surface, 'core> Driver<'surface, 'core> {
         emit_writer.flush().unwrap();
     }
 
+    fn emit_core_term(&self, term: &core::Term<'_>) {
+        let context = core::pretty::Context::new(&self.interner);
+        let doc = context.term(term);
+        let mut emit_writer = self.emit_writer.borrow_mut();
+        writeln!(emit_writer, "{}", doc.pretty(self.emit_width)).unwrap();
+        emit_writer.flush().unwrap();
+    }
+
     fn emit_term(&self, term: &surface::Term<'_, ()>) {
         let context = surface::pretty::Context::new(&self.interner, &self.surface_scope);
         self.emit_doc(context.term(term).into_doc());
@@ -410,6 +675,25 @@ impl<'surface, 'core> Driver<'surface, 'core> {
         self.emit_doc(doc);
     }
 
+    fn emit_field(&self, prefix: String, label: StringId, expr: &surface::Term<'_, ()>) {
+        use pretty::DocAllocator;
+
+        let context = surface::pretty::Context::new(&self.interner, &self.surface_scope);
+        let label = self.interner.borrow().resolve(label).unwrap().to_owned();
+        let doc = context
+            .concat([
+                context.text(prefix),
+                context.text(label),
+                context.space(),
+                context.text("="),
+                context.space(),
+                context.term(expr),
+            ])
+            .into_doc();
+
+        self.emit_doc(doc);
+    }
+
     fn emit_doc(&self, doc: pretty::RefDoc) {
         let mut emit_writer = self.emit_writer.borrow_mut();
         writeln!(emit_writer, "{}", doc.pretty(self.emit_width)).unwrap();
@@ -417,6 +701,13 @@ impl<'surface, 'core> Driver<'surface, 'core> {
     }
 
     fn emit_diagnostic(&self, diagnostic: Diagnostic<FileId>) {
+        if self.dedup_diagnostics {
+            let key = DiagnosticKey::new(&diagnostic);
+            if !self.seen_diagnostics.borrow_mut().insert(key) {
+                return;
+            }
+        }
+
         let mut writer = self.diagnostic_writer.borrow_mut();
         let config = &self.codespan_config;
 
@@ -460,6 +751,12 @@ impl<'surface, 'core> Driver<'surface, 'core> {
                 .with_notes(vec![format!(
                     "A fail format was encountered when reading this file."
                 )]),
+            ReadError::ReadReportedError(span) => Diagnostic::error()
+                .with_message(err.to_string())
+                .with_labels(label_for_span(&span).into_iter().collect())
+                .with_notes(vec![format!(
+                    "This part of the format could not be elaborated because of an earlier error."
+                )]),
             ReadError::CondFailure(span, ref value) => {
                 let core_scope = &self.core_scope;
                 let surface_scope = &self.surface_scope;
@@ -479,8 +776,46 @@ impl<'surface, 'core> Driver<'surface, 'core> {
             ReadError::UnwrappedNone(_) => Diagnostic::error()
                 .with_message(err.to_string())
                 .with_notes(vec![format!("option_unwrap was called on a none value.")]),
+            ReadError::UnknownDecompressor(span, algorithm) => Diagnostic::error()
+                .with_message(err.to_string())
+                .with_labels(label_for_span(&span).into_iter().collect())
+                .with_notes(vec![format!(
+                    "No decompressor was registered for the algorithm `{algorithm:#010x}`."
+                )]),
+            ReadError::DecompressionFailed(span) => Diagnostic::error()
+                .with_message(err.to_string())
+                .with_labels(label_for_span(&span).into_iter().collect())
+                .with_notes(vec![format!(
+                    "The data could not be decompressed with the registered decompressor."
+                )]),
+            ReadError::PositionInDecompressedData(span) => Diagnostic::error()
+                .with_message(err.to_string())
+                .with_labels(label_for_span(&span).into_iter().collect())
+                .with_notes(vec![format!(
+                    "Links, dereferences, and stream positions cannot be used inside a `decompress` format."
+                )]),
+            ReadError::UnsupportedInStream(span) => Diagnostic::error()
+                .with_message(err.to_string())
+                .with_labels(label_for_span(&span).into_iter().collect())
+                .with_notes(vec![format!(
+                    "Links and dereferences can only be used when reading from a stream that can seek."
+                )]),
+            ReadError::ChecksumMismatch(span, expected, actual) => Diagnostic::error()
+                .with_message(err.to_string())
+                .with_labels(label_for_span(&span).into_iter().collect())
+                .with_notes(vec![format!(
+                    "Expected the checksum `{expected:#010x}`, but the data has the checksum `{actual:#010x}`."
+                )]),
+            ReadError::MagicMismatch(span, offset, expected, found) => Diagnostic::error()
+                .with_message(err.to_string())
+                .with_labels(label_for_span(&span).into_iter().collect())
+                .with_notes(vec![format!(
+                    "Expected the byte `{expected:#04x}` at offset {offset}, but found `{found:#04x}`."
+                )]),
             ReadError::BufferError(span, err) => self.buffer_error_to_diagnostic(err, span),
-            ReadError::InvalidFormat(span) | ReadError::InvalidValue(span) => Diagnostic::bug()
+            ReadError::InvalidFormat(span)
+            | ReadError::InvalidValue(span)
+            | ReadError::UnreadRef(span, _, _) => Diagnostic::bug()
                 .with_message(format!("unexpected error '{err}'"))
                 .with_labels(label_for_span(&span).into_iter().collect())
                 .with_notes(vec![format!(
@@ -532,9 +867,422 @@ impl<'surface, 'core> Driver<'surface, 'core> {
     }
 }
 
+/// A collection of diagnostics, for callers that need to inspect diagnostics
+/// together rather than emitting them as they are reported.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic<FileId>>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic<FileId>) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// The number of diagnostics with the given severity.
+    pub fn count(&self, severity: Severity) -> usize {
+        (self.diagnostics.iter())
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .count()
+    }
+
+    /// Returns true if any of the diagnostics are errors or bugs.
+    pub fn has_errors(&self) -> bool {
+        (self.diagnostics.iter()).any(|diagnostic| diagnostic.severity >= Severity::Error)
+    }
+
+    /// Sort the diagnostics by the start of their primary labels, keeping
+    /// diagnostics without a primary label at the end. Diagnostics that start
+    /// at the same position remain in the order they were reported.
+    pub fn into_sorted_vec(mut self) -> Vec<Diagnostic<FileId>> {
+        self.diagnostics.sort_by_key(|diagnostic| {
+            let primary_label = (diagnostic.labels.iter())
+                .find(|label| label.style == LabelStyle::Primary)
+                .map(|label| (u32::from(label.file_id), label.range.start));
+            (primary_label.is_none(), primary_label)
+        });
+        self.diagnostics
+    }
+}
+
+impl Extend<Diagnostic<FileId>> for Diagnostics {
+    fn extend<T: IntoIterator<Item = Diagnostic<FileId>>>(&mut self, iter: T) {
+        self.diagnostics.extend(iter);
+    }
+}
+
+/// The errors that prevented a module from being loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The module could not be parsed.
+    Parse(Diagnostics),
+    /// The module was parsed, but could not be elaborated.
+    Check(Diagnostics),
+}
+
+impl LoadError {
+    /// The diagnostics that were reported while loading the module, sorted by
+    /// their positions in the source.
+    pub fn into_diagnostics(self) -> Vec<Diagnostic<FileId>> {
+        match self {
+            LoadError::Parse(diagnostics) | LoadError::Check(diagnostics) => {
+                diagnostics.into_sorted_vec()
+            }
+        }
+    }
+}
+
+/// Serialize diagnostics to a JSON array, for tools like editors that need to
+/// consume diagnostics without parsing the rendered output. Labels are
+/// described by their file id and byte offsets.
+#[cfg(feature = "json")]
+pub fn diagnostics_to_json(diagnostics: &[Diagnostic<FileId>]) -> String {
+    use itertools::Itertools;
+
+    let severity = |severity| match severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    };
+    let label_style = |style| match style {
+        LabelStyle::Primary => "primary",
+        LabelStyle::Secondary => "secondary",
+    };
+    let label = |label: &Label<FileId>| {
+        format!(
+            r#"{{"style":{},"file_id":{},"start":{},"end":{},"message":{}}}"#,
+            json_string(label_style(label.style)),
+            label.file_id,
+            label.range.start,
+            label.range.end,
+            json_string(&label.message),
+        )
+    };
+    let diagnostic = |diagnostic: &Diagnostic<FileId>| {
+        format!(
+            r#"{{"severity":{},"code":{},"message":{},"labels":[{}],"notes":[{}]}}"#,
+            json_string(severity(diagnostic.severity)),
+            diagnostic
+                .code
+                .as_deref()
+                .map_or("null".to_owned(), json_string),
+            json_string(&diagnostic.message),
+            diagnostic.labels.iter().map(label).join(","),
+            diagnostic
+                .notes
+                .iter()
+                .map(|note| json_string(note))
+                .join(","),
+        )
+    };
+
+    format!("[{}]", diagnostics.iter().map(diagnostic).join(","))
+}
+
+/// Render a string as a JSON string literal.
+#[cfg(feature = "json")]
+fn json_string(s: &str) -> String {
+    use std::fmt::Write;
+
+    let mut output = String::with_capacity(s.len() + 2);
+    output.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            ch if ch.is_control() => write!(output, "\\u{:04x}", u32::from(ch)).unwrap(),
+            ch => output.push(ch),
+        }
+    }
+    output.push('"');
+    output
+}
+
+/// The parts of a diagnostic used to detect duplicates.
+#[derive(PartialEq, Eq, Hash)]
+struct DiagnosticKey {
+    code: Option<String>,
+    message: String,
+    primary_label: Option<(FileId, Range<usize>)>,
+}
+
+impl DiagnosticKey {
+    fn new(diagnostic: &Diagnostic<FileId>) -> DiagnosticKey {
+        let primary_label = (diagnostic.labels.iter())
+            .find(|label| label.style == LabelStyle::Primary)
+            .map(|label| (label.file_id, label.range.clone()));
+
+        DiagnosticKey {
+            code: diagnostic.code.clone(),
+            message: diagnostic.message.clone(),
+            primary_label,
+        }
+    }
+}
+
 fn label_for_span(span: &Span) -> Option<Label<FileId>> {
     match span {
         Span::Range(range) => Some(Label::primary(range.file_id(), *range)),
         Span::Empty => None,
     }
 }
+
+/// The maximum number of bytes to show for each field in annotated output.
+const MAX_FIELD_BYTES: usize = 8;
+
+/// Render the bytes of a field in hexadecimal, eliding any bytes past
+/// [`MAX_FIELD_BYTES`].
+fn field_bytes(bytes: &[u8]) -> String {
+    use itertools::Itertools;
+
+    let shown = bytes
+        .iter()
+        .take(MAX_FIELD_BYTES)
+        .map(|byte| format!("{:02x}", byte));
+    match bytes.len() > MAX_FIELD_BYTES {
+        true => shown.chain(["..".to_owned()]).join(" "),
+        false => shown.join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::rc::Rc;
+
+    use codespan_reporting::term::termcolor::NoColor;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn dedup_diagnostics() {
+        let buffer = SharedBuffer::default();
+        let mut driver = Driver::new();
+        driver.set_dedup_diagnostics(true);
+        driver.set_diagnostic_writer(NoColor::new(buffer.clone()));
+
+        // Each elaboration reports the unbound name at both of its references
+        let source = "{ a <- x, b <- x }".to_owned();
+        let file_id = driver
+            .load_source_string("<test>".to_owned(), source)
+            .unwrap();
+        for _ in 0..3 {
+            driver.elaborate_and_emit_term(file_id, false);
+        }
+
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        assert_eq!(output.matches("cannot find `x` in scope").count(), 2);
+    }
+
+    #[test]
+    fn sorted_diagnostics() {
+        let file_id = FileId::try_from(1).unwrap();
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.push(Diagnostic::warning().with_message("unlabelled"));
+        diagnostics.push(
+            Diagnostic::error()
+                .with_message("second")
+                .with_labels(vec![Label::primary(file_id, 4..6)]),
+        );
+        diagnostics.push(
+            Diagnostic::warning()
+                .with_message("first")
+                .with_labels(vec![
+                    Label::secondary(file_id, 8..9),
+                    Label::primary(file_id, 0..2),
+                ]),
+        );
+
+        assert!(diagnostics.has_errors());
+        assert_eq!(diagnostics.count(Severity::Warning), 2);
+        assert_eq!(diagnostics.count(Severity::Error), 1);
+
+        let messages = (diagnostics.into_sorted_vec().into_iter())
+            .map(|diagnostic| diagnostic.message)
+            .collect::<Vec<_>>();
+        assert_eq!(messages, ["first", "second", "unlabelled"]);
+    }
+
+    #[test]
+    fn elaborate_module_diagnostics() {
+        let mut driver = Driver::new();
+        // `b` is elaborated before `a`, so its error is reported first
+        let source = "def a : U16 = b;\ndef b : U8 = c;".to_owned();
+        let file_id = driver.load_source_string("<test>".to_owned(), source);
+        let (module, diagnostics) = driver.elaborate_module(file_id.unwrap());
+
+        // Items are still elaborated when errors are reported
+        assert_eq!(module.items.len(), 2);
+        assert!(diagnostics.has_errors());
+        assert_eq!(diagnostics.count(Severity::Error), 2);
+
+        let messages = (diagnostics.into_sorted_vec().into_iter())
+            .map(|diagnostic| diagnostic.message)
+            .collect::<Vec<_>>();
+        assert_eq!(messages, ["mismatched types", "cannot find `c` in scope"]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn diagnostics_json() {
+        let mut driver = Driver::new();
+        let source = "def x : U8 = Type;".to_owned();
+        let file_id = driver.load_source_string("<test>".to_owned(), source);
+        let file_id = file_id.unwrap();
+        let diagnostics = match driver.load_module(file_id) {
+            Ok(_) => panic!("expected a type mismatch"),
+            Err(error) => error.into_diagnostics(),
+        };
+
+        let expected = [
+            r#"[{"severity":"error","code":null,"message":"mismatched types","#,
+            &format!(r#""labels":[{{"style":"primary","file_id":{file_id},"#),
+            r#""start":13,"end":17,"#,
+            r#""message":"type mismatch, expected `U8`, found `Type`"}],"#,
+            r#""notes":["expected `U8`\n   found `Type`"]}]"#,
+        ];
+        assert_eq!(diagnostics_to_json(&diagnostics), expected.concat());
+    }
+
+    #[test]
+    fn load_module_errors() {
+        let mut driver = Driver::new();
+        let mut load = |source: &str| {
+            let file_id = driver.load_source_string("<test>".to_owned(), source.to_owned());
+            let file_id = file_id.unwrap();
+            (driver.load_module(file_id)).map(|(module, diagnostics)| {
+                (module.items.len(), diagnostics.count(Severity::Warning))
+            })
+        };
+
+        assert!(matches!(load("def x : U8 = 1;"), Ok((1, 0))));
+        // Warnings are returned with the module
+        assert!(matches!(
+            load("def x : U8 = match (1 : U8) { y => 1, z => 2 };"),
+            Ok((1, 1)),
+        ));
+        assert!(matches!(load("def x : U8 = ;"), Err(LoadError::Parse(_))));
+        assert!(matches!(
+            load("def x : U8 = Type;"),
+            Err(LoadError::Check(_))
+        ));
+
+        let diagnostics = load("def x : U8 = Type;").unwrap_err().into_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn module_docs() {
+        let mut driver = Driver::new();
+        let source = "/// A single byte.\n\
+                      def byte = u8;\n\
+                      /// An alias of `byte`.\n\
+                      def octet = byte;\n\
+                      def header = {\n    \
+                          /// The length of the data\n    \
+                          len <- octet,\n\
+                      };\n";
+        let file_id = driver.load_source_string("<test>".to_owned(), source.to_owned());
+        let docs = driver.module_docs(file_id.unwrap()).unwrap();
+
+        let names = docs.keys().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(names, ["byte", "header", "octet"]);
+        assert_eq!(docs["byte"].doc, "A single byte.");
+        assert_eq!(docs["octet"].doc, "An alias of `byte`.");
+        assert_eq!(docs["header"].doc, "");
+        assert_eq!(docs["header"].fields[0].doc, "The length of the data");
+        assert_eq!(docs["header"].fields[0].format.as_deref(), Some("octet"));
+    }
+
+    #[test]
+    fn add_decompressor() {
+        let read_format = |driver: &mut Driver| {
+            driver.set_diagnostic_writer(NoColor::new(SharedBuffer::default()));
+            driver.set_emit_writer(NoColor::new(SharedBuffer::default()));
+            let source = "decompress 1 2 u16be".to_owned();
+            let file_id = driver.load_source_string("<test>".to_owned(), source);
+            driver.read_and_emit_format(None, file_id.unwrap(), &[0x12, 0x34])
+        };
+
+        let mut driver = Driver::new();
+        assert!(matches!(read_format(&mut driver), Status::Error));
+
+        let mut driver = Driver::new();
+        driver.add_decompressor(1, |data| Some(data.to_vec()));
+        assert!(matches!(read_format(&mut driver), Status::Ok));
+
+        // Registering a decompressor again replaces the previous one
+        let mut driver = Driver::new();
+        driver.add_decompressor(1, |data| Some(data.to_vec()));
+        driver.add_decompressor(1, |_| None);
+        assert!(matches!(read_format(&mut driver), Status::Error));
+    }
+
+    #[test]
+    fn emit_width() {
+        let source = "{ first : U8, second : U16, third : U32, fourth : U64, fifth : S8 }";
+        let emit_term = |emit_width| {
+            let buffer = SharedBuffer::default();
+            let mut driver = Driver::new();
+            driver.set_emit_width(emit_width);
+            driver.set_emit_writer(NoColor::new(buffer.clone()));
+
+            let file_id = driver.load_source_string("<test>".to_owned(), source.to_owned());
+            let status = driver.elaborate_and_emit_term(file_id.unwrap(), false);
+            assert!(matches!(status, Status::Ok));
+
+            let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+            output.lines().count()
+        };
+
+        // Terms are wrapped when they do not fit within the width
+        assert!(emit_term(40) > 1);
+        assert_eq!(emit_term(200), 1);
+    }
+
+    #[test]
+    fn emit_core_term() {
+        let buffer = SharedBuffer::default();
+        let mut driver = Driver::new();
+        driver.set_emit_writer(NoColor::new(buffer.clone()));
+
+        let file_id = driver.load_source_string("<test>".to_owned(), "1 : U8".to_owned());
+        let status = driver.elaborate_and_emit_term(file_id.unwrap(), true);
+        assert!(matches!(status, Status::Ok));
+
+        // Annotated terms are only annotated once
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        assert_eq!(output, "U8(1, Decimal) : U8Type\n1 : U8\n");
+    }
+}
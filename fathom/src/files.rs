@@ -7,11 +7,13 @@ use std::ops::Range;
 
 use codespan_reporting::files::{Error, SimpleFile};
 
+use crate::source::FileRange;
+
 /// File id.
 // - Use `u32` over `usize` because 4 billion files should be enough for anyone
 // - `u16` doesn't save any size in `ByteRange` or `Span` compared to `u32`
 // - `NonZeroU32` saves 4 bytes on the size of `Span` compared to `u32`
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct FileId(NonZeroU32);
 
 impl fmt::Display for FileId {
@@ -75,6 +77,16 @@ where
         let index = usize::from(file_id) - 1;
         self.files.get(index).ok_or(Error::FileMissing)
     }
+
+    /// Get the source text covered by a range. Offsets past the end of the
+    /// file are clamped to the end of the file, and an empty string is returned
+    /// if the range does not fall on character boundaries.
+    pub fn snippet(&self, range: FileRange) -> Result<&str, Error> {
+        let source = self.get(range.file_id())?.source().as_ref();
+        let end = usize::min(range.end() as usize, source.len());
+        let start = usize::min(range.start() as usize, end);
+        Ok(source.get(start..end).unwrap_or(""))
+    }
 }
 
 impl<'a, Name, Source> codespan_reporting::files::Files<'a> for Files<Name, Source>
@@ -102,3 +114,31 @@ where
         self.get(file_id)?.line_range((), line_index)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::ByteRange;
+
+    #[test]
+    fn snippet() {
+        let source = include_str!("../../formats/object-id.fathom");
+        let mut files = Files::new();
+        let file_id = files.add("object-id.fathom", source);
+
+        let start = source.find("repeat_len8 3 u8").unwrap() as u32;
+        let range = |start, end| FileRange::new(file_id, ByteRange::new(start, end));
+        assert_eq!(
+            files.snippet(range(start, start + 16)).unwrap(),
+            "repeat_len8 3 u8"
+        );
+
+        // Out of range offsets are clamped to the end of the file
+        let len = source.len() as u32;
+        assert_eq!(
+            files.snippet(range(len - 3, len + 10)).unwrap(),
+            &source[source.len() - 3..]
+        );
+        assert_eq!(files.snippet(range(len + 5, len + 10)).unwrap(), "");
+    }
+}
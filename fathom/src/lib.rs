@@ -19,4 +19,6 @@ mod driver;
 pub const BUG_REPORT_URL: &str = concat!(env!("CARGO_PKG_REPOSITORY"), "/issues/new");
 
 // Public exports
-pub use driver::{Driver, Status};
+#[cfg(feature = "json")]
+pub use driver::diagnostics_to_json;
+pub use driver::{Bytes, Diagnostics, Driver, LoadError, Status};
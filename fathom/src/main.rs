@@ -29,9 +29,18 @@ enum Cli {
         /// Continue even if errors were encountered
         #[clap(long = "allow-errors")]
         allow_errors: bool,
-        /// Pretty print core module
-        #[clap(long = "pretty-core", conflicts_with("TERM_FILE"))]
+        /// Report identical diagnostics only once
+        #[clap(long = "dedup-diagnostics")]
+        dedup_diagnostics: bool,
+        /// Pretty print core module or term
+        #[clap(long = "pretty-core")]
         pretty_core: bool,
+        /// Report items that are used both as formats and as types
+        #[clap(long = "report-mixed-uses", conflicts_with("TERM_FILE"))]
+        report_mixed_uses: bool,
+        /// Report definitions that are never used
+        #[clap(long = "report-unused", conflicts_with("TERM_FILE"))]
+        report_unused: bool,
     },
     /// Normalize a Fathom term, printing its normal form and type
     Norm {
@@ -41,6 +50,9 @@ enum Cli {
         /// Continue even if errors were encountered
         #[clap(long = "allow-errors")]
         allow_errors: bool,
+        /// Report identical diagnostics only once
+        #[clap(long = "dedup-diagnostics")]
+        dedup_diagnostics: bool,
     },
     /// Manipulate binary data based on a Fathom format
     #[clap(after_help = DATA_COMMAND_AFTER_HELP)]
@@ -68,6 +80,17 @@ enum Cli {
         /// Continue even if errors were encountered
         #[clap(long = "allow-errors")]
         allow_errors: bool,
+        /// Report identical diagnostics only once
+        #[clap(long = "dedup-diagnostics")]
+        dedup_diagnostics: bool,
+        /// Map the binary file into memory instead of reading it
+        #[clap(long = "mmap")]
+        mmap: bool,
+        /// Print the offset and bytes of each field that was read
+        ///
+        /// The format must be a record format. Links are not followed.
+        #[clap(long = "annotated")]
+        annotated: bool,
     },
 }
 
@@ -131,10 +154,13 @@ fn load_source_or_exit(
     unwrap_or_exit(driver.load_source(name, source.as_bytes()))
 }
 
-fn read_bytes_or_exit(driver: &mut fathom::Driver, file: PathOrStdin) -> Vec<u8> {
+fn read_bytes_or_exit(driver: &mut fathom::Driver, file: PathOrStdin, mmap: bool) -> fathom::Bytes {
     unwrap_or_exit(match file {
-        PathOrStdin::StdIn => driver.read_bytes("<stdin>".to_owned(), std::io::stdin()),
-        PathOrStdin::Path(path) => driver.read_bytes_path(&path),
+        PathOrStdin::StdIn => driver
+            .read_bytes("<stdin>".to_owned(), std::io::stdin())
+            .map(fathom::Bytes::Read),
+        PathOrStdin::Path(path) if mmap => driver.map_bytes_path(&path),
+        PathOrStdin::Path(path) => driver.read_bytes_path(&path).map(fathom::Bytes::Read),
     })
 }
 
@@ -151,11 +177,17 @@ fn main() -> ! {
             module_file,
             term_file,
             allow_errors,
+            dedup_diagnostics,
             pretty_core,
+            report_mixed_uses,
+            report_unused,
         } => {
             let mut driver = fathom::Driver::new();
             driver.install_panic_hook();
             driver.set_allow_errors(allow_errors);
+            driver.set_dedup_diagnostics(dedup_diagnostics);
+            driver.set_report_mixed_item_uses(report_mixed_uses);
+            driver.set_report_unused_items(report_unused);
             driver.set_emit_width(get_pretty_width());
 
             let status = match (module_file, term_file) {
@@ -165,7 +197,7 @@ fn main() -> ! {
                 }
                 (None, Some(term_file)) => {
                     let file_id = load_file_or_exit(&mut driver, term_file);
-                    driver.elaborate_and_emit_term(file_id)
+                    driver.elaborate_and_emit_term(file_id, pretty_core)
                 }
                 (Some(_), Some(_)) | (None, None) => {
                     unreachable!(r#"guarded by `required_unless_present = "input"`"#)
@@ -177,10 +209,12 @@ fn main() -> ! {
         Cli::Norm {
             term_file,
             allow_errors,
+            dedup_diagnostics,
         } => {
             let mut driver = fathom::Driver::new();
             driver.install_panic_hook();
             driver.set_allow_errors(allow_errors);
+            driver.set_dedup_diagnostics(dedup_diagnostics);
             driver.set_emit_width(get_pretty_width());
 
             let file_id = load_file_or_exit(&mut driver, term_file);
@@ -193,16 +227,21 @@ fn main() -> ! {
             format,
             binary_file,
             allow_errors,
+            dedup_diagnostics,
+            mmap,
+            annotated,
         } => {
             let mut driver = fathom::Driver::new();
             driver.install_panic_hook();
             driver.set_allow_errors(allow_errors);
+            driver.set_dedup_diagnostics(dedup_diagnostics);
             driver.set_emit_width(get_pretty_width());
+            driver.set_emit_annotated(annotated);
 
             let module_file_id = module_file.map(|input| load_file_or_exit(&mut driver, input));
             let format_file_id = load_source_or_exit(&mut driver, "<FORMAT>".to_owned(), format);
 
-            let data = read_bytes_or_exit(&mut driver, binary_file);
+            let data = read_bytes_or_exit(&mut driver, binary_file, mmap);
             let status = driver.read_and_emit_format(module_file_id, format_file_id, &data);
 
             std::process::exit(status.exit_code());
@@ -1,6 +1,7 @@
 //! Types related to source files.
 
 use std::fmt;
+use std::hash::{BuildHasher, BuildHasherDefault};
 use std::ops::{Deref, DerefMut, Range};
 
 use crate::files::FileId;
@@ -8,28 +9,29 @@ use crate::files::FileId;
 // Interned strings.
 pub type StringId = string_interner::symbol::SymbolU32;
 
+/// The hash builder used by string interners by default.
+pub type DefaultHashBuilder = BuildHasherDefault<fxhash::FxHasher32>;
+
 /// String interner.
-pub struct StringInterner {
+pub struct StringInterner<H = DefaultHashBuilder> {
     alphabetic_names: Vec<StringId>,
     tuple_labels: Vec<StringId>,
-    strings: Strings,
+    strings: Strings<H>,
 }
 
-type Strings = string_interner::StringInterner<
-    string_interner::backend::BucketBackend<StringId>,
-    std::hash::BuildHasherDefault<fxhash::FxHasher32>,
->;
+type Strings<H> =
+    string_interner::StringInterner<string_interner::backend::BucketBackend<StringId>, H>;
 
-impl Deref for StringInterner {
-    type Target = Strings;
+impl<H> Deref for StringInterner<H> {
+    type Target = Strings<H>;
 
-    fn deref(&self) -> &Strings {
+    fn deref(&self) -> &Strings<H> {
         &self.strings
     }
 }
 
-impl DerefMut for StringInterner {
-    fn deref_mut(&mut self) -> &mut Strings {
+impl<H> DerefMut for StringInterner<H> {
+    fn deref_mut(&mut self) -> &mut Strings<H> {
         &mut self.strings
     }
 }
@@ -37,13 +39,44 @@ impl DerefMut for StringInterner {
 impl StringInterner {
     /// Construct an empty string interner.
     pub fn new() -> StringInterner {
+        StringInterner::with_hasher(DefaultHashBuilder::default())
+    }
+}
+
+impl<H: BuildHasher> StringInterner<H> {
+    /// Construct an empty string interner that uses the supplied hash builder.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::collections::hash_map::RandomState;
+    ///
+    /// use fathom::source::StringInterner;
+    ///
+    /// let mut interner = StringInterner::with_hasher(RandomState::new());
+    /// let name = interner.get_or_intern("foo");
+    /// assert_eq!(interner.resolve(name), Some("foo"));
+    /// ```
+    pub fn with_hasher(hash_builder: H) -> StringInterner<H> {
         StringInterner {
             alphabetic_names: Vec::new(),
             tuple_labels: Vec::new(),
-            strings: string_interner::StringInterner::new(),
+            strings: string_interner::StringInterner::with_hasher(hash_builder),
         }
     }
 
+    /// Intern each of the strings of an iterator, returning their ids in the
+    /// same order as the strings.
+    pub fn get_or_intern_all<T: AsRef<str>>(
+        &mut self,
+        strings: impl IntoIterator<Item = T>,
+    ) -> Vec<StringId> {
+        let strings = strings.into_iter();
+        let mut ids = Vec::with_capacity(strings.size_hint().0);
+        ids.extend(strings.map(|string| self.strings.get_or_intern(string)));
+        ids
+    }
+
     /// Allocate and intern all alphabetic names up-to and including `max_index`
     /// if they are not already present.
     pub fn reserve_alphabetic_names(&mut self, max_index: usize) {
@@ -127,6 +160,26 @@ impl StringInterner {
     pub fn is_tuple_labels(&mut self, labels: &[StringId]) -> bool {
         labels == self.get_tuple_labels(0..labels.len())
     }
+
+    /// Compute statistics about the strings that have been interned so far.
+    pub fn stats(&self) -> InternerStats {
+        InternerStats {
+            unique_strings: self.strings.len(),
+            total_bytes: (&self.strings).into_iter().map(|(_, s)| s.len()).sum(),
+            tuple_labels: self.tuple_labels.len(),
+        }
+    }
+}
+
+/// Statistics about the strings stored in a [`StringInterner`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InternerStats {
+    /// The number of unique strings that have been interned.
+    pub unique_strings: usize,
+    /// The total length of the interned strings, in bytes.
+    pub total_bytes: usize,
+    /// The number of tuple labels that have been reserved.
+    pub tuple_labels: usize,
 }
 
 fn alphabetic_name(index: usize) -> String {
@@ -207,6 +260,15 @@ impl Span {
             (_, _) => Span::Empty,
         }
     }
+
+    /// Merge a sequence of spans into a single span that encloses all of them.
+    /// Returns [`Span::Empty`] if the sequence is empty, or if any of the spans
+    /// are empty or come from different files.
+    pub fn merge_all(spans: impl IntoIterator<Item = Span>) -> Span {
+        let mut spans = spans.into_iter();
+        let first = spans.next().unwrap_or(Span::Empty);
+        spans.fold(first, |merged, span| merged.merge(&span))
+    }
 }
 
 impl From<FileRange> for Span {
@@ -381,6 +443,74 @@ impl TryFrom<String> for ProgramSource {
 mod tests {
     use super::*;
 
+    #[test]
+    fn interner_stats() {
+        let mut interner = StringInterner::new();
+        interner.get_or_intern("foo");
+        interner.get_or_intern("bar");
+        interner.get_or_intern("foo");
+        interner.reserve_tuple_labels(2);
+
+        assert_eq!(
+            interner.stats(),
+            InternerStats {
+                unique_strings: 5,
+                total_bytes: 12,
+                tuple_labels: 3,
+            },
+        );
+    }
+
+    #[test]
+    fn get_or_intern_all() {
+        let labels = vec!["magic", "len", "data", "len"];
+        let mut interner = StringInterner::new();
+        let ids = interner.get_or_intern_all(&labels);
+
+        let mut other_interner = StringInterner::new();
+        let other_ids = (labels.iter())
+            .map(|label| other_interner.get_or_intern(label))
+            .collect::<Vec<_>>();
+
+        assert_eq!(ids, other_ids);
+        assert_eq!(ids[1], ids[3]);
+        assert_eq!(interner.resolve(ids[2]), Some("data"));
+    }
+
+    #[test]
+    fn interner_default_hasher() {
+        let mut interner: StringInterner<DefaultHashBuilder> = StringInterner::new();
+        let name = interner.get_or_intern("foo");
+        assert_eq!(interner.resolve(name), Some("foo"));
+        assert_eq!(interner.get_tuple_label(0), interner.get_or_intern("_0"));
+    }
+
+    #[test]
+    fn merge_all_spans() {
+        let file_id = FileId::try_from(1).unwrap();
+        let other_file_id = FileId::try_from(2).unwrap();
+        let span =
+            |file_id, start, end| Span::from(FileRange::new(file_id, ByteRange::new(start, end)));
+
+        let spans = [
+            span(file_id, 0, 3),
+            span(file_id, 3, 5),
+            span(file_id, 5, 9),
+        ];
+        match Span::merge_all(spans) {
+            Span::Range(range) => assert_eq!((range.start(), range.end()), (0, 9)),
+            Span::Empty => panic!("expected a range"),
+        }
+
+        let spans = [
+            span(file_id, 0, 3),
+            span(other_file_id, 3, 5),
+            span(file_id, 5, 9),
+        ];
+        assert!(matches!(Span::merge_all(spans), Span::Empty));
+        assert!(matches!(Span::merge_all([]), Span::Empty));
+    }
+
     #[test]
     /// `ByteRange` is used a lot. Ensure it doesn't grow accidentally.
     fn byte_range_size() {
@@ -20,10 +20,11 @@ pub mod lexer;
 pub mod pretty;
 
 pub mod distillation;
+pub mod docs;
 pub mod elaboration;
 
 /// Modules, consisting of a sequence of top-level items.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub struct Module<'arena, Range> {
     items: &'arena [Item<'arena, Range>],
 }
@@ -36,9 +37,10 @@ impl<'arena> Module<'arena, ByteRange> {
         scope: &'arena Scope<'arena>,
         source: &ProgramSource,
     ) -> (Module<'arena, ByteRange>, Vec<ParseMessage>) {
-        let mut messages = Vec::new();
+        let tokens = lexer::tokens(source).collect::<Vec<_>>();
+        let delimiter_messages = check_delimiters(&tokens);
 
-        let tokens = lexer::tokens(source);
+        let mut messages = Vec::new();
         let term = grammar::ModuleParser::new()
             .parse(interner, scope, &mut messages, tokens)
             .unwrap_or_else(|error| {
@@ -46,12 +48,12 @@ impl<'arena> Module<'arena, ByteRange> {
                 Module { items: &[] }
             });
 
-        (term, messages)
+        (term, merge_delimiter_messages(messages, delimiter_messages))
     }
 }
 
 /// Top-level items.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub enum Item<'arena, Range> {
     /// Top-level definitions
     Def(ItemDef<'arena, Range>),
@@ -60,7 +62,7 @@ pub enum Item<'arena, Range> {
 }
 
 /// Top-level definitions
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub struct ItemDef<'arena, Range> {
     /// The full range of the definition
     range: Range,
@@ -75,7 +77,7 @@ pub struct ItemDef<'arena, Range> {
 }
 
 /// Surface patterns.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub enum Pattern<Range> {
     /// Named patterns, eg. `x`, `true`, `false`
     Name(Range, StringId),
@@ -86,6 +88,11 @@ pub enum Pattern<Range> {
     /// As with [term literals][Term::StringLiteral], these will be parsed fully
     /// during [elaboration].
     StringLiteral(Range, StringId),
+    /// Character literal patterns, eg. `'A'`
+    ///
+    /// As with [term literals][Term::CharLiteral], these will be parsed fully
+    /// during [elaboration].
+    CharLiteral(Range, StringId),
     /// Number literal patterns, eg. `1`, `0x00FF`
     ///
     /// As with [term literals][Term::NumberLiteral], these will be parsed fully
@@ -97,7 +104,7 @@ pub enum Pattern<Range> {
     // RecordLiteral(Range, &'arena [((Range, StringId), Pattern<'arena, Range>)]),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Hash)]
 pub enum BinOp<Range> {
     Add(Range),
     Sub(Range),
@@ -173,6 +180,7 @@ impl<Range: Clone> Pattern<Range> {
             Pattern::Name(range, _)
             | Pattern::Placeholder(range)
             | Pattern::StringLiteral(range, _)
+            | Pattern::CharLiteral(range, _)
             | Pattern::NumberLiteral(range, _)
             | Pattern::BooleanLiteral(range, _) => range.clone(),
         }
@@ -180,7 +188,7 @@ impl<Range: Clone> Pattern<Range> {
 }
 
 /// Surface terms.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub enum Term<'arena, Range> {
     /// Parenthesized term
     Paren(Range, &'arena Term<'arena, Range>),
@@ -211,11 +219,16 @@ pub enum Term<'arena, Range> {
         &'arena Term<'arena, Range>,
         &'arena Term<'arena, Range>,
     ),
-    /// Match expressions
+    /// Match expressions, with equations that can be guarded by boolean
+    /// conditions, eg. `n if n > 128 => ...`
     Match(
         Range,
         &'arena Term<'arena, Range>,
-        &'arena [(Pattern<Range>, Term<'arena, Range>)],
+        &'arena [(
+            Pattern<Range>,
+            Option<Term<'arena, Range>>,
+            Term<'arena, Range>,
+        )],
     ),
     /// The type of types.
     Universe(Range),
@@ -262,6 +275,11 @@ pub enum Term<'arena, Range> {
     /// These are stored as strings, and will be parsed during [elaboration]
     /// once the target type is known.
     StringLiteral(Range, StringId),
+    /// Character literals.
+    ///
+    /// These are stored as strings, and will be parsed during [elaboration]
+    /// once the target type is known.
+    CharLiteral(Range, StringId),
     /// Number literals.
     ///
     /// These are stored as strings, and will be parsed during [elaboration]
@@ -273,6 +291,12 @@ pub enum Term<'arena, Range> {
     FormatRecord(Range, &'arena [FormatField<'arena, Range>]),
     /// Overlap format.
     FormatOverlap(Range, &'arena [FormatField<'arena, Range>]),
+    /// Sequence format, binding each field for use in a final result.
+    FormatDo(
+        Range,
+        &'arena [FormatField<'arena, Range>],
+        &'arena Term<'arena, Range>,
+    ),
     /// Conditional format.
     FormatCond(
         Range,
@@ -314,11 +338,13 @@ impl<'arena, Range: Clone> Term<'arena, Range> {
             | Term::Proj(range, _, _)
             | Term::ArrayLiteral(range, _)
             | Term::StringLiteral(range, _)
+            | Term::CharLiteral(range, _)
             | Term::NumberLiteral(range, _)
             | Term::BooleanLiteral(range, _)
             | Term::FormatRecord(range, _)
             | Term::FormatCond(range, _, _, _)
             | Term::FormatOverlap(range, _)
+            | Term::FormatDo(range, _, _)
             | Term::BinOp(range, _, _, _)
             | Term::ReportedError(range) => range.clone(),
         }
@@ -333,9 +359,10 @@ impl<'arena> Term<'arena, FileRange> {
         scope: &'arena Scope<'arena>,
         source: &ProgramSource,
     ) -> (Term<'arena, ByteRange>, Vec<ParseMessage>) {
-        let mut messages = Vec::new();
+        let tokens = lexer::tokens(source).collect::<Vec<_>>();
+        let delimiter_messages = check_delimiters(&tokens);
 
-        let tokens = lexer::tokens(source);
+        let mut messages = Vec::new();
         let term = grammar::TermParser::new()
             .parse(interner, scope, &mut messages, tokens)
             .unwrap_or_else(|error| {
@@ -345,25 +372,25 @@ impl<'arena> Term<'arena, FileRange> {
                 Term::ReportedError(range)
             });
 
-        (term, messages)
+        (term, merge_delimiter_messages(messages, delimiter_messages))
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub struct Param<'arena, Range> {
     pub plicity: Plicity,
     pub pattern: Pattern<Range>,
     pub r#type: Option<Term<'arena, Range>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub struct Arg<'arena, Range> {
     pub plicity: Plicity,
     pub term: Term<'arena, Range>,
 }
 
 /// A field declaration in a record and offset format
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub enum FormatField<'arena, Range> {
     /// Regular format field
     Format {
@@ -386,7 +413,7 @@ pub enum FormatField<'arena, Range> {
 }
 
 /// A field declaration in a record type
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub struct TypeField<'arena, Range> {
     /// Label identifying the field
     label: (Range, StringId),
@@ -395,7 +422,7 @@ pub struct TypeField<'arena, Range> {
 }
 
 /// A field definition in a record literal
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub struct ExprField<'arena, Range> {
     /// Label identifying the field
     label: (Range, StringId),
@@ -403,6 +430,309 @@ pub struct ExprField<'arena, Range> {
     expr: Term<'arena, Range>,
 }
 
+impl<'arena, Range: Clone> Module<'arena, Range> {
+    /// Rebuild the module in the `scope`, applying `f` to each of its source
+    /// ranges.
+    pub fn map_range<'out, T>(
+        &self,
+        scope: &'out Scope<'out>,
+        f: &impl Fn(Range) -> T,
+    ) -> Module<'out, T> {
+        let items = self.items.iter().map(|item| match item {
+            Item::Def(item) => Item::Def(item.map_range(scope, f)),
+            Item::ReportedError(range) => Item::ReportedError(f(range.clone())),
+        });
+
+        Module {
+            items: scope.to_scope_from_iter(items),
+        }
+    }
+}
+
+impl<'arena, Range: Clone> ItemDef<'arena, Range> {
+    /// Rebuild the definition in the `scope`, applying `f` to each of its
+    /// source ranges.
+    pub fn map_range<'out, T>(
+        &self,
+        scope: &'out Scope<'out>,
+        f: &impl Fn(Range) -> T,
+    ) -> ItemDef<'out, T> {
+        ItemDef {
+            range: f(self.range.clone()),
+            label: map_label(&self.label, f),
+            params: map_params(scope, self.params, f),
+            r#type: (self.r#type).map(|r#type| scope.to_scope(r#type.map_range(scope, f)) as &_),
+            expr: scope.to_scope(self.expr.map_range(scope, f)),
+        }
+    }
+}
+
+impl<Range: Clone> Pattern<Range> {
+    /// Apply `f` to the source range of the pattern.
+    pub fn map_range<T>(&self, f: &impl Fn(Range) -> T) -> Pattern<T> {
+        match self {
+            Pattern::Name(range, name) => Pattern::Name(f(range.clone()), *name),
+            Pattern::Placeholder(range) => Pattern::Placeholder(f(range.clone())),
+            Pattern::StringLiteral(range, lit) => Pattern::StringLiteral(f(range.clone()), *lit),
+            Pattern::CharLiteral(range, lit) => Pattern::CharLiteral(f(range.clone()), *lit),
+            Pattern::NumberLiteral(range, lit) => Pattern::NumberLiteral(f(range.clone()), *lit),
+            Pattern::BooleanLiteral(range, lit) => Pattern::BooleanLiteral(f(range.clone()), *lit),
+        }
+    }
+}
+
+impl<'arena, Range: Clone> Term<'arena, Range> {
+    /// Rebuild the term in the `scope`, applying `f` to each of its source
+    /// ranges.
+    pub fn map_range<'out, T>(
+        &self,
+        scope: &'out Scope<'out>,
+        f: &impl Fn(Range) -> T,
+    ) -> Term<'out, T> {
+        let term = |term: &Term<'_, Range>| scope.to_scope(term.map_range(scope, f)) as &_;
+        let terms = |terms: &[Term<'_, Range>]| {
+            scope.to_scope_from_iter(terms.iter().map(|term| term.map_range(scope, f))) as &_
+        };
+
+        match self {
+            Term::Paren(range, expr) => Term::Paren(f(range.clone()), term(expr)),
+            Term::Name(range, name) => Term::Name(f(range.clone()), *name),
+            Term::Hole(range, name) => Term::Hole(f(range.clone()), *name),
+            Term::Placeholder(range) => Term::Placeholder(f(range.clone())),
+            Term::Ann(range, expr, r#type) => Term::Ann(f(range.clone()), term(expr), term(r#type)),
+            Term::Let(range, def_pattern, def_type, def_expr, body_expr) => Term::Let(
+                f(range.clone()),
+                def_pattern.map_range(f),
+                def_type.map(term),
+                term(def_expr),
+                term(body_expr),
+            ),
+            Term::If(range, cond_expr, then_expr, else_expr) => Term::If(
+                f(range.clone()),
+                term(cond_expr),
+                term(then_expr),
+                term(else_expr),
+            ),
+            Term::Match(range, scrutinee, equations) => Term::Match(
+                f(range.clone()),
+                term(scrutinee),
+                scope.to_scope_from_iter(equations.iter().map(|(pattern, guard, body_expr)| {
+                    (
+                        pattern.map_range(f),
+                        guard.as_ref().map(|guard| guard.map_range(scope, f)),
+                        body_expr.map_range(scope, f),
+                    )
+                })),
+            ),
+            Term::Universe(range) => Term::Universe(f(range.clone())),
+            Term::Arrow(range, plicity, param_type, body_type) => Term::Arrow(
+                f(range.clone()),
+                *plicity,
+                term(param_type),
+                term(body_type),
+            ),
+            Term::FunType(range, params, body_type) => Term::FunType(
+                f(range.clone()),
+                map_params(scope, params, f),
+                term(body_type),
+            ),
+            Term::FunLiteral(range, params, body_expr) => Term::FunLiteral(
+                f(range.clone()),
+                map_params(scope, params, f),
+                term(body_expr),
+            ),
+            Term::App(range, head_expr, args) => Term::App(
+                f(range.clone()),
+                term(head_expr),
+                scope.to_scope_from_iter(args.iter().map(|arg| Arg {
+                    plicity: arg.plicity,
+                    term: arg.term.map_range(scope, f),
+                })),
+            ),
+            Term::RecordType(range, type_fields) => Term::RecordType(
+                f(range.clone()),
+                scope.to_scope_from_iter(type_fields.iter().map(|field| TypeField {
+                    label: map_label(&field.label, f),
+                    r#type: field.r#type.map_range(scope, f),
+                })),
+            ),
+            Term::RecordLiteral(range, expr_fields) => Term::RecordLiteral(
+                f(range.clone()),
+                scope.to_scope_from_iter(expr_fields.iter().map(|field| ExprField {
+                    label: map_label(&field.label, f),
+                    expr: field.expr.map_range(scope, f),
+                })),
+            ),
+            Term::Tuple(range, exprs) => Term::Tuple(f(range.clone()), terms(exprs)),
+            Term::Proj(range, head_expr, labels) => Term::Proj(
+                f(range.clone()),
+                term(head_expr),
+                scope.to_scope_from_iter(labels.iter().map(|label| map_label(label, f))),
+            ),
+            Term::ArrayLiteral(range, exprs) => Term::ArrayLiteral(f(range.clone()), terms(exprs)),
+            Term::StringLiteral(range, lit) => Term::StringLiteral(f(range.clone()), *lit),
+            Term::CharLiteral(range, lit) => Term::CharLiteral(f(range.clone()), *lit),
+            Term::NumberLiteral(range, lit) => Term::NumberLiteral(f(range.clone()), *lit),
+            Term::BooleanLiteral(range, lit) => Term::BooleanLiteral(f(range.clone()), *lit),
+            Term::FormatRecord(range, format_fields) => {
+                Term::FormatRecord(f(range.clone()), map_format_fields(scope, format_fields, f))
+            }
+            Term::FormatOverlap(range, format_fields) => {
+                Term::FormatOverlap(f(range.clone()), map_format_fields(scope, format_fields, f))
+            }
+            Term::FormatDo(range, format_fields, expr) => Term::FormatDo(
+                f(range.clone()),
+                map_format_fields(scope, format_fields, f),
+                term(expr),
+            ),
+            Term::FormatCond(range, label, format, cond) => Term::FormatCond(
+                f(range.clone()),
+                map_label(label, f),
+                term(format),
+                term(cond),
+            ),
+            Term::BinOp(range, lhs, op, rhs) => Term::BinOp(
+                f(range.clone()),
+                term(lhs),
+                op.clone().map_range(f),
+                term(rhs),
+            ),
+            Term::ReportedError(range) => Term::ReportedError(f(range.clone())),
+        }
+    }
+}
+
+fn map_label<Range: Clone, T>(
+    (range, label): &(Range, StringId),
+    f: &impl Fn(Range) -> T,
+) -> (T, StringId) {
+    (f(range.clone()), *label)
+}
+
+fn map_params<'out, Range: Clone, T>(
+    scope: &'out Scope<'out>,
+    params: &[Param<'_, Range>],
+    f: &impl Fn(Range) -> T,
+) -> &'out [Param<'out, T>] {
+    scope.to_scope_from_iter(params.iter().map(|param| {
+        Param {
+            plicity: param.plicity,
+            pattern: param.pattern.map_range(f),
+            r#type: param
+                .r#type
+                .as_ref()
+                .map(|r#type| r#type.map_range(scope, f)),
+        }
+    }))
+}
+
+fn map_format_fields<'out, Range: Clone, T>(
+    scope: &'out Scope<'out>,
+    format_fields: &[FormatField<'_, Range>],
+    f: &impl Fn(Range) -> T,
+) -> &'out [FormatField<'out, T>] {
+    scope.to_scope_from_iter(format_fields.iter().map(|field| match field {
+        FormatField::Format {
+            label,
+            format,
+            pred,
+        } => FormatField::Format {
+            label: map_label(label, f),
+            format: format.map_range(scope, f),
+            pred: pred.as_ref().map(|pred| pred.map_range(scope, f)),
+        },
+        FormatField::Computed {
+            label,
+            r#type,
+            expr,
+        } => FormatField::Computed {
+            label: map_label(label, f),
+            r#type: r#type.as_ref().map(|r#type| r#type.map_range(scope, f)),
+            expr: expr.map_range(scope, f),
+        },
+    }))
+}
+
+/// Move a module to the file identified by `file_id`, preserving the byte
+/// offsets of its source ranges. This is useful when splicing the items of one
+/// module into another, so that diagnostics point at the right file.
+pub fn reroot<'out>(
+    scope: &'out Scope<'out>,
+    module: &Module<'_, FileRange>,
+    file_id: FileId,
+) -> Module<'out, FileRange> {
+    module.map_range(scope, &|range: FileRange| {
+        FileRange::new(file_id, range.byte_range())
+    })
+}
+
+/// Check that the parentheses and brackets in the lexed tokens are balanced,
+/// returning a message for each closing delimiter without a matching opening
+/// delimiter, and for the outermost delimiter left unclosed at the end of the
+/// file. This lets us report these mistakes more clearly than the parser can.
+fn check_delimiters(
+    tokens: &[Result<lexer::Spanned<lexer::Token<'_>, BytePos>, lexer::Error>],
+) -> Vec<ParseMessage> {
+    use lexer::Token;
+
+    let mut messages = Vec::new();
+    let mut open_delimiters = Vec::new();
+
+    for (start, token, end) in tokens.iter().flatten() {
+        let range = ByteRange::new(*start, *end);
+        match token {
+            Token::OpenParen => open_delimiters.push((range, "(", ")")),
+            Token::OpenBracket => open_delimiters.push((range, "[", "]")),
+            Token::CloseParen | Token::CloseBracket => {
+                let delimiter = token.description();
+                match open_delimiters.last() {
+                    Some((_, _, close)) if *close == delimiter => {
+                        open_delimiters.pop();
+                    }
+                    _ => messages.push(ParseMessage::UnmatchedDelimiter { range, delimiter }),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((range, delimiter, _)) = open_delimiters.first().copied() {
+        messages.push(ParseMessage::UnclosedDelimiter { range, delimiter });
+    }
+
+    messages
+}
+
+/// Combine the messages from the parser with those from [`check_delimiters`],
+/// dropping parser errors that only restate an unbalanced delimiter.
+fn merge_delimiter_messages(
+    messages: Vec<ParseMessage>,
+    delimiter_messages: Vec<ParseMessage>,
+) -> Vec<ParseMessage> {
+    let is_unmatched_delimiter = |range: ByteRange| {
+        delimiter_messages.iter().any(|message| match message {
+            ParseMessage::UnmatchedDelimiter { range: r, .. } => {
+                (r.start(), r.end()) == (range.start(), range.end())
+            }
+            _ => false,
+        })
+    };
+    let has_unclosed_delimiter = (delimiter_messages.iter())
+        .any(|message| matches!(message, ParseMessage::UnclosedDelimiter { .. }));
+
+    let mut messages = (messages.into_iter())
+        .filter(|message| match message {
+            ParseMessage::InvalidToken { range }
+            | ParseMessage::UnrecognizedToken { range, .. }
+            | ParseMessage::ExtraToken { range, .. } => !is_unmatched_delimiter(*range),
+            ParseMessage::UnrecognizedEof { .. } => !has_unclosed_delimiter,
+            _ => true,
+        })
+        .collect::<Vec<_>>();
+    messages.extend(delimiter_messages);
+    messages
+}
+
 /// Messages produced during parsing
 #[derive(Clone, Debug)]
 pub enum ParseMessage {
@@ -423,6 +753,33 @@ pub enum ParseMessage {
         range: ByteRange,
         token: &'static str,
     },
+    UnknownAttribute {
+        range: ByteRange,
+        attr: String,
+    },
+    NonNumericAttributeTarget {
+        range: ByteRange,
+        name: String,
+    },
+    InapplicableAttribute {
+        range: ByteRange,
+        attr: &'static str,
+        name: String,
+    },
+    ConflictingAttributes {
+        range: ByteRange,
+        previous_range: ByteRange,
+        attr: &'static str,
+        previous_attr: &'static str,
+    },
+    UnmatchedDelimiter {
+        range: ByteRange,
+        delimiter: &'static str,
+    },
+    UnclosedDelimiter {
+        range: ByteRange,
+        delimiter: &'static str,
+    },
 }
 
 impl ParseMessage {
@@ -432,7 +789,13 @@ impl ParseMessage {
             ParseMessage::InvalidToken { range }
             | ParseMessage::UnrecognizedEof { range, .. }
             | ParseMessage::UnrecognizedToken { range, .. }
-            | ParseMessage::ExtraToken { range, .. } => *range,
+            | ParseMessage::ExtraToken { range, .. }
+            | ParseMessage::UnknownAttribute { range, .. }
+            | ParseMessage::NonNumericAttributeTarget { range, .. }
+            | ParseMessage::InapplicableAttribute { range, .. }
+            | ParseMessage::ConflictingAttributes { range, .. }
+            | ParseMessage::UnmatchedDelimiter { range, .. }
+            | ParseMessage::UnclosedDelimiter { range, .. } => *range,
         }
     }
 
@@ -495,10 +858,145 @@ impl ParseMessage {
             ParseMessage::ExtraToken { range, token } => Diagnostic::error()
                 .with_message(format!("extra token {token}"))
                 .with_labels(vec![primary_label(range).with_message("extra token")]),
+            ParseMessage::UnknownAttribute { range, attr } => Diagnostic::error()
+                .with_message(format!("unknown attribute `{attr}`"))
+                .with_labels(vec![primary_label(range).with_message("unknown attribute")])
+                .with_notes(vec![
+                    "expected one of `be`, `le`, `signed`, or `unsigned`".to_owned()
+                ]),
+            ParseMessage::NonNumericAttributeTarget { range, name } => Diagnostic::error()
+                .with_message(format!("attributes cannot be applied to `{name}`"))
+                .with_labels(vec![
+                    primary_label(range).with_message("not a number format")
+                ])
+                .with_notes(vec![
+                    "attributes can only be applied to `u8`, `u16`, `u32`, `u64`, `s8`, `s16`, \
+                     `s32`, `s64`, `f32`, or `f64`"
+                        .to_owned(),
+                ]),
+            ParseMessage::InapplicableAttribute { range, attr, name } => Diagnostic::error()
+                .with_message(format!("attribute `{attr}` cannot be applied to `{name}`"))
+                .with_labels(vec![
+                    primary_label(range).with_message("inapplicable attribute")
+                ]),
+            ParseMessage::ConflictingAttributes {
+                range,
+                previous_range,
+                attr,
+                previous_attr,
+            } => {
+                let message = match attr == previous_attr {
+                    true => format!("duplicate attribute `{attr}`"),
+                    false => format!("conflicting attributes `{previous_attr}` and `{attr}`"),
+                };
+                Diagnostic::error().with_message(message).with_labels(vec![
+                    primary_label(range).with_message("conflicting attribute"),
+                    Label::secondary(file_id, *previous_range).with_message("previous attribute"),
+                ])
+            }
+            ParseMessage::UnmatchedDelimiter { range, delimiter } => Diagnostic::error()
+                .with_message(format!("unbalanced delimiter `{delimiter}`"))
+                .with_labels(vec![
+                    primary_label(range).with_message("no matching opening delimiter")
+                ]),
+            ParseMessage::UnclosedDelimiter { range, delimiter } => Diagnostic::error()
+                .with_message(format!("unbalanced delimiter `{delimiter}`"))
+                .with_labels(vec![primary_label(range).with_message("unclosed delimiter")])
+                .with_notes(vec![
+                    "reached the end of the file before the delimiter was closed".to_owned(),
+                ]),
         }
     }
 }
 
+/// Apply byte order and signedness attributes to the name of a numeric format,
+/// for example `u16 #[le]` to `u16le`, or `u32 #[signed, be]` to `s32be`.
+///
+/// This is done while parsing so that later passes, like the ordering of
+/// items, only ever see the concrete name. The name is left unchanged if the
+/// attributes could not be applied.
+fn apply_attributes(
+    interner: &RefCell<StringInterner>,
+    messages: &mut Vec<ParseMessage>,
+    (name_range, name): (ByteRange, StringId),
+    attrs: &[(ByteRange, StringId)],
+) -> StringId {
+    let mut interner = interner.borrow_mut();
+    let name_str = interner.resolve(name).unwrap().to_owned();
+
+    let (kind, width) = match name_str.as_str() {
+        name @ ("u8" | "u16" | "u32" | "u64" | "s8" | "s16" | "s32" | "s64" | "f32" | "f64") => {
+            name.split_at(1)
+        }
+        _ => {
+            messages.push(ParseMessage::NonNumericAttributeTarget {
+                range: name_range,
+                name: name_str.clone(),
+            });
+            return name;
+        }
+    };
+
+    let mut byte_order = None;
+    let mut signedness = None;
+    let mut is_valid = true;
+
+    for (range, attr) in attrs {
+        let (slot, attr) = match interner.resolve(*attr).unwrap() {
+            "be" => (&mut byte_order, "be"),
+            "le" => (&mut byte_order, "le"),
+            "signed" => (&mut signedness, "signed"),
+            "unsigned" => (&mut signedness, "unsigned"),
+            attr => {
+                messages.push(ParseMessage::UnknownAttribute {
+                    range: *range,
+                    attr: attr.to_owned(),
+                });
+                is_valid = false;
+                continue;
+            }
+        };
+
+        let is_applicable = match attr {
+            "be" | "le" => width != "8",
+            _ => kind != "f",
+        };
+        if !is_applicable {
+            messages.push(ParseMessage::InapplicableAttribute {
+                range: *range,
+                attr,
+                name: name_str.clone(),
+            });
+            is_valid = false;
+        }
+
+        match *slot {
+            Some((previous_range, previous_attr)) => {
+                messages.push(ParseMessage::ConflictingAttributes {
+                    range: *range,
+                    previous_range,
+                    attr,
+                    previous_attr,
+                });
+                is_valid = false;
+            }
+            None => *slot = Some((*range, attr)),
+        }
+    }
+
+    if !is_valid {
+        return name;
+    }
+
+    let kind = match signedness {
+        Some((_, "signed")) => "s",
+        Some((_, _)) => "u",
+        None => kind,
+    };
+    let byte_order = byte_order.map_or("", |(_, byte_order)| byte_order);
+    interner.get_or_intern(format!("{kind}{width}{byte_order}"))
+}
+
 type LalrpopParseError<'source> =
     lalrpop_util::ParseError<BytePos, lexer::Token<'source>, lexer::Error>;
 
@@ -539,4 +1037,40 @@ mod tests {
         assert_eq!(std::mem::size_of::<Pattern<()>>(), 8);
         assert_eq!(std::mem::size_of::<Pattern<ByteRange>>(), 16);
     }
+
+    #[test]
+    fn reroot_module() {
+        let interner = RefCell::new(StringInterner::new());
+        let scope = Scope::new();
+        let source = ProgramSource::try_from(String::from(
+            "def header = { len <- u8, data <- repeat_len8 len u8 where len > 0 };\n\
+             def len : U8 -> Format = fun (n : U8) => match n { 0 => u8, _ => u16be };\n",
+        ))
+        .unwrap();
+
+        let (module, messages) = Module::parse(&interner, &scope, &source);
+        assert!(messages.is_empty());
+
+        let (file_id, new_file_id) = (FileId::try_from(1).unwrap(), FileId::try_from(2).unwrap());
+        let module = module.map_range(&scope, &|range| FileRange::new(file_id, range));
+        let rerooted = reroot(&scope, &module, new_file_id);
+
+        let ranges = RefCell::new(Vec::new());
+        module.map_range(&scope, &|range: FileRange| ranges.borrow_mut().push(range));
+        let new_ranges = RefCell::new(Vec::new());
+        rerooted.map_range(&scope, &|range: FileRange| {
+            new_ranges.borrow_mut().push(range)
+        });
+
+        let (ranges, new_ranges) = (ranges.into_inner(), new_ranges.into_inner());
+        assert!(!ranges.is_empty());
+        assert_eq!(ranges.len(), new_ranges.len());
+        for (range, new_range) in Iterator::zip(ranges.iter(), new_ranges.iter()) {
+            assert_eq!(new_range.file_id(), new_file_id);
+            assert_eq!(
+                (new_range.start(), new_range.end()),
+                (range.start(), range.end())
+            );
+        }
+    }
 }
@@ -159,7 +159,9 @@ impl<'interner, 'arena, 'env> Context<'interner, 'arena, 'env> {
     }
 
     fn check_number_literal<T: std::fmt::Display>(&mut self, number: T) -> Term<'arena, ()> {
-        let number = self.interner.borrow_mut().get_or_intern(number.to_string());
+        // Not-a-number is displayed as `NaN`, but written as `nan` in literals
+        let number = number.to_string().replace("NaN", "nan");
+        let number = self.interner.borrow_mut().get_or_intern(number);
         Term::NumberLiteral((), number)
     }
 
@@ -405,7 +407,7 @@ impl<'interner, 'arena, 'env> Context<'interner, 'arena, 'env> {
                             let default_expr = self.check_prec(Prec::Top, default_expr);
                             self.pop_local();
 
-                            (name_to_pattern(name), default_expr)
+                            (name_to_pattern(name), None, default_expr)
                         };
 
                         Term::Match(
@@ -417,7 +419,7 @@ impl<'interner, 'arena, 'env> Context<'interner, 'arena, 'env> {
                                     .map(|(r#const, body_expr)| {
                                         let pattern = self.check_constant_pattern(r#const);
                                         let body_expr = self.check_prec(Prec::Top, body_expr);
-                                        (pattern, body_expr)
+                                        (pattern, None, body_expr)
                                     })
                                     .chain(std::iter::once(default_branch)),
                             ),
@@ -430,7 +432,7 @@ impl<'interner, 'arena, 'env> Context<'interner, 'arena, 'env> {
                             |(r#const, body_expr)| {
                                 let pattern = self.check_constant_pattern(r#const);
                                 let body_expr = self.check_prec(Prec::Top, body_expr);
-                                (pattern, body_expr)
+                                (pattern, None, body_expr)
                             },
                         )),
                     ),
@@ -618,6 +620,50 @@ impl<'interner, 'arena, 'env> Context<'interner, 'arena, 'env> {
                     args.push((plicity, arg_expr));
                 }
 
+                // Distill format mappings that bind their fields to do blocks
+                if let (core::Term::Prim(_, core::Prim::FormatMap), [(_, map_fn), (_, format), _]) =
+                    (head_expr, &args[..])
+                {
+                    if let Some((labels, formats, result_expr)) = match_format_do(format, map_fn) {
+                        let format_fields = self.synth_format_fields(labels, formats);
+
+                        let initial_local_len = self.local_len();
+                        self.push_local(None);
+                        for label in labels {
+                            self.push_local(Some(*label));
+                        }
+                        let result_expr = self.check_prec(Prec::Top, result_expr);
+                        self.truncate_local(initial_local_len);
+
+                        return Term::FormatDo((), format_fields, self.scope.to_scope(result_expr));
+                    }
+                }
+
+                // Distill nested sized arrays to multi-dimensional arrays
+                if let (
+                    core::Term::Prim(_, prim),
+                    [(Plicity::Explicit, elem), (Plicity::Explicit, dim)],
+                ) = (head_expr, &args[..])
+                {
+                    if let Some((dims, elem)) = match_array_dims(*prim, dim, elem) {
+                        let head_expr = self.scope.to_scope(self.synth_prim(*prim));
+                        let dims = self.scope.to_scope_from_iter(
+                            dims.into_iter().map(|dim| self.check_prec(Prec::Top, dim)),
+                        );
+                        let args = self.scope.to_scope_from_iter([
+                            Arg {
+                                plicity: Plicity::Explicit,
+                                term: Term::ArrayLiteral((), dims),
+                            },
+                            Arg {
+                                plicity: Plicity::Explicit,
+                                term: self.check_prec(Prec::Proj, elem),
+                            },
+                        ]);
+                        return self.paren(prec > Prec::App, Term::App((), head_expr, args));
+                    }
+                }
+
                 // Distill appropriate primitives to binary operator expressions
                 if let (core::Term::Prim(_, prim), [(_, rhs), (_, lhs)]) = (head_expr, &args[..]) {
                     if let Some(op) = prim_to_bin_op(prim) {
@@ -790,7 +836,7 @@ impl<'interner, 'arena, 'env> Context<'interner, 'arena, 'env> {
                             let default_expr = self.synth_prec(Prec::Top, default_expr);
                             self.pop_local();
 
-                            (name_to_pattern(name), default_expr)
+                            (name_to_pattern(name), None, default_expr)
                         };
 
                         Term::Match(
@@ -802,7 +848,7 @@ impl<'interner, 'arena, 'env> Context<'interner, 'arena, 'env> {
                                     .map(|(r#const, body_expr)| {
                                         let pattern = self.check_constant_pattern(r#const);
                                         let body_expr = self.synth_prec(Prec::Top, body_expr);
-                                        (pattern, body_expr)
+                                        (pattern, None, body_expr)
                                     })
                                     .chain(std::iter::once(default_branch)),
                             ),
@@ -815,7 +861,7 @@ impl<'interner, 'arena, 'env> Context<'interner, 'arena, 'env> {
                             |(r#const, body_expr)| {
                                 let pattern = self.check_constant_pattern(r#const);
                                 let body_expr = self.synth_prec(Prec::Top, body_expr);
-                                (pattern, body_expr)
+                                (pattern, None, body_expr)
                             },
                         )),
                     ),
@@ -903,6 +949,78 @@ fn match_if_then_else<'arena>(
     }
 }
 
+/// Match the format and the mapping function of a `map` application that was
+/// elaborated from a do block, returning the fields of the record format and
+/// the result expression.
+fn match_format_do<'arena>(
+    format: &'arena core::Term<'arena>,
+    map_fn: &'arena core::Term<'arena>,
+) -> Option<(
+    &'arena [StringId],
+    &'arena [core::Term<'arena>],
+    &'arena core::Term<'arena>,
+)> {
+    let (labels, formats) = match format {
+        core::Term::FormatRecord(_, labels, formats) => (*labels, *formats),
+        _ => return None,
+    };
+    let mut body_expr = match map_fn {
+        core::Term::FunLit(_, Plicity::Explicit, _, body_expr) => *body_expr,
+        _ => return None,
+    };
+
+    for (label, record_var) in Iterator::zip(labels.iter(), env::indices()) {
+        match body_expr {
+            core::Term::Let(
+                _,
+                Some(name),
+                _,
+                core::Term::RecordProj(_, core::Term::LocalVar(_, var), proj_label),
+                next_body_expr,
+            ) if name == label && proj_label == label && *var == record_var => {
+                body_expr = *next_body_expr;
+            }
+            _ => return None,
+        }
+    }
+
+    Some((labels, formats, body_expr))
+}
+
+/// Match nested applications of a sized array type or repetition format,
+/// returning the dimensions from the outermost inwards along with the element.
+/// At least two dimensions are required for a match.
+fn match_array_dims<'arena>(
+    prim: core::Prim,
+    dim: &'arena core::Term<'arena>,
+    mut elem: &'arena core::Term<'arena>,
+) -> Option<(Vec<&'arena core::Term<'arena>>, &'arena core::Term<'arena>)> {
+    use crate::core::Prim::*;
+
+    match prim {
+        Array8Type | Array16Type | Array32Type | Array64Type | FormatRepeatLen8
+        | FormatRepeatLen16 | FormatRepeatLen32 | FormatRepeatLen64 => {}
+        _ => return None,
+    }
+
+    let mut dims = vec![dim];
+    while let core::Term::FunApp(
+        _,
+        Plicity::Explicit,
+        core::Term::FunApp(_, Plicity::Explicit, core::Term::Prim(_, elem_prim), dim),
+        next_elem,
+    ) = elem
+    {
+        if *elem_prim != prim {
+            break;
+        }
+        dims.push(*dim);
+        elem = *next_elem;
+    }
+
+    (dims.len() > 1).then_some((dims, elem))
+}
+
 /// Returns true if `labels` is a sequence of tuple labels (`_0`, `_1`, ...),
 /// and a telescope of `types` contains independent entries.
 fn is_tuple_type(
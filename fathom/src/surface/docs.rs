@@ -0,0 +1,153 @@
+//! Documentation comments on the items of a module.
+//!
+//! Documentation comments are skipped by the lexer, so they are recovered from
+//! the source of a module using the ranges of its items and fields.
+
+use std::cell::RefCell;
+
+use scoped_arena::Scope;
+
+use crate::source::{BytePos, ByteRange, StringId, StringInterner};
+use crate::surface::{pretty, FormatField, Item, Module, Term};
+
+/// The documentation of a top-level item.
+#[derive(Debug, Clone)]
+pub struct ItemDoc {
+    pub label: StringId,
+    /// The documentation comment preceding the item, with the `///` markers
+    /// removed. This is empty if the item is undocumented.
+    pub doc: String,
+    /// The fields of the item, if it is defined as a record format.
+    pub fields: Vec<FieldDoc>,
+}
+
+/// The documentation of a field of a record format.
+#[derive(Debug, Clone)]
+pub struct FieldDoc {
+    pub label: StringId,
+    /// The documentation comment preceding the field, with the `///` markers
+    /// removed. This is empty if the field is undocumented.
+    pub doc: String,
+    /// The format of the field, or the type annotation of a computed field.
+    pub format: Option<String>,
+}
+
+/// Collect the documentation of the items in a module, in the order that they
+/// appear in the source.
+pub fn module_docs(
+    interner: &RefCell<StringInterner>,
+    source: &str,
+    module: &Module<'_, ByteRange>,
+) -> Vec<ItemDoc> {
+    let scope = Scope::new();
+    let pretty_context = pretty::Context::new(interner, &scope);
+    let render = |term: &Term<'_, ByteRange>| {
+        let doc = pretty_context.term(term).into_doc();
+        doc.pretty(usize::MAX).to_string()
+    };
+
+    let mut item_docs = Vec::with_capacity(module.items.len());
+    for item in module.items {
+        let item = match item {
+            Item::Def(item) => item,
+            Item::ReportedError(_) => continue,
+        };
+
+        let fields = match item.expr {
+            Term::FormatRecord(_, format_fields) => (format_fields.iter())
+                .map(|format_field| match format_field {
+                    FormatField::Format { label, format, .. } => FieldDoc {
+                        label: label.1,
+                        doc: doc_comment(source, label.0.start()),
+                        format: Some(render(format)),
+                    },
+                    FormatField::Computed { label, r#type, .. } => FieldDoc {
+                        label: label.1,
+                        doc: doc_comment(source, label.0.start()),
+                        format: r#type.as_ref().map(render),
+                    },
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        item_docs.push(ItemDoc {
+            label: item.label.1,
+            doc: doc_comment(source, item.range.start()),
+            fields,
+        });
+    }
+
+    item_docs
+}
+
+/// Find the documentation comment on the lines directly preceding `pos`.
+fn doc_comment(source: &str, pos: BytePos) -> String {
+    let before = &source[..pos as usize];
+    let mut lines = before.lines().rev();
+
+    // Skip any indentation on the line that `pos` is on
+    if !before.ends_with('\n') {
+        lines.next();
+    }
+
+    let mut doc_lines = (lines.map(str::trim_start))
+        .map_while(|line| line.strip_prefix("///"))
+        .map(|line| line.strip_prefix(' ').unwrap_or(line))
+        .collect::<Vec<_>>();
+    doc_lines.reverse();
+    doc_lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::ProgramSource;
+
+    #[test]
+    fn record_field_docs() {
+        let interner = RefCell::new(StringInterner::new());
+        let scope = Scope::new();
+        let source = ProgramSource::try_from(String::from(
+            "/// A table header.\n\
+             ///\n\
+             /// Appears at the start of each table.\n\
+             def header = {\n    \
+                 /// The version of the table\n    \
+                 version <- u16be,\n    \
+                 count <- u16be,\n    \
+                 /// Twice the count\n    \
+                 let double : U16 = count + count,\n\
+             };\n\
+             \n\
+             def undocumented = u8;\n",
+        ))
+        .unwrap();
+
+        let (module, messages) = Module::parse(&interner, &scope, &source);
+        assert!(messages.is_empty());
+
+        let docs = module_docs(&interner, &source, &module);
+        let resolve = |name| interner.borrow().resolve(name).unwrap().to_owned();
+
+        assert_eq!(docs.len(), 2);
+        assert_eq!(resolve(docs[0].label), "header");
+        assert_eq!(
+            docs[0].doc,
+            "A table header.\n\nAppears at the start of each table."
+        );
+
+        let fields = &docs[0].fields;
+        let labels = fields.iter().map(|field| resolve(field.label));
+        assert_eq!(labels.collect::<Vec<_>>(), ["version", "count", "double"]);
+        assert_eq!(fields[0].doc, "The version of the table");
+        assert_eq!(fields[0].format.as_deref(), Some("u16be"));
+        assert_eq!(fields[1].doc, "");
+        assert_eq!(fields[2].doc, "Twice the count");
+        assert_eq!(fields[2].format.as_deref(), Some("U16"));
+
+        assert_eq!(resolve(docs[1].label), "undocumented");
+        assert_eq!(docs[1].doc, "");
+        assert!(docs[1].fields.is_empty());
+    }
+}
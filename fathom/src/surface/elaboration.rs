@@ -21,21 +21,25 @@
 //! - [elaboration-zoo](https://github.com/AndrasKovacs/elaboration-zoo/)
 
 use std::cell::RefCell;
-use std::str::FromStr;
+use std::hash::{Hash, Hasher};
+use std::iter::Peekable;
+use std::str::{CharIndices, FromStr};
 use std::sync::Arc;
 
+use fxhash::{FxHashMap, FxHashSet, FxHasher};
 use scoped_arena::Scope;
 
 use super::ExprField;
 use crate::alloc::SliceVec;
-use crate::core::semantics::{self, ArcValue, Head, Telescope, Value};
-use crate::core::{self, prim, Const, Plicity, Prim, UIntStyle};
-use crate::env::{self, EnvLen, Level, SharedEnv, UniqueEnv};
+use crate::core::fold::{self, Folder};
+use crate::core::semantics::{self, ArcValue, Elim, Head, Telescope, Value};
+use crate::core::{self, deps, layout, prim, Const, Plicity, Prim, UIntStyle};
+use crate::env::{self, EnvLen, Level, SharedEnv, SliceEnv, UniqueEnv};
 use crate::files::FileId;
 use crate::source::{BytePos, ByteRange, FileRange, Span, Spanned, StringId, StringInterner};
 use crate::surface::elaboration::reporting::Message;
 use crate::surface::{
-    distillation, pretty, BinOp, FormatField, Item, Module, Param, Pattern, Term,
+    distillation, pretty, BinOp, FormatField, Item, ItemDef, Module, Param, Pattern, Term,
 };
 
 mod order;
@@ -50,6 +54,9 @@ pub struct ItemEnv<'arena> {
     types: UniqueEnv<ArcValue<'arena>>,
     /// Expressions of items.
     exprs: UniqueEnv<ArcValue<'arena>>,
+    /// Items provided by [`ItemEnv::define`], which are included at the start
+    /// of elaborated modules.
+    defined_items: Vec<core::Item<'arena>>,
 }
 
 impl<'arena> ItemEnv<'arena> {
@@ -59,9 +66,45 @@ impl<'arena> ItemEnv<'arena> {
             names: UniqueEnv::new(),
             types: UniqueEnv::new(),
             exprs: UniqueEnv::new(),
+            defined_items: Vec::new(),
         }
     }
 
+    /// Define an item that can be referred to by name in the modules and
+    /// terms that are elaborated with this environment. This can be used to
+    /// provide domain-specific formats to embedders, in addition to the
+    /// primitives.
+    ///
+    /// The type and expression are evaluated in an environment containing the
+    /// items that were defined before this one, and must be defined before
+    /// any modules are elaborated. The defined items are included at the start
+    /// of elaborated modules, so that adding a module to a binary context also
+    /// adds them. When only elaborating terms or formats, they should instead
+    /// be added to the binary context with [`core::binary::Context::add_module`].
+    ///
+    /// The expression is not checked against the type. This can be done
+    /// with [`Context::defined_items_are_well_typed`], for example in the
+    /// tests of the embedder.
+    pub fn define(
+        &mut self,
+        name: StringId,
+        r#type: &'arena core::Term<'arena>,
+        expr: &'arena core::Term<'arena>,
+    ) {
+        let meta_exprs = UniqueEnv::new();
+        let mut local_exprs = SharedEnv::new();
+        let elim_env = semantics::ElimEnv::new(&self.exprs, &meta_exprs);
+        let type_value = elim_env.eval_env(&mut local_exprs).eval(r#type);
+        let expr_value = elim_env.eval_env(&mut local_exprs).eval(expr);
+
+        self.push_definition(name, type_value, expr_value);
+        self.defined_items.push(core::Item::Def {
+            label: name,
+            r#type,
+            expr,
+        });
+    }
+
     fn push_definition(
         &mut self,
         name: StringId,
@@ -80,6 +123,113 @@ impl<'arena> ItemEnv<'arena> {
     }
 }
 
+/// Elaborated items of a module, which are reused by [`Context::reelaborate`]
+/// when the module is elaborated again after an edit.
+///
+/// Items are keyed by a hash of their surface syntax, with source ranges made
+/// relative to the start of the item, along with the names of the items they
+/// depend on. An item is only reused if all of its dependencies were reused
+/// as well, so a change to an item causes all of its dependents to be
+/// elaborated again. Items that were elaborated with errors, holes or unsolved
+/// metavariables are never cached.
+pub struct ItemCache<'arena> {
+    /// Labels of the items in the last elaborated module, indexed by level.
+    labels: Vec<StringId>,
+    /// Cached items, along with the ranges they were elaborated from.
+    items: FxHashMap<u64, (ByteRange, core::Item<'arena>)>,
+    /// Labels of the items that were not reused in the last elaboration.
+    recomputed: Vec<StringId>,
+}
+
+impl<'arena> ItemCache<'arena> {
+    /// Construct a new, empty cache.
+    pub fn new() -> ItemCache<'arena> {
+        ItemCache {
+            labels: Vec::new(),
+            items: FxHashMap::default(),
+            recomputed: Vec::new(),
+        }
+    }
+
+    /// The labels of the items that were elaborated from their surface syntax
+    /// the last time the cache was used, in elaboration order.
+    pub fn recomputed(&self) -> &[StringId] {
+        &self.recomputed
+    }
+}
+
+/// Compute the cache key of an item.
+fn item_key(item: &ItemDef<'_, ByteRange>, deps: &[StringId]) -> u64 {
+    let scope = Scope::new();
+    let start = item.range.start();
+    let item = item.map_range(&scope, &|range| {
+        (
+            range.start().saturating_sub(start),
+            range.end().saturating_sub(start),
+        )
+    });
+
+    let mut hasher = FxHasher::default();
+    (item, deps).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Copies a cached core item into a new elaboration.
+struct ItemReuser<'a, 'arena> {
+    scope: &'arena Scope<'arena>,
+    file_id: FileId,
+    /// Names of the items in the current item environment.
+    item_names: &'a UniqueEnv<StringId>,
+    /// Labels of the items in the elaboration that the item was cached from.
+    cached_labels: &'a [StringId],
+    /// The range that the item was elaborated from.
+    cached_range: ByteRange,
+    /// The current range of the item.
+    range: ByteRange,
+    /// Set if the item refers to an item that is not in the environment.
+    is_unbound: bool,
+}
+
+impl<'a, 'arena> Folder<'arena> for ItemReuser<'a, 'arena> {
+    fn scope(&self) -> &'arena Scope<'arena> {
+        self.scope
+    }
+
+    fn fold_term(&mut self, term: &core::Term<'_>) -> core::Term<'arena> {
+        match term {
+            core::Term::ItemVar(span, cached_var) => {
+                let cached_labels = <&SliceEnv<_>>::from(self.cached_labels);
+                let var = (cached_labels.get_level(*cached_var))
+                    .and_then(|label| self.item_names.elem_level(label));
+                let var = var.unwrap_or_else(|| {
+                    self.is_unbound = true;
+                    *cached_var
+                });
+                core::Term::ItemVar(self.fold_span(*span), var)
+            }
+            term => fold::walk_term(self, term),
+        }
+    }
+
+    fn fold_span(&mut self, span: Span) -> Span {
+        match span {
+            // Spans within the item are moved along with it. Other spans may
+            // have come from the solutions of metavariables, and could be out
+            // of date, so they are dropped.
+            Span::Range(range)
+                if range.file_id() == self.file_id
+                    && self.cached_range.start() <= range.start()
+                    && range.end() <= self.cached_range.end() =>
+            {
+                let start = self.range.start() + (range.start() - self.cached_range.start());
+                let end = self.range.start() + (range.end() - self.cached_range.start());
+                Span::Range(FileRange::new(self.file_id, ByteRange::new(start, end)))
+            }
+            Span::Range(_) | Span::Empty => Span::Empty,
+        }
+    }
+}
+
 /// Local variable environment.
 ///
 /// This is used for keeping track of [local variables] that are bound by the
@@ -191,6 +341,8 @@ pub enum MetaSource {
     NamedPatternType(FileRange, StringId),
     /// The overall type of a match expression
     MatchExprType(FileRange),
+    /// The type of the result of a sequence format
+    FormatDoResultType(FileRange),
     /// The type of a reported error.
     ReportedErrorType(FileRange),
 }
@@ -206,6 +358,7 @@ impl MetaSource {
             | MetaSource::PlaceholderPatternType(range)
             | MetaSource::NamedPatternType(range, _)
             | MetaSource::MatchExprType(range)
+            | MetaSource::FormatDoResultType(range)
             | MetaSource::ReportedErrorType(range) => *range,
         }
     }
@@ -282,20 +435,31 @@ pub struct Context<'interner, 'arena> {
     local_env: LocalEnv<'arena>,
     /// A partial renaming to be used during [`unification`].
     renaming: unification::PartialRenaming,
+    /// The number of reduction steps remaining for the current item or term.
+    fuel: semantics::Fuel,
     /// Diagnostic messages encountered during elaboration.
     messages: Vec<Message>,
 }
 
+/// Find the candidate that is most similar to a name, if any are similar
+/// enough to be worth suggesting.
 fn suggest_name(
     interner: &StringInterner,
     name: StringId,
     candidates: impl Iterator<Item = StringId>,
 ) -> Option<StringId> {
     let name = interner.resolve(name).unwrap();
-    candidates.min_by_key(|candidate| {
-        let candidate = interner.resolve(*candidate).unwrap();
-        levenshtein::levenshtein(name, candidate)
-    })
+    // Allow roughly one edit for every three characters in the name
+    let max_distance = usize::max(1, name.chars().count() / 3);
+
+    (candidates)
+        .map(|candidate| {
+            let distance = levenshtein::levenshtein(name, interner.resolve(candidate).unwrap());
+            (candidate, distance)
+        })
+        .filter(|(_, distance)| *distance <= max_distance)
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(candidate, _)| candidate)
 }
 
 impl<'interner, 'arena> Context<'interner, 'arena> {
@@ -320,10 +484,46 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
             meta_env: MetaEnv::new(),
             local_env: LocalEnv::new(),
             renaming: unification::PartialRenaming::new(),
+            fuel: semantics::Fuel::default(),
             messages: Vec::new(),
         }
     }
 
+    /// Check the expressions of the [defined items][ItemEnv::define] against
+    /// their types, by distilling and then re-elaborating them. The messages
+    /// and metavariables of the context are left unchanged.
+    pub fn defined_items_are_well_typed(&mut self) -> bool {
+        let scope = self.scope;
+        let range = ByteRange::new(0, 0);
+        let messages_len = self.messages.len();
+        let meta_env = std::mem::replace(&mut self.meta_env, MetaEnv::new());
+
+        for index in 0..self.item_env.defined_items.len() {
+            let core::Item::Def { r#type, expr, .. } = self.item_env.defined_items[index];
+            let surface_type = self.distillation_context(scope).check(r#type);
+            let surface_expr = self.distillation_context(scope).check(expr);
+
+            let r#type = self.check(
+                &surface_type.map_range(scope, &|()| range),
+                &self.universe.clone(),
+            );
+            let type_value = self.eval_env().eval(&r#type);
+            self.check(&surface_expr.map_range(scope, &|()| range), &type_value);
+        }
+        self.fuel.refill(semantics::Fuel::DEFAULT_STEPS);
+
+        let well_typed = self.messages.len() == messages_len;
+        self.messages.truncate(messages_len);
+        self.meta_env = meta_env;
+        well_typed
+    }
+
+    /// Restrict the primitives that can be referenced by name. References to
+    /// the removed primitives are reported as unbound names.
+    pub fn retain_prims(&mut self, f: impl FnMut(Prim) -> bool) {
+        self.prim_env.retain(f);
+    }
+
     pub fn finish(self) -> ItemEnv<'arena> {
         self.item_env
     }
@@ -348,6 +548,15 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         Some((local_var, local_type))
     }
 
+    /// Returns true if the name is `inf` or `nan`, and is not bound to
+    /// anything else, in which case it should be treated as a number literal.
+    fn is_float_literal_name(&self, name: StringId) -> bool {
+        matches!(self.interner.borrow().resolve(name), Some("inf" | "nan"))
+            && self.get_local_name(name).is_none()
+            && self.get_item_name(name).is_none()
+            && self.prim_env.get_name(name).is_none()
+    }
+
     /// Push an unsolved term onto the context, to be updated later during
     /// unification.
     fn push_unsolved_term(
@@ -417,11 +626,24 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
 
     pub fn eval_env(&mut self) -> semantics::EvalEnv<'arena, '_> {
         semantics::ElimEnv::new(&self.item_env.exprs, &self.meta_env.exprs)
+            .with_fuel(&self.fuel)
             .eval_env(&mut self.local_env.exprs)
     }
 
     pub fn elim_env(&self) -> semantics::ElimEnv<'arena, '_> {
-        semantics::ElimEnv::new(&self.item_env.exprs, &self.meta_env.exprs)
+        semantics::ElimEnv::new(&self.item_env.exprs, &self.meta_env.exprs).with_fuel(&self.fuel)
+    }
+
+    /// Report if evaluation ran out of fuel while elaborating the item or
+    /// term at `range`, refilling the fuel for the next one.
+    fn check_fuel(&mut self, range: ByteRange) {
+        if self.fuel.is_exhausted() {
+            self.push_message(Message::EvaluationLimit {
+                range: self.file_range(range),
+                steps: semantics::Fuel::DEFAULT_STEPS,
+            });
+        }
+        self.fuel.refill(semantics::Fuel::DEFAULT_STEPS);
     }
 
     pub fn quote_env(&self) -> semantics::QuoteEnv<'arena, '_> {
@@ -435,6 +657,7 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
             &self.item_env.exprs,
             self.local_env.len(),
             &mut self.meta_env.exprs,
+            &self.fuel,
         )
     }
 
@@ -500,6 +723,78 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         (labels.into(), filtered_fields)
     }
 
+    /// Resolve the characters of a string literal, replacing escape sequences
+    /// with the characters that they represent. Each character is returned
+    /// along with its range in the source.
+    fn string_literal_chars(
+        &mut self,
+        range: ByteRange,
+        string_id: StringId,
+    ) -> Option<Vec<(ByteRange, char)>> {
+        let interner = self.interner.borrow();
+        let source = interner.resolve(string_id).unwrap();
+        let mut source_chars = source.char_indices().peekable();
+        let mut chars = Vec::with_capacity(source.len());
+        let mut is_valid = true;
+
+        while let Some((offset, ch)) = source_chars.next() {
+            let ch = match ch {
+                '\\' => parse_escape(&mut source_chars),
+                ch => Some(ch),
+            };
+            let end_offset = source_chars
+                .peek()
+                .map_or(source.len(), |(offset, _)| *offset);
+            let ch_start = range.start() + 1 + offset as BytePos;
+            let ch_end = range.start() + 1 + end_offset as BytePos;
+            let ch_range = ByteRange::new(ch_start, ch_end);
+
+            match ch {
+                Some(ch) => chars.push((ch_range, ch)),
+                None => {
+                    self.push_message(Message::InvalidStringEscape {
+                        invalid_range: self.file_range(ch_range),
+                    });
+                    is_valid = false;
+                }
+            }
+        }
+
+        is_valid.then_some(chars)
+    }
+
+    /// Resolve the bytes of a string literal. Each character must either be
+    /// ASCII or a `\x` escape sequence. Each byte is returned along with its
+    /// range in the source.
+    fn string_literal_bytes(
+        &mut self,
+        range: ByteRange,
+        string_id: StringId,
+    ) -> Option<Vec<(ByteRange, u8)>> {
+        let chars = self.string_literal_chars(range, string_id)?;
+        let interner = self.interner.borrow();
+        let source = interner.resolve(string_id).unwrap();
+        let mut bytes = Vec::with_capacity(chars.len());
+        let mut is_valid = true;
+
+        for (ch_range, ch) in chars {
+            let offset = (ch_range.start() - range.start() - 1) as usize;
+            match u8::try_from(ch) {
+                Ok(byte) if ch.is_ascii() || source[offset..].starts_with("\\x") => {
+                    bytes.push((ch_range, byte));
+                }
+                _ => {
+                    self.push_message(Message::NonAsciiStringLiteral {
+                        invalid_range: self.file_range(ch_range),
+                    });
+                    is_valid = false;
+                }
+            }
+        }
+
+        is_valid.then_some(bytes)
+    }
+
     /// Parse a source string into number, assuming an ASCII encoding.
     fn parse_ascii<T>(
         &mut self,
@@ -510,31 +805,19 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
     where
         T: From<u8> + std::ops::Shl<Output = T> + std::ops::BitOr<Output = T>,
     {
-        // TODO: Parse escape codes
         // TODO: Alternate byte orders
         // TODO: Non-ASCII encodings
 
-        let interner = self.interner.borrow();
-        let source = interner.resolve(string_id).unwrap();
+        let bytes = self.string_literal_bytes(range, string_id)?;
         let mut num = Some(T::from(0));
         let mut count: u8 = 0;
 
-        for (offset, ch) in source.char_indices() {
-            if !ch.is_ascii() {
-                let ch_start = range.start() + 1 + offset as BytePos;
-                let ch_end = ch_start + ch.len_utf8() as BytePos;
-
-                self.push_message(Message::NonAsciiStringLiteral {
-                    invalid_range: self.file_range(ByteRange::new(ch_start, ch_end)),
-                });
-                num = None;
-            }
-
+        for (_, byte) in bytes {
             num = num.filter(|_| usize::from(count) < std::mem::size_of::<T>());
             num = num.map(|num| {
                 // Yikes this is a tad ugly. Setting the bytes in reverse order...
                 let offset = 8 * (std::mem::size_of::<T>() as u8 - (count + 1));
-                num | (T::from(ch as u8) << T::from(offset))
+                num | (T::from(byte) << T::from(offset))
             });
             count += 1;
         }
@@ -551,6 +834,57 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         num.map(|num| make(num, UIntStyle::Ascii))
     }
 
+    /// Parse a character literal into the number of its Unicode scalar value.
+    fn parse_char<T: TryFrom<u32>>(
+        &mut self,
+        range: ByteRange,
+        string_id: StringId,
+        make: fn(T, UIntStyle) -> Const,
+    ) -> Option<Const> {
+        let chars = self.string_literal_chars(range, string_id)?;
+        let ch = match chars.as_slice() {
+            [(_, ch)] => *ch,
+            _ => {
+                self.push_message(Message::InvalidCharLiteral {
+                    range: self.file_range(range),
+                    found_len: chars.len(),
+                });
+                return None;
+            }
+        };
+
+        match T::try_from(u32::from(ch)) {
+            Ok(num) => Some(make(num, UIntStyle::Ascii)),
+            Err(_) => {
+                self.push_message(Message::CharLiteralOutOfRange {
+                    range: self.file_range(range),
+                    type_size: std::mem::size_of::<T>(),
+                });
+                None
+            }
+        }
+    }
+
+    /// Check that a string literal can be used as an array of bytes with the
+    /// given length.
+    fn check_byte_string(&mut self, range: ByteRange, string_id: StringId, len: u64) -> bool {
+        let bytes = match self.string_literal_bytes(range, string_id) {
+            Some(bytes) => bytes,
+            None => return false,
+        };
+
+        if bytes.len() as u64 != len {
+            self.push_message(Message::MismatchedStringLiteralByteLength {
+                range: self.file_range(range),
+                expected_len: len as usize,
+                found_len: bytes.len(),
+            });
+            return false;
+        }
+
+        true
+    }
+
     /// Parse a source string into a number.
     fn parse_number<T: FromStr>(
         &mut self,
@@ -562,10 +896,14 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         T::Err: std::fmt::Display,
     {
         // TODO: Custom parsing and improved errors
-        match self.interner.borrow().resolve(string_id).unwrap().parse() {
+        let interner = self.interner.borrow();
+        let result = match strip_digit_separators(interner.resolve(string_id).unwrap()) {
+            Ok(digits) => digits.parse().map_err(|error: T::Err| error.to_string()),
+            Err(message) => Err(message.to_owned()),
+        };
+        match result {
             Ok(data) => Some(make(data)),
-            Err(error) => {
-                let message = error.to_string();
+            Err(message) => {
                 self.push_message(Message::InvalidNumericLiteral {
                     range: self.file_range(range),
                     message,
@@ -575,6 +913,57 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         }
     }
 
+    /// Parse a source string into a floating point number, warning if an
+    /// integer literal could not be represented exactly, and reporting an
+    /// error if a finite literal is too large for the type.
+    fn parse_float<T: FromStr>(
+        &mut self,
+        range: ByteRange,
+        string_id: StringId,
+        make: fn(T) -> Const,
+    ) -> Option<Const>
+    where
+        T::Err: std::fmt::Display,
+    {
+        let constant = self.parse_number(range, string_id, make)?;
+        let (value, rounded, type_name) = match constant {
+            Const::F32(value) => (f64::from(value), value.to_string(), "F32"),
+            Const::F64(value) => (value, value.to_string(), "F64"),
+            _ => return Some(constant),
+        };
+
+        let is_infinite_literal = self
+            .interner
+            .borrow()
+            .resolve(string_id)
+            .unwrap()
+            .ends_with("inf");
+        if value.is_infinite() && !is_infinite_literal {
+            self.push_message(Message::InvalidNumericLiteral {
+                range: self.file_range(range),
+                message: format!("number too large to fit in `{type_name}`"),
+            });
+            return None;
+        }
+
+        let integer = strip_digit_separators(self.interner.borrow().resolve(string_id).unwrap())
+            .ok()
+            .and_then(|digits| decimal_integer(&digits));
+        let is_inexact = match integer {
+            // Saturating casts never equal integers outside the range of `i128`
+            Some(integer) => value as i128 != integer,
+            None => false,
+        };
+        if is_inexact {
+            self.push_message(Message::InexactFloatLiteral {
+                range: self.file_range(range),
+                rounded,
+            });
+        }
+
+        Some(constant)
+    }
+
     /// Parse a source string into a number.
     fn parse_number_radix<T: FromStrRadix>(
         &mut self,
@@ -587,15 +976,20 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         let s = interner.resolve(string_id).unwrap();
         let (s, radix, style) = if let Some(s) = s.strip_prefix("0x") {
             (s, 16, UIntStyle::Hexadecimal)
+        } else if let Some(s) = s.strip_prefix("0o") {
+            (s, 8, UIntStyle::Octal)
         } else if let Some(s) = s.strip_prefix("0b") {
             (s, 2, UIntStyle::Binary)
         } else {
             (s, 10, UIntStyle::Decimal)
         };
-        match T::from_str_radix(s, radix) {
+        let result = match strip_digit_separators(s) {
+            Ok(digits) => T::from_str_radix(&digits, radix).map_err(|error| error.to_string()),
+            Err(message) => Err(message.to_owned()),
+        };
+        match result {
             Ok(data) => Some(make(data, style)),
-            Err(error) => {
-                let message = error.to_string();
+            Err(message) => {
                 self.push_message(Message::InvalidNumericLiteral {
                     range: self.file_range(range),
                     message,
@@ -661,6 +1055,21 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         }
     }
 
+    /// Report refinements of the field at `field_range` that can never hold.
+    fn check_refinement(
+        &mut self,
+        pred_range: ByteRange,
+        field_range: FileRange,
+        pred_expr: &core::Term<'_>,
+    ) {
+        if is_unsatisfiable_refinement(pred_expr) {
+            self.push_message(Message::UnsatisfiableRefinement {
+                range: self.file_range(pred_range),
+                field_range,
+            });
+        }
+    }
+
     /// Elaborate a module.
     pub fn elab_module<'out_arena>(
         &mut self,
@@ -668,17 +1077,82 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         surface_module: &Module<'_, ByteRange>,
         on_message: &mut dyn FnMut(Message),
     ) -> core::Module<'out_arena> {
-        let elab_order = order::elaboration_order(self, surface_module);
-        let mut items = Vec::with_capacity(surface_module.items.len());
+        self.elab_items(scope, surface_module, None, on_message)
+    }
+
+    /// Elaborate a module that may have been edited since it was previously
+    /// elaborated, reusing the core items in the `cache` for the items whose
+    /// surface syntax and dependencies are unchanged. The cache is then updated
+    /// with the items of the new module.
+    ///
+    /// The context should be constructed with the same item environment as the
+    /// one that was used to fill the cache.
+    pub fn reelaborate<'out_arena>(
+        &mut self,
+        scope: &'out_arena Scope<'out_arena>,
+        cache: &mut ItemCache<'out_arena>,
+        surface_module: &Module<'_, ByteRange>,
+        on_message: &mut dyn FnMut(Message),
+    ) -> core::Module<'out_arena> {
+        self.elab_items(scope, surface_module, Some(cache), on_message)
+    }
+
+    fn elab_items<'out_arena>(
+        &mut self,
+        scope: &'out_arena Scope<'out_arena>,
+        surface_module: &Module<'_, ByteRange>,
+        cache: Option<&mut ItemCache<'out_arena>>,
+        on_message: &mut dyn FnMut(Message),
+    ) -> core::Module<'out_arena> {
+        let (elab_order, item_deps) = order::elaboration_order(self, surface_module);
+        let defined_items = self.item_env.defined_items.iter().map(|item| match item {
+            core::Item::Def {
+                label,
+                r#type,
+                expr,
+            } => core::Item::Def {
+                label: *label,
+                r#type: *r#type,
+                expr: *expr,
+            },
+        });
+        let mut items = Vec::with_capacity(defined_items.len() + surface_module.items.len());
+        items.extend(defined_items);
         self.item_env.reserve(surface_module.items.len());
 
-        for item in elab_order.iter().copied().map(|i| &surface_module.items[i]) {
-            match item {
+        let mut elaborated = Vec::with_capacity(surface_module.items.len());
+        let mut reused = FxHashSet::default();
+        let mut recomputed = Vec::new();
+
+        for index in elab_order.iter().copied() {
+            match &surface_module.items[index] {
                 Item::Def(item) => {
-                    let (expr, r#type) =
-                        self.synth_fun_lit(item.range, item.params, item.expr, item.r#type);
+                    let deps = &item_deps[index];
+                    let key = cache.is_some().then(|| item_key(item, deps));
+                    let cached_item = match (&cache, key) {
+                        (Some(cache), Some(key)) if deps.iter().all(|dep| reused.contains(dep)) => {
+                            (cache.items.get(&key)).and_then(|(cached_range, cached_item)| {
+                                self.reuse_item(cache, *cached_range, cached_item, item.range)
+                            })
+                        }
+                        _ => None,
+                    };
+
+                    let messages_len = self.messages.len();
+                    let metas_len = self.meta_env.exprs.len();
+                    let (expr, r#type) = match cached_item {
+                        Some(cached_item) => {
+                            reused.insert(item.label.1);
+                            cached_item
+                        }
+                        None => {
+                            recomputed.push(item.label.1);
+                            self.synth_fun_lit(item.range, item.params, item.expr, item.r#type)
+                        }
+                    };
                     let expr_value = self.eval_env().eval(&expr);
                     let type_value = self.eval_env().eval(&r#type);
+                    self.check_fuel(item.range);
 
                     self.item_env
                         .push_definition(item.label.1, type_value, expr_value);
@@ -688,6 +1162,9 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                         r#type: self.scope.to_scope(r#type),
                         expr: self.scope.to_scope(expr),
                     });
+                    let cacheable = self.messages.len() == messages_len
+                        && self.are_metas_solved_since(metas_len);
+                    elaborated.push((key, item.range, cacheable));
                 }
                 Item::ReportedError(_) => {}
             }
@@ -712,6 +1189,30 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
             }
         }));
 
+        if let Some(cache) = cache {
+            let defined_items_len = self.item_env.defined_items.len();
+            let module_items = Iterator::zip(items[defined_items_len..].iter(), elaborated);
+
+            cache.labels = self.item_env.names.iter().copied().collect();
+            cache.items.clear();
+            for (item, (key, range, cacheable)) in module_items {
+                if let Some(key) = key.filter(|_| cacheable) {
+                    let core::Item::Def {
+                        label,
+                        r#type,
+                        expr,
+                    } = item;
+                    let item = core::Item::Def {
+                        label: *label,
+                        r#type: *r#type,
+                        expr: *expr,
+                    };
+                    cache.items.insert(key, (range, item));
+                }
+            }
+            cache.recomputed = recomputed;
+        }
+
         self.handle_messages(on_message);
 
         // TODO: Clear environments
@@ -720,6 +1221,114 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         core::Module { items }
     }
 
+    /// Returns `true` if the metavariables inserted since the metavariable
+    /// environment had the length `len` have all been solved, and none of them
+    /// were inserted for holes.
+    fn are_metas_solved_since(&self, len: EnvLen) -> bool {
+        let vars = std::iter::successors(Some(len.next_level()), |var| Some(var.next()));
+        let metas = vars.map_while(|var| {
+            let expr = self.meta_env.exprs.get_level(var)?;
+            let source = self.meta_env.sources.get_level(var)?;
+            Some((expr, source))
+        });
+
+        metas.all(|(expr, source)| match source {
+            MetaSource::HoleType(_, _) | MetaSource::HoleExpr(_, _) => false,
+            _ => expr.is_some(),
+        })
+    }
+
+    /// Copy an item from the `cache` that was elaborated from `cached_range`,
+    /// moving its spans to `range`, and updating its references to other items
+    /// to their levels in the current item environment.
+    fn reuse_item(
+        &self,
+        cache: &ItemCache<'_>,
+        cached_range: ByteRange,
+        item: &core::Item<'_>,
+        range: ByteRange,
+    ) -> Option<(core::Term<'arena>, core::Term<'arena>)> {
+        let core::Item::Def { r#type, expr, .. } = item;
+        let mut folder = ItemReuser {
+            scope: self.scope,
+            file_id: self.file_id,
+            item_names: &self.item_env.names,
+            cached_labels: &cache.labels,
+            cached_range,
+            range,
+            is_unbound: false,
+        };
+        let expr = folder.fold_term(expr);
+        let r#type = folder.fold_term(r#type);
+
+        (!folder.is_unbound).then_some((expr, r#type))
+    }
+
+    /// Report the items of an elaborated module that are used both as formats
+    /// and as types.
+    pub fn report_mixed_item_uses(
+        &self,
+        module: &core::Module<'_>,
+        on_message: &mut dyn FnMut(Message),
+    ) {
+        let ranges = |spans: Vec<Span>| -> Vec<FileRange> {
+            (spans.into_iter())
+                .filter_map(|span| match span {
+                    Span::Range(range) => Some(range),
+                    Span::Empty => None,
+                })
+                .collect()
+        };
+
+        for (item, uses) in Iterator::zip(module.items.iter(), deps::item_uses(module)) {
+            let core::Item::Def { label, .. } = item;
+            let format_ranges = ranges(uses.format_spans);
+            let type_ranges = ranges(uses.type_spans);
+
+            if !format_ranges.is_empty() && !type_ranges.is_empty() {
+                on_message(Message::MixedItemUses {
+                    label: *label,
+                    format_ranges,
+                    type_ranges,
+                });
+            }
+        }
+    }
+
+    /// Report the items of an elaborated module that are not referenced by any
+    /// other item. The `main` item is assumed to be used as the entrypoint of
+    /// the module.
+    pub fn report_unused_items(
+        &self,
+        module: &core::Module<'_>,
+        on_message: &mut dyn FnMut(Message),
+    ) {
+        let graph = deps::dependency_graph(module);
+        let mut used = vec![false; graph.len()];
+        for item in 0..graph.len() {
+            for dep in graph.dependencies(item) {
+                // Self references do not count as uses
+                if *dep != item {
+                    used[*dep] = true;
+                }
+            }
+        }
+
+        let main = self.interner.borrow_mut().get_or_intern("main");
+        for (item, used) in Iterator::zip(module.items.iter(), used) {
+            let core::Item::Def { label, expr, .. } = item;
+            match expr.span() {
+                Span::Range(range) if !used && *label != main => {
+                    on_message(Message::UnusedItem {
+                        range,
+                        label: *label,
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
     /// Elaborate a term, returning its synthesized type.
     pub fn elab_term<'out_arena>(
         &mut self,
@@ -730,6 +1339,7 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         let (term, r#type) = self.synth(surface_term);
         let term = self.eval_env().unfold_metas(scope, &term);
         let r#type = self.quote_env().unfolding_metas().quote(scope, &r#type);
+        self.check_fuel(surface_term.range());
 
         self.handle_messages(on_message);
 
@@ -739,6 +1349,23 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         (term, r#type)
     }
 
+    /// Normalize an elaborated term, reporting if evaluation ran out of fuel
+    /// while normalizing the term at `range`.
+    pub fn normalize_term<'out_arena>(
+        &mut self,
+        scope: &'out_arena Scope<'out_arena>,
+        range: ByteRange,
+        term: &core::Term<'arena>,
+        on_message: &mut dyn FnMut(Message),
+    ) -> core::Term<'out_arena> {
+        let term = self.eval_env().normalize(scope, term);
+        self.check_fuel(range);
+
+        self.handle_messages(on_message);
+
+        term
+    }
+
     /// Elaborate a term, expecting it to be a format.
     pub fn elab_format<'out_arena>(
         &mut self,
@@ -748,6 +1375,7 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
     ) -> core::Term<'out_arena> {
         let term = self.check(surface_term, &self.format_type.clone());
         let term = self.eval_env().unfold_metas(scope, &term); // TODO: fuse with above?
+        self.check_fuel(surface_term.range());
 
         self.handle_messages(on_message);
 
@@ -768,15 +1396,18 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
             Pattern::Name(_, name) => CheckedPattern::Binder(file_range, *name),
             Pattern::Placeholder(_) => CheckedPattern::Placeholder(file_range),
             Pattern::StringLiteral(range, lit) => {
+                if let Some(len) = byte_array_len(expected_type) {
+                    return match self.check_byte_string(*range, *lit, len) {
+                        true => CheckedPattern::ByteString(file_range, *lit),
+                        false => CheckedPattern::ReportedError(file_range),
+                    };
+                }
+
                 let constant = match expected_type.match_prim_spine() {
                     Some((Prim::U8Type, [])) => self.parse_ascii(*range, *lit, Const::U8),
                     Some((Prim::U16Type, [])) => self.parse_ascii(*range, *lit, Const::U16),
                     Some((Prim::U32Type, [])) => self.parse_ascii(*range, *lit, Const::U32),
                     Some((Prim::U64Type, [])) => self.parse_ascii(*range, *lit, Const::U64),
-                    // Some((Prim::Array8Type, [len, _])) => todo!(),
-                    // Some((Prim::Array16Type, [len, _])) => todo!(),
-                    // Some((Prim::Array32Type, [len, _])) => todo!(),
-                    // Some((Prim::Array64Type, [len, _])) => todo!(),
                     Some((Prim::ReportedError, _)) => None,
                     _ => {
                         let expected_type = self.pretty_print_value(expected_type);
@@ -793,6 +1424,28 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                     None => CheckedPattern::ReportedError(file_range),
                 }
             }
+            Pattern::CharLiteral(range, lit) => {
+                let constant = match expected_type.match_prim_spine() {
+                    Some((Prim::U8Type, [])) => self.parse_char(*range, *lit, Const::U8),
+                    Some((Prim::U16Type, [])) => self.parse_char(*range, *lit, Const::U16),
+                    Some((Prim::U32Type, [])) => self.parse_char(*range, *lit, Const::U32),
+                    Some((Prim::U64Type, [])) => self.parse_char(*range, *lit, Const::U64),
+                    Some((Prim::ReportedError, _)) => None,
+                    _ => {
+                        let expected_type = self.pretty_print_value(expected_type);
+                        self.push_message(Message::CharLiteralNotSupported {
+                            range: file_range,
+                            expected_type,
+                        });
+                        None
+                    }
+                };
+
+                match constant {
+                    Some(constant) => CheckedPattern::ConstLit(file_range, constant),
+                    None => CheckedPattern::ReportedError(file_range),
+                }
+            }
             Pattern::NumberLiteral(range, lit) => {
                 let constant = match expected_type.match_prim_spine() {
                     Some((Prim::U8Type, [])) => self.parse_number_radix(*range, *lit, Const::U8),
@@ -803,8 +1456,8 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                     Some((Prim::S16Type, [])) => self.parse_number(*range, *lit, Const::S16),
                     Some((Prim::S32Type, [])) => self.parse_number(*range, *lit, Const::S32),
                     Some((Prim::S64Type, [])) => self.parse_number(*range, *lit, Const::S64),
-                    Some((Prim::F32Type, [])) => self.parse_number(*range, *lit, Const::F32),
-                    Some((Prim::F64Type, [])) => self.parse_number(*range, *lit, Const::F64),
+                    Some((Prim::F32Type, [])) => self.parse_float(*range, *lit, Const::F32),
+                    Some((Prim::F64Type, [])) => self.parse_float(*range, *lit, Const::F64),
                     Some((Prim::ReportedError, _)) => None,
                     _ => {
                         let expected_type = self.pretty_print_value(expected_type);
@@ -866,6 +1519,12 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                 let r#type = self.push_unsolved_type(source);
                 (CheckedPattern::ReportedError(file_range), r#type)
             }
+            Pattern::CharLiteral(_, _) => {
+                self.push_message(Message::AmbiguousCharLiteral { range: file_range });
+                let source = MetaSource::ReportedErrorType(file_range);
+                let r#type = self.push_unsolved_type(source);
+                (CheckedPattern::ReportedError(file_range), r#type)
+            }
             Pattern::NumberLiteral(_, _) => {
                 self.push_message(Message::AmbiguousNumericLiteral { range: file_range });
                 let source = MetaSource::ReportedErrorType(file_range);
@@ -944,7 +1603,7 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
             CheckedPattern::Binder(_, name) => Some(name),
             CheckedPattern::Placeholder(_) => None,
             // FIXME: generate failing parameter expressions?
-            CheckedPattern::ConstLit(range, _) => {
+            CheckedPattern::ConstLit(range, _) | CheckedPattern::ByteString(range, _) => {
                 self.push_message(Message::RefutablePattern {
                     pattern_range: range,
                 });
@@ -969,7 +1628,7 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
             CheckedPattern::Binder(_, name) => Some(name),
             CheckedPattern::Placeholder(_) => None,
             // FIXME: generate failing parameter expressions?
-            CheckedPattern::ConstLit(range, _) => {
+            CheckedPattern::ConstLit(range, _) | CheckedPattern::ByteString(range, _) => {
                 self.push_message(Message::RefutablePattern {
                     pattern_range: range,
                 });
@@ -1247,15 +1906,31 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                 }
             }
             (Term::StringLiteral(range, lit), _) => {
+                // String literals can be used as arrays of bytes
+                let is_valid_bytes = match byte_array_len(&expected_type) {
+                    Some(len) => Some(self.check_byte_string(*range, *lit, len)),
+                    None => is_byte_array_type(&expected_type).then_some(true),
+                };
+                if let Some(is_valid) = is_valid_bytes {
+                    let bytes = is_valid.then(|| self.string_literal_bytes(*range, *lit));
+                    return match bytes.flatten() {
+                        Some(bytes) => {
+                            let elem_exprs = bytes.into_iter().map(|(_, byte)| {
+                                let r#const = Const::U8(byte, UIntStyle::Ascii);
+                                core::Term::ConstLit(file_range.into(), r#const)
+                            });
+                            let elem_exprs = self.scope.to_scope_from_iter(elem_exprs);
+                            core::Term::ArrayLit(file_range.into(), elem_exprs)
+                        }
+                        None => core::Term::Prim(file_range.into(), Prim::ReportedError),
+                    };
+                }
+
                 let constant = match expected_type.match_prim_spine() {
                     Some((Prim::U8Type, [])) => self.parse_ascii(*range, *lit, Const::U8),
                     Some((Prim::U16Type, [])) => self.parse_ascii(*range, *lit, Const::U16),
                     Some((Prim::U32Type, [])) => self.parse_ascii(*range, *lit, Const::U32),
                     Some((Prim::U64Type, [])) => self.parse_ascii(*range, *lit, Const::U64),
-                    // Some((Prim::Array8Type, [len, _])) => todo!(),
-                    // Some((Prim::Array16Type, [len, _])) => todo!(),
-                    // Some((Prim::Array32Type, [len, _])) => todo!(),
-                    // Some((Prim::Array64Type, [len, _])) => todo!(),
                     Some((Prim::ReportedError, _)) => None,
                     _ => {
                         let expected_type = self.pretty_print_value(&expected_type);
@@ -1272,9 +1947,34 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                     None => core::Term::Prim(file_range.into(), Prim::ReportedError),
                 }
             }
-            (Term::NumberLiteral(range, lit), _) => {
+            (Term::CharLiteral(range, lit), _) => {
                 let constant = match expected_type.match_prim_spine() {
-                    Some((Prim::U8Type, [])) => self.parse_number_radix(*range, *lit, Const::U8),
+                    Some((Prim::U8Type, [])) => self.parse_char(*range, *lit, Const::U8),
+                    Some((Prim::U16Type, [])) => self.parse_char(*range, *lit, Const::U16),
+                    Some((Prim::U32Type, [])) => self.parse_char(*range, *lit, Const::U32),
+                    Some((Prim::U64Type, [])) => self.parse_char(*range, *lit, Const::U64),
+                    Some((Prim::ReportedError, _)) => None,
+                    _ => {
+                        let expected_type = self.pretty_print_value(&expected_type);
+                        self.push_message(Message::CharLiteralNotSupported {
+                            range: file_range,
+                            expected_type,
+                        });
+                        None
+                    }
+                };
+
+                match constant {
+                    Some(constant) => core::Term::ConstLit(file_range.into(), constant),
+                    None => core::Term::Prim(file_range.into(), Prim::ReportedError),
+                }
+            }
+            (Term::Name(range, name), _) if self.is_float_literal_name(*name) => {
+                self.check(&Term::NumberLiteral(*range, *name), &expected_type)
+            }
+            (Term::NumberLiteral(range, lit), _) => {
+                let constant = match expected_type.match_prim_spine() {
+                    Some((Prim::U8Type, [])) => self.parse_number_radix(*range, *lit, Const::U8),
                     Some((Prim::U16Type, [])) => self.parse_number_radix(*range, *lit, Const::U16),
                     Some((Prim::U32Type, [])) => self.parse_number_radix(*range, *lit, Const::U32),
                     Some((Prim::U64Type, [])) => self.parse_number_radix(*range, *lit, Const::U64),
@@ -1282,8 +1982,8 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                     Some((Prim::S16Type, [])) => self.parse_number(*range, *lit, Const::S16),
                     Some((Prim::S32Type, [])) => self.parse_number(*range, *lit, Const::S32),
                     Some((Prim::S64Type, [])) => self.parse_number(*range, *lit, Const::S64),
-                    Some((Prim::F32Type, [])) => self.parse_number(*range, *lit, Const::F32),
-                    Some((Prim::F64Type, [])) => self.parse_number(*range, *lit, Const::F64),
+                    Some((Prim::F32Type, [])) => self.parse_float(*range, *lit, Const::F32),
+                    Some((Prim::F64Type, [])) => self.parse_float(*range, *lit, Const::F64),
                     Some((Prim::ReportedError, _)) => None,
                     _ => {
                         let expected_type = self.pretty_print_value(&expected_type);
@@ -1375,6 +2075,9 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                 if let Some((prim, r#type)) = self.prim_env.get_name(*name) {
                     return (core::Term::Prim(file_range.into(), prim), r#type.clone());
                 }
+                if self.is_float_literal_name(*name) {
+                    return self.synth(&Term::NumberLiteral(*range, *name));
+                }
 
                 let candidates = self
                     .local_env
@@ -1382,7 +2085,8 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                     .iter()
                     .flatten()
                     .copied()
-                    .chain(self.item_env.names.iter().copied());
+                    .chain(self.item_env.names.iter().copied())
+                    .chain(self.prim_env.names());
                 let suggestion = suggest_name(&self.interner.borrow(), *name, candidates);
 
                 self.push_message(Message::UnboundName {
@@ -1527,6 +2231,29 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                 let mut head_range = head_expr.range();
                 let (mut head_expr, mut head_type) = self.synth(head_expr);
 
+                // Elaborate multi-dimensional arrays, for example
+                // `Array8 [2, 3] U8`, to nested applications of the head
+                if let (core::Term::Prim(_, prim), [dims_arg, elem_arg]) = (&head_expr, *args) {
+                    if let (Some(index_type), Term::ArrayLiteral(_, dim_exprs)) =
+                        (array_dimension_type(*prim), &dims_arg.term)
+                    {
+                        if dims_arg.plicity == Plicity::Explicit
+                            && elem_arg.plicity == Plicity::Explicit
+                            && !dim_exprs.is_empty()
+                        {
+                            let prim = *prim;
+                            return self.synth_array_dims(
+                                *range,
+                                head_range,
+                                prim,
+                                index_type,
+                                dim_exprs,
+                                &elem_arg.term,
+                            );
+                        }
+                    }
+                }
+
                 for arg in *args {
                     head_type = self.elim_env().force(&head_type);
 
@@ -1588,6 +2315,21 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                     );
                     head_type = self.elim_env().apply_closure(body_type, arg_expr_value);
                 }
+
+                if let [limit_arg, format_arg] = *args {
+                    self.check_format_limit(
+                        &head_expr,
+                        limit_arg.term.range(),
+                        format_arg.term.range(),
+                    );
+                }
+                if let [format_arg] = *args {
+                    self.check_format_size(&head_expr, format_arg.term.range());
+                }
+                if let [align_arg, _] | [_, align_arg, _] = *args {
+                    self.check_alignment(&head_expr, align_arg.term.range());
+                }
+
                 (head_expr, head_type)
             }
             Term::RecordType(range, type_fields) => {
@@ -1729,7 +2471,10 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                 self.push_message(Message::AmbiguousStringLiteral { range: file_range });
                 self.synth_reported_error(*range)
             }
-            // TODO: Stuck macros + unification like in Klister?
+            Term::CharLiteral(range, _) => {
+                self.push_message(Message::AmbiguousCharLiteral { range: file_range });
+                self.synth_reported_error(*range)
+            }
             Term::NumberLiteral(range, _) => {
                 self.push_message(Message::AmbiguousNumericLiteral { range: file_range });
                 self.synth_reported_error(*range)
@@ -1739,11 +2484,12 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                 (expr, self.bool_type.clone())
             }
             Term::FormatRecord(range, format_fields) => {
-                let (labels, formats) = self.check_format_fields(*range, format_fields);
+                let (labels, formats) = self.check_format_fields(*range, format_fields, true);
                 let format_record = core::Term::FormatRecord(file_range.into(), labels, formats);
                 (format_record, self.format_type.clone())
             }
-            Term::FormatCond(_, (_, name), format, pred) => {
+            Term::FormatCond(_, (name_range, name), format, pred) => {
+                let name_range = self.file_range(*name_range);
                 let format_type = self.format_type.clone();
                 let format = self.check(format, &format_type);
                 let format_value = self.eval_env().eval(&format);
@@ -1752,6 +2498,7 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                 self.local_env.push_param(Some(*name), repr_type);
                 let bool_type = self.bool_type.clone();
                 let pred_expr = self.check(pred, &bool_type);
+                self.check_refinement(pred.range(), name_range, &pred_expr);
                 self.local_env.pop();
 
                 let cond_format = core::Term::FormatCond(
@@ -1764,11 +2511,14 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                 (cond_format, format_type)
             }
             Term::FormatOverlap(range, format_fields) => {
-                let (labels, formats) = self.check_format_fields(*range, format_fields);
+                let (labels, formats) = self.check_format_fields(*range, format_fields, false);
                 let overlap_format = core::Term::FormatOverlap(file_range.into(), labels, formats);
 
                 (overlap_format, self.format_type.clone())
             }
+            Term::FormatDo(range, format_fields, expr) => {
+                self.synth_format_do(*range, format_fields, expr)
+            }
             Term::BinOp(range, lhs, op, rhs) => self.synth_bin_op(*range, lhs, *op, rhs),
             Term::ReportedError(range) => self.synth_reported_error(*range),
         }
@@ -2156,21 +2906,192 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         (expr, r#type)
     }
 
-    /// Check a series of format fields
+    /// Report computed fields that reuse the label of an earlier format field,
+    /// along with format fields that reuse the label of an earlier computed
+    /// field, returning the fields that remain.
+    fn report_shadowed_format_fields<'fields, 'a>(
+        &mut self,
+        format_fields: &'fields [FormatField<'a, ByteRange>],
+    ) -> Vec<&'fields FormatField<'a, ByteRange>> {
+        let mut remaining_fields = Vec::<&'fields FormatField<'a, ByteRange>>::new();
+
+        for format_field in format_fields {
+            let (range, label) = *format_field_label(format_field);
+            let is_computed = is_computed_field(format_field);
+            let shadowed_range = (remaining_fields.iter())
+                .find(|f| format_field_label(f).1 == label)
+                .filter(|f| is_computed_field(f) != is_computed)
+                .map(|f| format_field_label(f).0);
+
+            match shadowed_range {
+                Some(shadowed_range) => self.push_message(Message::ShadowedFormatField {
+                    range: self.file_range(range),
+                    shadowed_range: self.file_range(shadowed_range),
+                    label,
+                    is_computed,
+                }),
+                None => remaining_fields.push(format_field),
+            }
+        }
+
+        remaining_fields
+    }
+
+    /// Elaborate a sized array type or repetition format applied to a list of
+    /// dimensions, nesting the applications so that the outermost dimension
+    /// comes first.
+    fn synth_array_dims(
+        &mut self,
+        range: ByteRange,
+        head_range: ByteRange,
+        prim: Prim,
+        index_type: Prim,
+        dim_exprs: &[Term<'_, ByteRange>],
+        elem_expr: &Term<'_, ByteRange>,
+    ) -> (core::Term<'arena>, ArcValue<'arena>) {
+        let index_type = Spanned::empty(Arc::new(Value::prim(index_type, [])));
+        let elem_type = match prim {
+            Prim::FormatRepeatLen8
+            | Prim::FormatRepeatLen16
+            | Prim::FormatRepeatLen32
+            | Prim::FormatRepeatLen64 => self.format_type.clone(),
+            _ => self.universe.clone(),
+        };
+
+        let dim_exprs: Vec<_> = (dim_exprs.iter())
+            .map(|dim_expr| (dim_expr.range(), self.check(dim_expr, &index_type)))
+            .collect();
+        let mut expr = self.check(elem_expr, &elem_type);
+
+        for (dim_range, dim_expr) in dim_exprs.into_iter().rev() {
+            let head_expr = core::Term::FunApp(
+                self.file_range(ByteRange::merge(head_range, dim_range))
+                    .into(),
+                Plicity::Explicit,
+                self.scope
+                    .to_scope(core::Term::Prim(self.file_range(head_range).into(), prim)),
+                self.scope.to_scope(dim_expr),
+            );
+            expr = core::Term::FunApp(
+                self.file_range(range).into(),
+                Plicity::Explicit,
+                self.scope.to_scope(head_expr),
+                self.scope.to_scope(expr),
+            );
+        }
+
+        (expr, elem_type)
+    }
+
+    /// Report a warning if `expr` is a limit format applied to a format that
+    /// always reads more bytes than the limit allows.
+    fn check_format_limit(
+        &mut self,
+        expr: &core::Term<'arena>,
+        limit_range: ByteRange,
+        format_range: ByteRange,
+    ) {
+        let (limit_expr, format_expr) = match expr {
+            core::Term::FunApp(
+                _,
+                _,
+                core::Term::FunApp(_, _, head_expr, limit_expr),
+                format_expr,
+            ) if matches!(head_expr, core::Term::Prim(_, prim) if is_limit_prim(*prim)) => {
+                (limit_expr, format_expr)
+            }
+            _ => return,
+        };
+
+        let limit = self.eval_env().eval(limit_expr);
+        let format = self.eval_env().eval(format_expr);
+
+        let format_size = layout::format_size(self.elim_env(), self.local_env.len(), &format);
+        if let (Some(limit), Some(format_size)) = (layout::const_u64(&limit), format_size) {
+            if format_size > limit {
+                self.push_message(Message::FormatExceedsLimit {
+                    format_range: self.file_range(format_range),
+                    format_size,
+                    limit_range: self.file_range(limit_range),
+                    limit,
+                });
+            }
+        }
+    }
+
+    /// Report an error if `expr` is an application of `size_of` to a format
+    /// that does not always read the same number of bytes.
+    fn check_format_size(&mut self, expr: &core::Term<'arena>, format_range: ByteRange) {
+        let format_expr = match expr {
+            core::Term::FunApp(_, _, core::Term::Prim(_, Prim::FormatSize), format_expr) => {
+                format_expr
+            }
+            _ => return,
+        };
+
+        let format = self.eval_env().eval(format_expr);
+        if matches!(format.match_prim_spine(), Some((Prim::ReportedError, _))) {
+            return;
+        }
+        if layout::format_size(self.elim_env(), self.local_env.len(), &format).is_none() {
+            self.push_message(Message::UnknownFormatSize {
+                range: self.file_range(format_range),
+            });
+        }
+    }
+
+    /// Report an error if `expr` is an application of `repeat_aligned` or
+    /// `align` to an alignment that is known to be zero.
+    fn check_alignment(&mut self, expr: &core::Term<'arena>, align_range: ByteRange) {
+        let align_expr = match expr {
+            core::Term::FunApp(
+                _,
+                _,
+                core::Term::FunApp(
+                    _,
+                    _,
+                    core::Term::FunApp(_, _, core::Term::Prim(_, Prim::FormatRepeatAligned), _),
+                    align_expr,
+                ),
+                _,
+            )
+            | core::Term::FunApp(
+                _,
+                _,
+                core::Term::FunApp(_, _, core::Term::Prim(_, Prim::FormatAlign), align_expr),
+                _,
+            ) => align_expr,
+            _ => return,
+        };
+
+        let align = self.eval_env().eval(align_expr);
+        if layout::const_u64(&align) == Some(0) {
+            self.push_message(Message::ZeroAlignment {
+                range: self.file_range(align_range),
+            });
+        }
+    }
+
+    /// Check a series of format fields.
+    ///
+    /// Elaborate the fields of a record or overlap format. If the fields are
+    /// `sequential`, warnings are reported for fields that follow a format that
+    /// reads until the end of the input.
     fn check_format_fields(
         &mut self,
         range: ByteRange,
         format_fields: &[FormatField<'_, ByteRange>],
+        sequential: bool,
     ) -> (&'arena [StringId], &'arena [core::Term<'arena>]) {
         let universe = self.universe.clone();
         let format_type = self.format_type.clone();
 
         let initial_local_len = self.local_env.len();
+        let format_fields = self.report_shadowed_format_fields(format_fields);
         let (labels, format_fields) =
-            self.report_duplicate_labels(range, format_fields, |f| match f {
-                FormatField::Format { label, .. } | FormatField::Computed { label, .. } => *label,
-            });
+            self.report_duplicate_labels(range, &format_fields, |f| *format_field_label(f));
         let mut formats = SliceVec::new(self.scope, labels.len());
+        let mut greedy_range = None;
 
         for format_field in format_fields {
             match format_field {
@@ -2184,6 +3105,19 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                     let format_value = self.eval_env().eval(&format);
                     let r#type = self.elim_env().format_repr(&format_value);
 
+                    match greedy_range {
+                        Some(greedy_range) => {
+                            self.push_message(Message::FieldAfterGreedyRead {
+                                range: label_range,
+                                greedy_range,
+                            });
+                        }
+                        None if sequential && is_greedy_format(&format_value) => {
+                            greedy_range = Some(label_range);
+                        }
+                        None => {}
+                    }
+
                     self.local_env.push_param(Some(*label), r#type);
 
                     match pred {
@@ -2193,6 +3127,7 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                             // Note: No need to push a param, as this was done above,
                             // in preparation for checking the the next format field.
                             let cond_expr = self.check(pred, &self.bool_type.clone());
+                            self.check_refinement(pred.range(), label_range, &cond_expr);
 
                             let field_span = Span::merge(&label_range.into(), &cond_expr.span());
                             formats.push(core::Term::FormatCond(
@@ -2249,12 +3184,107 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         (labels, formats.into())
     }
 
+    /// Elaborate a sequence format to a mapping over a record format, where
+    /// the fields of the record are bound for use in the result expression.
+    fn synth_format_do(
+        &mut self,
+        range: ByteRange,
+        format_fields: &[FormatField<'_, ByteRange>],
+        expr: &Term<'_, ByteRange>,
+    ) -> (core::Term<'arena>, ArcValue<'arena>) {
+        let file_range = self.file_range(range);
+        let span = Span::from(file_range);
+
+        let (labels, formats) = self.check_format_fields(range, format_fields, true);
+        let format_record = core::Term::FormatRecord(span, labels, formats);
+        let format_value = self.eval_env().eval(&format_record);
+        let repr_type = self.elim_env().format_repr(&format_value);
+
+        // The result type is created before the fields are bound, ensuring
+        // that it does not depend on them.
+        let result_type = self.push_unsolved_type(MetaSource::FormatDoResultType(file_range));
+
+        // Bind the parsed record, along with each of its fields.
+        let initial_local_len = self.local_env.len();
+        let record_expr = self.local_env.push_param(None, repr_type.clone());
+        let mut field_types = match repr_type.as_ref() {
+            Value::RecordType(_, types) => types.clone(),
+            _ => unreachable!("record formats should be represented by record types"),
+        };
+        let mut field_defs = Vec::with_capacity(labels.len());
+        let mut record_var = env::Index::last();
+
+        for label in labels {
+            let (field_type, next_field_types) = match self.elim_env().split_telescope(field_types)
+            {
+                Some(split) => split,
+                None => unreachable!("record types should have a type for each label"),
+            };
+            let field_expr = self.elim_env().record_proj(record_expr.clone(), *label);
+
+            let r#type = self.quote_env().quote(self.scope, &field_type);
+            field_defs.push((*label, r#type, record_var));
+
+            self.local_env
+                .push_def(Some(*label), field_expr.clone(), field_type);
+            field_types = next_field_types(field_expr);
+            record_var = record_var.prev();
+        }
+
+        let result_expr = self.check(expr, &result_type);
+        self.local_env.truncate(initial_local_len);
+
+        let body_expr = field_defs.into_iter().rev().fold(
+            result_expr,
+            |body_expr, (label, r#type, record_var)| {
+                let record_expr = core::Term::LocalVar(span, record_var);
+                core::Term::Let(
+                    span,
+                    Some(label),
+                    self.scope.to_scope(r#type),
+                    self.scope.to_scope(core::Term::RecordProj(
+                        span,
+                        self.scope.to_scope(record_expr),
+                        label,
+                    )),
+                    self.scope.to_scope(body_expr),
+                )
+            },
+        );
+        let result_type = self.quote_env().quote(self.scope, &result_type);
+
+        // map @result_type format_record (fun record => body_expr)
+        let map_expr = core::Term::FunApp(
+            span,
+            Plicity::Explicit,
+            self.scope.to_scope(core::Term::FunApp(
+                span,
+                Plicity::Explicit,
+                self.scope.to_scope(core::Term::FunApp(
+                    span,
+                    Plicity::Implicit,
+                    self.scope.to_scope(core::Term::Prim(span, Prim::FormatMap)),
+                    self.scope.to_scope(result_type),
+                )),
+                self.scope.to_scope(format_record),
+            )),
+            self.scope.to_scope(core::Term::FunLit(
+                span,
+                Plicity::Explicit,
+                None,
+                self.scope.to_scope(body_expr),
+            )),
+        );
+
+        (map_expr, self.format_type.clone())
+    }
+
     /// Elaborate a match expression in checking mode
     fn check_match(
         &mut self,
         range: ByteRange,
         scrutinee_expr: &Term<'_, ByteRange>,
-        equations: &[(Pattern<ByteRange>, Term<'_, ByteRange>)],
+        equations: &[Equation<'_>],
         expected_type: &ArcValue<'arena>,
     ) -> core::Term<'arena> {
         let match_info = MatchInfo {
@@ -2286,11 +3316,22 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         &mut self,
         match_info: &MatchInfo<'arena>,
         is_reachable: bool,
-        mut equations: impl Iterator<Item = &'a (Pattern<ByteRange>, Term<'a, ByteRange>)>,
+        mut equations: impl Iterator<Item = &'a Equation<'a>>,
     ) -> core::Term<'arena> {
         match equations.next() {
-            Some((pattern, body_expr)) => {
-                match self.check_pattern(pattern, &match_info.scrutinee.r#type) {
+            Some((pattern, guard_expr, body_expr)) => {
+                let pattern = self.check_pattern(pattern, &match_info.scrutinee.r#type);
+
+                // Guarded patterns fall through to the subsequent equations
+                // if the guard does not hold.
+                if let Some(guard_expr) = guard_expr {
+                    if let Some((range, name)) = self.check_guard_pattern(&pattern, guard_expr) {
+                        let guard = (range, name, guard_expr, body_expr);
+                        return self.elab_match_guard(match_info, is_reachable, guard, equations);
+                    }
+                }
+
+                match pattern {
                     // Named patterns are elaborated to let bindings, where the
                     // scrutinee is bound as a definition in the body expression.
                     // Subsequent patterns are unreachable.
@@ -2336,6 +3377,49 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
 
                         self.elab_match_const(match_info, is_reachable, const_equation, equations)
                     }
+                    // Byte string patterns are elaborated to a comparison of
+                    // each byte of the bound scrutinee, falling through to the
+                    // subsequent equations when the first comparison fails.
+                    CheckedPattern::ByteString(range, string_id) => {
+                        self.check_match_reachable(is_reachable, range);
+
+                        let def_expr = self.eval_env().eval(match_info.scrutinee.expr);
+                        let def_type_value = match_info.scrutinee.r#type.clone();
+                        let def_type = self.quote_env().quote(self.scope, &def_type_value);
+
+                        self.local_env
+                            .push_def(None, def_expr, def_type_value.clone());
+                        let bound_match_info = MatchInfo {
+                            range: match_info.range,
+                            scrutinee: Scrutinee {
+                                range: match_info.scrutinee.range,
+                                expr: (self.scope).to_scope(core::Term::LocalVar(
+                                    match_info.scrutinee.expr.span(),
+                                    env::Index::last(),
+                                )),
+                                r#type: def_type_value,
+                            },
+                            expected_type: match_info.expected_type.clone(),
+                        };
+                        let body_expr = self.check(body_expr, &bound_match_info.expected_type);
+                        let else_expr = self.elab_match(&bound_match_info, is_reachable, equations);
+                        let match_expr = self.byte_string_match(
+                            &bound_match_info,
+                            range,
+                            string_id,
+                            body_expr,
+                            else_expr,
+                        );
+                        self.local_env.pop();
+
+                        core::Term::Let(
+                            Span::merge(&range.into(), &match_expr.span()),
+                            None,
+                            self.scope.to_scope(def_type),
+                            match_info.scrutinee.expr,
+                            self.scope.to_scope(match_expr),
+                        )
+                    }
                     // If we hit an error, propagate it, while still checking
                     // the body expression and the subsequent branches.
                     CheckedPattern::ReportedError(range) => {
@@ -2357,13 +3441,90 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         }
     }
 
+    /// Compare each byte of an array scrutinee with the bytes of a string in
+    /// turn, returning the body expression if every byte is equal, and the
+    /// else expression as soon as a byte differs.
+    fn byte_string_match(
+        &mut self,
+        match_info: &MatchInfo<'arena>,
+        range: FileRange,
+        string_id: StringId,
+        body_expr: core::Term<'arena>,
+        else_expr: core::Term<'arena>,
+    ) -> core::Term<'arena> {
+        let span = Span::from(range);
+        let (index_prim, len, elem_type) = match match_info.scrutinee.r#type.match_prim_spine() {
+            Some((array_prim, [Elim::FunApp(_, len), Elim::FunApp(_, elem_type)])) => {
+                let index_prim = match array_prim {
+                    Prim::Array8Type => Prim::Array8Index,
+                    Prim::Array16Type => Prim::Array16Index,
+                    Prim::Array32Type => Prim::Array32Index,
+                    _ => Prim::Array64Index,
+                };
+                (index_prim, len.clone(), elem_type.clone())
+            }
+            _ => unreachable!("byte string patterns are only checked against arrays"),
+        };
+
+        let scope = self.scope;
+        let len = scope.to_scope(self.quote_env().quote(scope, &len)) as &_;
+        let elem_type = scope.to_scope(self.quote_env().quote(scope, &elem_type)) as &_;
+
+        let prim = |prim| scope.to_scope(core::Term::Prim(span, prim)) as &'arena _;
+        let r#const = |r#const| scope.to_scope(core::Term::ConstLit(span, r#const)) as &'arena _;
+        let app = |plicity, head_expr, arg_expr| {
+            scope.to_scope(core::Term::FunApp(span, plicity, head_expr, arg_expr)) as &'arena _
+        };
+
+        // The string was validated when checking the pattern
+        let bytes = (self.string_literal_bytes(range.byte_range(), string_id)).unwrap_or_default();
+        let match_span = Span::merge(&span, &else_expr.span());
+        let mut match_expr = body_expr;
+
+        // Build the comparisons from the last byte outwards, so that the first
+        // byte is compared first
+        for (index, (_, byte)) in bytes.into_iter().enumerate().rev() {
+            let index = match index_prim {
+                Prim::Array8Index => Const::U8(index as u8, UIntStyle::Decimal),
+                Prim::Array16Index => Const::U16(index as u16, UIntStyle::Decimal),
+                Prim::Array32Index => Const::U32(index as u32, UIntStyle::Decimal),
+                _ => Const::U64(index as u64, UIntStyle::Decimal),
+            };
+            // arrayN_index @len @U8 index scrutinee
+            let index_expr = app(Plicity::Implicit, prim(index_prim), len);
+            let index_expr = app(Plicity::Implicit, index_expr, elem_type);
+            let index_expr = app(Plicity::Explicit, index_expr, r#const(index));
+            let index_expr = app(Plicity::Explicit, index_expr, match_info.scrutinee.expr);
+            // u8_eq (arrayN_index ...) byte
+            let eq_expr = app(Plicity::Explicit, prim(Prim::U8Eq), index_expr);
+            let eq_expr = app(
+                Plicity::Explicit,
+                eq_expr,
+                r#const(Const::U8(byte, UIntStyle::Ascii)),
+            );
+
+            match_expr = core::Term::ConstMatch(
+                match_span,
+                eq_expr,
+                // NOTE: in lexicographic order: in Rust, `false < true`
+                scope.to_scope_from_iter([
+                    (Const::Bool(false), else_expr.clone()),
+                    (Const::Bool(true), match_expr),
+                ]),
+                None,
+            );
+        }
+
+        match_expr
+    }
+
     /// Elaborate the equations, expecting a series of constant patterns
     fn elab_match_const<'a>(
         &mut self,
         match_info: &MatchInfo<'arena>,
         is_reachable: bool,
         (const_range, r#const, body_expr): (FileRange, Const, core::Term<'arena>),
-        mut equations: impl Iterator<Item = &'a (Pattern<ByteRange>, Term<'a, ByteRange>)>,
+        mut equations: impl Iterator<Item = &'a Equation<'a>>,
     ) -> core::Term<'arena> {
         // The full range of this series of patterns
         let mut full_span = Span::merge(&const_range.into(), &body_expr.span());
@@ -2371,14 +3532,49 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         let mut branches = vec![(r#const, body_expr)];
 
         // Elaborate a run of constant patterns.
-        'patterns: while let Some((pattern, body_expr)) = equations.next() {
+        'patterns: while let Some((pattern, guard_expr, body_expr)) = equations.next() {
             // Update the range up to the end of the next body expression
             full_span = Span::merge(&full_span, &self.file_range(body_expr.range()).into());
 
             // Default expression, defined if we arrive at a default case
             let default_branch;
 
-            match self.check_pattern(pattern, &match_info.scrutinee.r#type) {
+            let pattern = self.check_pattern(pattern, &match_info.scrutinee.r#type);
+
+            // Guarded patterns are elaborated in the default case, falling
+            // through to the subsequent equations if the guard does not hold.
+            if let Some(guard_expr) = guard_expr {
+                if let Some((range, name)) = self.check_guard_pattern(&pattern, guard_expr) {
+                    let scrutinee_type = match_info.scrutinee.r#type.clone();
+                    (self.local_env).push_param(None, scrutinee_type.clone());
+
+                    let default_match_info = MatchInfo {
+                        range: match_info.range,
+                        scrutinee: Scrutinee {
+                            range: match_info.scrutinee.range,
+                            expr: (self.scope).to_scope(core::Term::LocalVar(
+                                match_info.scrutinee.expr.span(),
+                                env::Index::last(),
+                            )),
+                            r#type: scrutinee_type,
+                        },
+                        expected_type: match_info.expected_type.clone(),
+                    };
+                    let guard = (range, name, guard_expr, body_expr);
+                    let default_expr =
+                        self.elab_match_guard(&default_match_info, is_reachable, guard, equations);
+                    self.local_env.pop();
+
+                    return core::Term::ConstMatch(
+                        Span::merge(&full_span, &default_expr.span()),
+                        match_info.scrutinee.expr,
+                        self.scope.to_scope_from_iter(branches.into_iter()),
+                        Some((None, self.scope.to_scope(default_expr) as &_)),
+                    );
+                }
+            }
+
+            match pattern {
                 // Accumulate constant pattern. Search for it in the accumulated
                 // branches and insert it in order.
                 CheckedPattern::ConstLit(range, r#const) => {
@@ -2407,7 +3603,8 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                 // `core::Term::ConstMatch` binds a variable, so both
                 // the named and  placeholder patterns should bind this.
                 CheckedPattern::Binder(range, name) => {
-                    self.check_match_reachable(is_reachable, range);
+                    let is_covered = covers_all_consts(&match_info.scrutinee.r#type, &branches);
+                    self.check_match_reachable(is_reachable && !is_covered, range);
 
                     // TODO: If we know this is an exhaustive match, bind the
                     // scrutinee to a let binding with the elaborated body, and
@@ -2419,13 +3616,17 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                     self.local_env.pop();
                 }
                 CheckedPattern::Placeholder(range) => {
-                    self.check_match_reachable(is_reachable, range);
+                    let is_covered = covers_all_consts(&match_info.scrutinee.r#type, &branches);
+                    self.check_match_reachable(is_reachable && !is_covered, range);
 
                     (self.local_env).push_param(None, match_info.scrutinee.r#type.clone());
                     let default_expr = self.check(body_expr, &match_info.expected_type);
                     default_branch = (None, self.scope.to_scope(default_expr) as &_);
                     self.local_env.pop();
                 }
+                CheckedPattern::ByteString(..) => {
+                    unreachable!("byte string patterns are only checked against arrays")
+                }
                 CheckedPattern::ReportedError(range) => {
                     (self.local_env).push_param(None, match_info.scrutinee.r#type.clone());
                     let default_expr = core::Term::Prim(range.into(), Prim::ReportedError);
@@ -2448,10 +3649,10 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         // Finished all the constant patterns without encountering a default
         // case. This should have been an exhaustive match, so check to see if
         // all the cases were covered.
-        let default_expr = match match_info.scrutinee.r#type.match_prim_spine() {
+        let default_expr = match covers_all_consts(&match_info.scrutinee.r#type, &branches) {
             // No need for a default case if all the values were covered
-            Some((Prim::BoolType, [])) if branches.len() >= 2 => None,
-            _ => Some(self.elab_match_absurd(is_reachable, match_info)),
+            true => None,
+            false => Some(self.elab_match_absurd(is_reachable, match_info)),
         };
 
         core::Term::ConstMatch(
@@ -2462,12 +3663,89 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
         )
     }
 
+    /// Check that a guard is applied to a pattern that matches everything,
+    /// returning the range of the pattern and the name it binds.
+    fn check_guard_pattern(
+        &mut self,
+        pattern: &CheckedPattern,
+        guard_expr: &Term<'_, ByteRange>,
+    ) -> Option<(FileRange, Option<StringId>)> {
+        match pattern {
+            CheckedPattern::Binder(range, name) => Some((*range, Some(*name))),
+            CheckedPattern::Placeholder(range) => Some((*range, None)),
+            CheckedPattern::ConstLit(range, _) | CheckedPattern::ByteString(range, _) => {
+                self.push_message(Message::UnsupportedPatternGuard {
+                    pattern_range: *range,
+                    guard_range: self.file_range(guard_expr.range()),
+                });
+                None
+            }
+            CheckedPattern::ReportedError(_) => None,
+        }
+    }
+
+    /// Elaborate a guarded equation to a boolean match on the guard, binding
+    /// the scrutinee in both the guard and the body expression. Subsequent
+    /// equations are elaborated for when the guard does not hold.
+    fn elab_match_guard<'a>(
+        &mut self,
+        match_info: &MatchInfo<'arena>,
+        is_reachable: bool,
+        (range, name, guard_expr, body_expr): (
+            FileRange,
+            Option<StringId>,
+            &Term<'_, ByteRange>,
+            &Term<'_, ByteRange>,
+        ),
+        equations: impl Iterator<Item = &'a Equation<'a>>,
+    ) -> core::Term<'arena> {
+        self.check_match_reachable(is_reachable, range);
+
+        let def_expr = self.eval_env().eval(match_info.scrutinee.expr);
+        let def_type_value = match_info.scrutinee.r#type.clone();
+        let def_type = self.quote_env().quote(self.scope, &def_type_value);
+        let def_type = self.scope.to_scope(def_type) as &_;
+
+        self.local_env.push_def(name, def_expr, def_type_value);
+        let guard_expr = self.check(guard_expr, &self.bool_type.clone());
+        let body_expr = self.check(body_expr, &match_info.expected_type);
+        self.local_env.pop();
+
+        let else_expr = self.elab_match(match_info, is_reachable, equations);
+
+        let guard_expr = core::Term::Let(
+            guard_expr.span(),
+            name,
+            def_type,
+            match_info.scrutinee.expr,
+            self.scope.to_scope(guard_expr),
+        );
+        let then_expr = core::Term::Let(
+            body_expr.span(),
+            name,
+            def_type,
+            match_info.scrutinee.expr,
+            self.scope.to_scope(body_expr),
+        );
+
+        core::Term::ConstMatch(
+            Span::merge(&range.into(), &else_expr.span()),
+            self.scope.to_scope(guard_expr),
+            // NOTE: in lexicographic order: in Rust, `false < true`
+            self.scope.to_scope_from_iter([
+                (Const::Bool(false), else_expr),
+                (Const::Bool(true), then_expr),
+            ]),
+            None,
+        )
+    }
+
     /// Elaborate unreachable match cases. This is useful for that these cases
     /// are correctly typed, even if they are never actually needed.
     fn elab_match_unreachable<'a>(
         &mut self,
         match_info: &MatchInfo<'arena>,
-        equations: impl Iterator<Item = &'a (Pattern<ByteRange>, Term<'a, ByteRange>)>,
+        equations: impl Iterator<Item = &'a Equation<'a>>,
     ) {
         self.elab_match(match_info, false, equations);
     }
@@ -2513,6 +3791,233 @@ impl_from_str_radix!(u16);
 impl_from_str_radix!(u32);
 impl_from_str_radix!(u64);
 
+/// Returns the type of the dimensions of primitives that can be applied to a
+/// list of dimensions, for example `repeat_len8 [2, 3] u8`.
+fn array_dimension_type(prim: Prim) -> Option<Prim> {
+    match prim {
+        Prim::Array8Type | Prim::FormatRepeatLen8 => Some(Prim::U8Type),
+        Prim::Array16Type | Prim::FormatRepeatLen16 => Some(Prim::U16Type),
+        Prim::Array32Type | Prim::FormatRepeatLen32 => Some(Prim::U32Type),
+        Prim::Array64Type | Prim::FormatRepeatLen64 => Some(Prim::U64Type),
+        _ => None,
+    }
+}
+
+fn is_computed_field<Range>(format_field: &FormatField<'_, Range>) -> bool {
+    matches!(format_field, FormatField::Computed { .. })
+}
+
+fn format_field_label<'f, Range>(
+    format_field: &'f FormatField<'_, Range>,
+) -> &'f (Range, StringId) {
+    match format_field {
+        FormatField::Format { label, .. } | FormatField::Computed { label, .. } => label,
+    }
+}
+
+/// Returns the length of an array of bytes, if it is known.
+fn byte_array_len(r#type: &ArcValue<'_>) -> Option<u64> {
+    match r#type.match_prim_spine()? {
+        (
+            Prim::Array8Type | Prim::Array16Type | Prim::Array32Type | Prim::Array64Type,
+            [Elim::FunApp(_, len), Elim::FunApp(_, elem_type)],
+        ) if matches!(elem_type.match_prim_spine(), Some((Prim::U8Type, []))) => {
+            layout::const_u64(len)
+        }
+        _ => None,
+    }
+}
+
+/// Returns true if `r#type` is an array of bytes with no fixed length.
+fn is_byte_array_type(r#type: &ArcValue<'_>) -> bool {
+    match r#type.match_prim_spine() {
+        Some((Prim::ArrayType, [Elim::FunApp(_, elem_type)])) => {
+            matches!(elem_type.match_prim_spine(), Some((Prim::U8Type, [])))
+        }
+        _ => false,
+    }
+}
+
+/// Remove the digit separators from the digits of a numeric literal, ensuring
+/// that each separator appears between two digits.
+fn strip_digit_separators(digits: &str) -> Result<String, &'static str> {
+    if digits.starts_with('_') {
+        Err("digit separator before first digit")
+    } else if digits.ends_with('_') {
+        Err("digit separator after last digit")
+    } else if digits.contains("__") {
+        Err("consecutive digit separators")
+    } else {
+        Ok(digits.replace('_', ""))
+    }
+}
+
+/// Returns the integer denoted by a decimal literal, if it denotes an integer
+/// that fits in an `i128`. This includes literals with exponents, like `2e3`
+/// or `1.5e1`.
+fn decimal_integer(literal: &str) -> Option<i128> {
+    let (mantissa, exponent) = match literal.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>().ok()?),
+        None => (literal, 0),
+    };
+    let (integer_digits, fraction_digits) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let mut digits = format!("{integer_digits}{fraction_digits}");
+    let mut exponent = exponent.checked_sub(i32::try_from(fraction_digits.len()).ok()?)?;
+
+    // Trailing zeros can be divided out of the digits for negative exponents
+    while exponent < 0 && digits.ends_with('0') {
+        digits.pop();
+        exponent += 1;
+    }
+
+    let scale = 10_i128.checked_pow(u32::try_from(exponent).ok()?)?;
+    digits.parse::<i128>().ok()?.checked_mul(scale)
+}
+
+/// Returns true if the constant branches of a match cover every value of the
+/// scrutinee type, in which case any default branch would be unreachable.
+fn covers_all_consts(r#type: &ArcValue<'_>, branches: &[(Const, core::Term<'_>)]) -> bool {
+    match r#type.match_prim_spine() {
+        Some((Prim::BoolType, [])) => branches.len() >= 2,
+        Some((Prim::U8Type | Prim::S8Type, [])) => branches.len() >= 256,
+        _ => false,
+    }
+}
+
+/// Parse the remainder of an escape sequence in a string literal, after its
+/// initial backslash.
+fn parse_escape(chars: &mut Peekable<CharIndices<'_>>) -> Option<char> {
+    match chars.next()?.1 {
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        '\\' => Some('\\'),
+        '\'' => Some('\''),
+        '"' => Some('"'),
+        'x' => {
+            let high = chars.next()?.1.to_digit(16)?;
+            let low = chars.next()?.1.to_digit(16)?;
+            char::from_u32(high * 16 + low)
+        }
+        'u' => {
+            if chars.next()?.1 != '{' {
+                return None;
+            }
+            let mut code = None;
+            loop {
+                match chars.next()?.1 {
+                    '}' => return code.and_then(char::from_u32),
+                    ch => {
+                        let digit = ch.to_digit(16)?;
+                        code = Some(u32::checked_add(code.unwrap_or(0).checked_mul(16)?, digit)?);
+                    }
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+fn is_limit_prim(prim: Prim) -> bool {
+    matches!(
+        prim,
+        Prim::FormatLimit8 | Prim::FormatLimit16 | Prim::FormatLimit32 | Prim::FormatLimit64,
+    )
+}
+
+/// Returns true if `format` reads until the end of the input, preventing any
+/// subsequent formats from reading any data.
+fn is_greedy_format(format: &ArcValue<'_>) -> bool {
+    match format.as_ref() {
+        Value::FormatCond(_, format, _) => is_greedy_format(format),
+        format => matches!(
+            format.match_prim_spine(),
+            Some((Prim::FormatRepeatUntilEnd, _)),
+        ),
+    }
+}
+
+/// Returns true if `pred` compares an expression of the refined field with a
+/// constant that the expression can never satisfy, given the range of values
+/// it can take. For example `x > 0xff` on a `U8` field, or
+/// `u8_eq (u8_and x 0x0f) 0x10`. Such refinements can never hold.
+fn is_unsatisfiable_refinement(pred: &core::Term<'_>) -> bool {
+    use std::cmp::Ordering;
+
+    use crate::core::Term::{ConstLit, FunApp, Prim as PrimTerm};
+    use Prim::*;
+
+    let (prim, lhs, rhs) = match pred {
+        FunApp(_, _, FunApp(_, _, PrimTerm(_, prim), lhs), rhs) => (*prim, *lhs, *rhs),
+        _ => return false,
+    };
+    let ordering = match prim {
+        U8Lt | U16Lt | U32Lt | U64Lt | S8Lt | S16Lt | S32Lt | S64Lt => Ordering::Less,
+        U8Gt | U16Gt | U32Gt | U64Gt | S8Gt | S16Gt | S32Gt | S64Gt => Ordering::Greater,
+        U8Eq | U16Eq | U32Eq | U64Eq | S8Eq | S16Eq | S32Eq | S64Eq => Ordering::Equal,
+        _ => return false,
+    };
+
+    // Compare as `expr < constant`, `expr > constant`, or `expr == constant`
+    let (ordering, expr, r#const) = match (lhs, rhs) {
+        (expr, ConstLit(_, r#const)) => (ordering, expr, r#const),
+        (ConstLit(_, r#const), expr) => (ordering.reverse(), expr, r#const),
+        _ => return false,
+    };
+    let (r#const, type_range) = match integer_const(r#const) {
+        Some(integer) => integer,
+        None => return false,
+    };
+
+    match (ordering, refined_field_range(expr, type_range)) {
+        (Ordering::Less, Some((min, _))) => r#const <= min,
+        (Ordering::Greater, Some((_, max))) => r#const >= max,
+        (Ordering::Equal, Some((min, max))) => r#const < min || max < r#const,
+        (_, None) => false,
+    }
+}
+
+/// The range of values that an expression of the refined field can take, if
+/// it is the field itself, or the field masked with a constant. Fields range
+/// over every value of their type.
+fn refined_field_range(expr: &core::Term<'_>, type_range: (i128, i128)) -> Option<(i128, i128)> {
+    use crate::core::Term::{ConstLit, FunApp, LocalVar, Prim as PrimTerm};
+    use Prim::*;
+
+    match expr {
+        LocalVar(_, var) if *var == env::Index::last() => Some(type_range),
+        FunApp(_, _, FunApp(_, _, PrimTerm(_, U8And | U16And | U32And | U64And), lhs), rhs) => {
+            let (expr, mask) = match (lhs, rhs) {
+                (expr, ConstLit(_, mask)) | (ConstLit(_, mask), expr) => (expr, mask),
+                _ => return None,
+            };
+            let (mask, _) = integer_const(mask)?;
+            let (_, max) = refined_field_range(expr, type_range)?;
+            Some((0, std::cmp::min(max, mask)))
+        }
+        _ => None,
+    }
+}
+
+/// The value of an integer constant, along with the range of its type.
+fn integer_const(r#const: &Const) -> Option<(i128, (i128, i128))> {
+    fn integer<T: Into<i128>>(value: T, min: T, max: T) -> Option<(i128, (i128, i128))> {
+        Some((value.into(), (min.into(), max.into())))
+    }
+
+    match r#const {
+        Const::U8(n, _) => integer(*n, u8::MIN, u8::MAX),
+        Const::U16(n, _) => integer(*n, u16::MIN, u16::MAX),
+        Const::U32(n, _) => integer(*n, u32::MIN, u32::MAX),
+        Const::U64(n, _) => integer(*n, u64::MIN, u64::MAX),
+        Const::S8(n) => integer(*n, i8::MIN, i8::MAX),
+        Const::S16(n) => integer(*n, i16::MIN, i16::MAX),
+        Const::S32(n) => integer(*n, i32::MIN, i32::MAX),
+        Const::S64(n) => integer(*n, i64::MIN, i64::MAX),
+        _ => None,
+    }
+}
+
 /// Simple patterns that have had some initial elaboration performed on them
 #[derive(Debug)]
 enum CheckedPattern {
@@ -2522,10 +4027,19 @@ enum CheckedPattern {
     Placeholder(FileRange),
     /// Constant literals
     ConstLit(FileRange, Const),
+    /// String literals, matched against arrays of bytes
+    ByteString(FileRange, StringId),
     /// Error sentinel
     ReportedError(FileRange),
 }
 
+/// An equation of a match expression, with an optional guard
+type Equation<'a> = (
+    Pattern<ByteRange>,
+    Option<Term<'a, ByteRange>>,
+    Term<'a, ByteRange>,
+);
+
 /// Scrutinee of a match expression
 struct Scrutinee<'arena> {
     range: ByteRange,
@@ -2546,6 +4060,282 @@ struct MatchInfo<'arena> {
 mod tests {
     use super::*;
 
+    use crate::source::ProgramSource;
+    use crate::surface::pretty;
+
+    #[test]
+    fn retain_prims() {
+        let interner = RefCell::new(StringInterner::new());
+        let scope = Scope::new();
+        let source = ProgramSource::try_from(String::from("fun (x : F32) (y : U32) => y")).unwrap();
+        let (term, parse_messages) = Term::parse(&interner, &scope, &source);
+        assert!(parse_messages.is_empty());
+
+        let file_id = FileId::try_from(1).unwrap();
+        let mut context = Context::new(file_id, &interner, &scope, ItemEnv::new());
+        context.retain_prims(|prim| !matches!(prim, Prim::F32Type | Prim::F64Type));
+        let mut messages = Vec::new();
+        context.elab_term(&scope, &term, &mut |message| messages.push(message));
+
+        let f32_name = interner.borrow_mut().get_or_intern("F32");
+        assert!(matches!(
+            messages.as_slice(),
+            [Message::UnboundName { name, .. }] if *name == f32_name,
+        ));
+    }
+
+    #[test]
+    fn fun_app_round_trip() {
+        let interner = RefCell::new(StringInterner::new());
+        let scope = Scope::new();
+        let file_id = FileId::try_from(1).unwrap();
+
+        // Parse, elaborate, and distill a term, printing it as an annotated term
+        let round_trip = |source: &str| {
+            let source = ProgramSource::try_from(source.to_owned()).unwrap();
+            let (term, parse_messages) = Term::parse(&interner, &scope, &source);
+            assert!(parse_messages.is_empty());
+
+            let mut context = Context::new(file_id, &interner, &scope, ItemEnv::new());
+            let mut messages = Vec::new();
+            let (term, r#type) = context.elab_term(&scope, &term, &mut |m| messages.push(m));
+            assert!(messages.is_empty());
+
+            let mut context = context.distillation_context(&scope);
+            let (term, r#type) = (context.check(&term), context.check(&r#type));
+            let context = pretty::Context::new(&interner, &scope);
+            let doc = context.term(&Term::Ann((), &term, &r#type)).into_doc();
+            doc.pretty(80).to_string()
+        };
+
+        for source in [
+            "fun (f : U8 -> U8 -> U8) (x : U8) => f x x",
+            "fun (f : U8 -> U8 -> U8) (x : U8) => f (f x x) (u8_add x 1)",
+            "fun (f : U8 -> (U8 -> U8) -> U8) (g : U8 -> U8) => f (g 1) g",
+            "fun (A : Type) (f : A -> A) (x : A) => f (f (f x))",
+        ] {
+            // Printed applications should be grouped the same way when reparsed
+            let output = round_trip(source);
+            assert_eq!(round_trip(&output), output, "round trip of `{source}`");
+        }
+    }
+
+    #[test]
+    fn tuple_round_trip() {
+        let interner = RefCell::new(StringInterner::new());
+        let scope = Scope::new();
+        let file_id = FileId::try_from(1).unwrap();
+
+        let source = "(1, 2, 3) : (U8, U16, U32)";
+        let source = ProgramSource::try_from(source.to_owned()).unwrap();
+        let (term, parse_messages) = Term::parse(&interner, &scope, &source);
+        assert!(parse_messages.is_empty());
+
+        let mut context = Context::new(file_id, &interner, &scope, ItemEnv::new());
+        let mut messages = Vec::new();
+        let (term, r#type) = context.elab_term(&scope, &term, &mut |m| messages.push(m));
+        assert!(messages.is_empty());
+
+        // Tuple elements are given positional labels
+        let labels = match &term {
+            core::Term::Ann(_, core::Term::RecordLit(_, labels, _), _) => *labels,
+            _ => panic!("expected an annotated record literal"),
+        };
+        assert!(interner.borrow_mut().is_tuple_labels(labels));
+
+        // ...which are distilled back to tuple syntax
+        let mut context = context.distillation_context(&scope);
+        let (term, r#type) = (context.check(&term), context.check(&r#type));
+        let context = pretty::Context::new(&interner, &scope);
+        let doc = context.term(&Term::Ann((), &term, &r#type)).into_doc();
+        assert_eq!(doc.pretty(80).to_string(), "(1, 2, 3) : (U8, U16, U32)");
+    }
+
+    #[test]
+    fn byte_string_pattern() {
+        let interner = RefCell::new(StringInterner::new());
+        let scope = Scope::new();
+        let file_id = FileId::try_from(1).unwrap();
+
+        let source = r#"fun (magic : Array8 2 U8) => (match magic { "ab" => 1, _ => 0 } : U8)"#;
+        let source = ProgramSource::try_from(source.to_owned()).unwrap();
+        let (term, parse_messages) = Term::parse(&interner, &scope, &source);
+        assert!(parse_messages.is_empty());
+
+        let mut context = Context::new(file_id, &interner, &scope, ItemEnv::new());
+        let mut messages = Vec::new();
+        let (term, _) = context.elab_term(&scope, &term, &mut |m| messages.push(m));
+        assert!(messages.is_empty());
+
+        // The scrutinee is bound once...
+        let match_expr = match &term {
+            core::Term::FunLit(
+                _,
+                _,
+                _,
+                core::Term::Ann(_, core::Term::Let(_, None, _, _, expr), _),
+            ) => *expr,
+            _ => panic!("expected a bound scrutinee"),
+        };
+        // ...and each byte is compared in turn, falling through on the first
+        // byte that differs
+        assert!(matches!(
+            match_expr,
+            core::Term::ConstMatch(
+                _,
+                _,
+                [
+                    _,
+                    (
+                        _,
+                        core::Term::ConstMatch(_, _, [_, (_, core::Term::ConstLit(..))], None)
+                    )
+                ],
+                None,
+            ),
+        ));
+    }
+
+    #[test]
+    fn defined_items() {
+        let interner = RefCell::new(StringInterner::new());
+        let scope = Scope::new();
+        let file_id = FileId::try_from(1).unwrap();
+        let my_u24 = interner.borrow_mut().get_or_intern("MyU24");
+
+        // def MyU24 : Format = repeat_len8 3 u8;
+        let prim = |prim| scope.to_scope(core::Term::Prim(Span::Empty, prim)) as &_;
+        let app = |head, arg| core::Term::FunApp(Span::Empty, Plicity::Explicit, head, arg);
+        let len = core::Term::ConstLit(Span::Empty, Const::U8(3, UIntStyle::Decimal));
+        let expr = app(
+            scope.to_scope(app(prim(Prim::FormatRepeatLen8), scope.to_scope(len))),
+            prim(Prim::FormatU8),
+        );
+        let mut item_env = ItemEnv::new();
+        item_env.define(my_u24, prim(Prim::FormatType), scope.to_scope(expr));
+
+        let source = ProgramSource::try_from(String::from("{ x <- MyU24 }")).unwrap();
+        let (term, parse_messages) = Term::parse(&interner, &scope, &source);
+        assert!(parse_messages.is_empty());
+
+        let mut context = Context::new(file_id, &interner, &scope, item_env);
+        let mut messages = Vec::new();
+        let format = context.elab_format(&scope, &term, &mut |m| messages.push(m));
+        assert!(messages.is_empty());
+        assert!(matches!(
+            format,
+            core::Term::FormatRecord(_, _, [core::Term::ItemVar(_, var)])
+                if *var == env::levels().next().unwrap(),
+        ));
+    }
+
+    #[test]
+    fn defined_items_are_well_typed() {
+        let interner = RefCell::new(StringInterner::new());
+        let scope = Scope::new();
+        let file_id = FileId::try_from(1).unwrap();
+        let my_u16 = interner.borrow_mut().get_or_intern("MyU16");
+        let prim = |prim| scope.to_scope(core::Term::Prim(Span::Empty, prim)) as &_;
+
+        // def MyU16 : Format = u16be;
+        let mut item_env = ItemEnv::new();
+        item_env.define(my_u16, prim(Prim::FormatType), prim(Prim::FormatU16Be));
+        let mut context = Context::new(file_id, &interner, &scope, item_env);
+        assert!(context.defined_items_are_well_typed());
+
+        // def MyU16 : Format = U16;
+        let mut item_env = ItemEnv::new();
+        item_env.define(my_u16, prim(Prim::FormatType), prim(Prim::U16Type));
+        let mut context = Context::new(file_id, &interner, &scope, item_env);
+        assert!(!context.defined_items_are_well_typed());
+        assert!(context.messages.is_empty());
+    }
+
+    #[test]
+    fn defined_items_in_module() {
+        use crate::core::binary;
+
+        let interner = RefCell::new(StringInterner::new());
+        let scope = Scope::new();
+        let file_id = FileId::try_from(1).unwrap();
+        let my_u16 = interner.borrow_mut().get_or_intern("MyU16");
+
+        // def MyU16 : Format = u16be;
+        let prim = |prim| scope.to_scope(core::Term::Prim(Span::Empty, prim)) as &_;
+        let mut item_env = ItemEnv::new();
+        item_env.define(my_u16, prim(Prim::FormatType), prim(Prim::FormatU16Be));
+
+        let source = ProgramSource::try_from(String::from("def main = { x <- MyU16 };")).unwrap();
+        let (surface_module, parse_messages) = Module::parse(&interner, &scope, &source);
+        assert!(parse_messages.is_empty());
+
+        let mut context = Context::new(file_id, &interner, &scope, item_env);
+        let mut messages = Vec::new();
+        let module = context.elab_module(&scope, &surface_module, &mut |m| messages.push(m));
+        assert!(messages.is_empty());
+        assert_eq!(module.items.len(), 2);
+
+        // The defined item comes first, so the levels of the items match
+        let data = [0x01, 0x02];
+        let mut binary_context = binary::Context::new(binary::Buffer::from(&data[..]));
+        binary_context.add_module(&module);
+        let main = core::Term::ItemVar(Span::Empty, env::levels().nth(1).unwrap());
+        let refs = binary_context.read_entrypoint(&main).unwrap();
+        match refs[&0][0].expr.as_ref() {
+            Value::RecordLit(_, exprs) => assert!(matches!(
+                exprs[0].as_ref(),
+                Value::ConstLit(Const::U16(0x0102, _)),
+            )),
+            _ => panic!("expected a record"),
+        }
+    }
+
+    #[test]
+    fn reelaborate_changed_alias() {
+        let interner = RefCell::new(StringInterner::new());
+        let scope = Scope::new();
+        let file_id = FileId::try_from(1).unwrap();
+        let label = |name| interner.borrow_mut().get_or_intern(name);
+        let (item_a, item_b, item_c) = (label("A"), label("B"), label("C"));
+
+        let mut cache = ItemCache::new();
+        let mut reelaborate = |source: &str| {
+            let source = ProgramSource::try_from(source.to_owned()).unwrap();
+            let (surface_module, parse_messages) = Module::parse(&interner, &scope, &source);
+            assert!(parse_messages.is_empty());
+
+            let mut context = Context::new(file_id, &interner, &scope, ItemEnv::new());
+            let mut messages = Vec::new();
+            let module = context.reelaborate(&scope, &mut cache, &surface_module, &mut |m| {
+                messages.push(m)
+            });
+            assert!(messages.is_empty());
+            (module, cache.recomputed().to_vec())
+        };
+
+        let source = "def A = u8; def B = A; def C = { x <- B }; def D = u16be;";
+        let (_, recomputed) = reelaborate(source);
+        assert_eq!(recomputed.len(), 4);
+
+        let (_, recomputed) = reelaborate(source);
+        assert!(recomputed.is_empty());
+
+        // Only the changed alias and its dependents are elaborated again
+        let source = "def A = u32be; def B = A; def C = { x <- B }; def D = u16be;";
+        let (module, recomputed) = reelaborate(source);
+        assert_eq!(recomputed, [item_a, item_b, item_c]);
+
+        // ...and the spans of the reused items are moved with them
+        let start = source.rfind("u16be").unwrap() as BytePos;
+        match module.items[3] {
+            core::Item::Def {
+                expr: core::Term::Prim(Span::Range(range), Prim::FormatU16Be),
+                ..
+            } => assert_eq!(range.start(), start),
+            _ => panic!("expected a reused `u16be` format"),
+        }
+    }
+
     #[test]
     #[cfg(target_pointer_width = "64")]
     fn checked_pattern_size() {
@@ -28,27 +28,54 @@ enum Error {
     CycleDetected,
 }
 
+/// Determine the order in which the items of a module should be elaborated,
+/// along with the names of the items that each item depends on.
 pub fn elaboration_order(
     elab_context: &mut elaboration::Context,
     surface_module: &Module<'_, ByteRange>,
-) -> Vec<usize> {
-    let item_names = item_names(surface_module);
+) -> (Vec<usize>, Vec<Vec<StringId>>) {
+    let item_names = item_names(elab_context, surface_module);
     let item_deps = collect_item_dependencies(surface_module, &item_names);
 
     let context = ModuleOrderContext::new(elab_context);
-    context.determine_order(surface_module.items, &item_names, &item_deps)
+    let order = context.determine_order(surface_module.items, &item_names, &item_deps);
+    (order, item_deps)
 }
 
-fn item_names(surface_module: &Module<'_, ByteRange>) -> FxHashMap<StringId, usize> {
-    surface_module
-        .items
-        .iter()
-        .enumerate()
-        .filter_map(|(i, item)| match item {
-            Item::Def(item) => Some((item.label.1, i)),
-            Item::ReportedError(_) => None,
-        })
-        .collect()
+/// Map the names of items to their indices in the module, reporting items that
+/// are defined more than once or that shadow primitives. References to these
+/// items refer to their last definition, so that they can still be elaborated.
+fn item_names(
+    elab_context: &mut elaboration::Context,
+    surface_module: &Module<'_, ByteRange>,
+) -> FxHashMap<StringId, usize> {
+    let mut item_names = FxHashMap::default();
+    let mut item_ranges = FxHashMap::default();
+
+    for (i, item) in surface_module.items.iter().enumerate() {
+        match item {
+            Item::Def(item) => {
+                let (range, label) = item.label;
+                if elab_context.prim_env.get_name(label).is_some() {
+                    elab_context.push_message(Message::ItemShadowsPrim {
+                        range: elab_context.file_range(range),
+                        label,
+                    });
+                }
+                if let Some(original_range) = item_ranges.insert(label, range) {
+                    elab_context.push_message(Message::DuplicateItemLabel {
+                        range: elab_context.file_range(range),
+                        original_range: elab_context.file_range(original_range),
+                        label,
+                    });
+                }
+                item_names.insert(label, i);
+            }
+            Item::ReportedError(_) => {}
+        }
+    }
+
+    item_names
 }
 
 fn collect_item_dependencies(
@@ -200,8 +227,11 @@ fn term_deps(
         Term::Match(_, scrutinee, equations) => {
             let initial_locals_names_len = local_names.len();
             term_deps(scrutinee, item_names, local_names, deps);
-            for (pattern, body) in *equations {
+            for (pattern, guard, body) in *equations {
                 push_pattern(pattern, local_names);
+                if let Some(guard) = guard {
+                    term_deps(guard, item_names, local_names, deps);
+                }
                 term_deps(body, item_names, local_names, deps);
             }
             local_names.truncate(initial_locals_names_len);
@@ -261,6 +291,20 @@ fn term_deps(
         Term::FormatOverlap(_, format_fields) => {
             field_deps(format_fields, item_names, local_names, deps);
         }
+        Term::FormatDo(_, format_fields, expr) => {
+            let initial_locals_names_len = local_names.len();
+            field_deps(format_fields, item_names, local_names, deps);
+            local_names.extend(format_fields.iter().map(|field| match field {
+                FormatField::Format {
+                    label: (_, label), ..
+                }
+                | FormatField::Computed {
+                    label: (_, label), ..
+                } => *label,
+            }));
+            term_deps(expr, item_names, local_names, deps);
+            local_names.truncate(initial_locals_names_len);
+        }
         Term::FormatCond(_, (_, name), format, cond) => {
             local_names.push(*name);
             term_deps(format, item_names, local_names, deps);
@@ -275,6 +319,7 @@ fn term_deps(
         | Term::Placeholder(_)
         | Term::Universe(_)
         | Term::StringLiteral(_, _)
+        | Term::CharLiteral(_, _)
         | Term::NumberLiteral(_, _)
         | Term::BooleanLiteral(_, _)
         | Term::ReportedError(_) => {}
@@ -333,6 +378,7 @@ fn push_pattern(pattern: &Pattern<ByteRange>, local_names: &mut Vec<StringId>) {
         Pattern::Name(_, name) => local_names.push(*name),
         Pattern::Placeholder(_) => {}
         Pattern::StringLiteral(_, _) => {}
+        Pattern::CharLiteral(_, _) => {}
         Pattern::NumberLiteral(_, _) => {}
         Pattern::BooleanLiteral(_, _) => {}
     }
@@ -345,6 +391,7 @@ fn pop_pattern(pattern: &Pattern<ByteRange>, local_names: &mut Vec<StringId>) {
         }
         Pattern::Placeholder(_) => {}
         Pattern::StringLiteral(_, _) => {}
+        Pattern::CharLiteral(_, _) => {}
         Pattern::NumberLiteral(_, _) => {}
         Pattern::BooleanLiteral(_, _) => {}
     }
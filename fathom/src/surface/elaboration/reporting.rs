@@ -28,6 +28,39 @@ pub enum Message {
     UnreachablePattern {
         range: FileRange,
     },
+    UnsupportedPatternGuard {
+        pattern_range: FileRange,
+        guard_range: FileRange,
+    },
+    UnsatisfiableRefinement {
+        range: FileRange,
+        field_range: FileRange,
+    },
+    FieldAfterGreedyRead {
+        range: FileRange,
+        greedy_range: FileRange,
+    },
+    FormatExceedsLimit {
+        format_range: FileRange,
+        format_size: u64,
+        limit_range: FileRange,
+        limit: u64,
+    },
+    UnknownFormatSize {
+        range: FileRange,
+    },
+    ZeroAlignment {
+        range: FileRange,
+    },
+    MixedItemUses {
+        label: StringId,
+        format_ranges: Vec<FileRange>,
+        type_ranges: Vec<FileRange>,
+    },
+    UnusedItem {
+        range: FileRange,
+        label: StringId,
+    },
     UnexpectedParameter {
         param_range: FileRange,
     },
@@ -61,6 +94,12 @@ pub enum Message {
         range: FileRange,
         labels: Vec<(FileRange, StringId)>,
     },
+    ShadowedFormatField {
+        range: FileRange,
+        shadowed_range: FileRange,
+        label: StringId,
+        is_computed: bool,
+    },
     ArrayLiteralNotSupported {
         range: FileRange,
         expected_type: String,
@@ -84,14 +123,36 @@ pub enum Message {
     NonAsciiStringLiteral {
         invalid_range: FileRange,
     },
+    InvalidStringEscape {
+        invalid_range: FileRange,
+    },
     StringLiteralNotSupported {
         range: FileRange,
         expected_type: String,
     },
+    AmbiguousCharLiteral {
+        range: FileRange,
+    },
+    InvalidCharLiteral {
+        range: FileRange,
+        found_len: usize,
+    },
+    CharLiteralOutOfRange {
+        range: FileRange,
+        type_size: usize,
+    },
+    CharLiteralNotSupported {
+        range: FileRange,
+        expected_type: String,
+    },
     InvalidNumericLiteral {
         range: FileRange,
         message: String,
     },
+    InexactFloatLiteral {
+        range: FileRange,
+        rounded: String,
+    },
     NumericLiteralNotSupported {
         range: FileRange,
         expected_type: String,
@@ -102,6 +163,10 @@ pub enum Message {
     BooleanLiteralNotSupported {
         range: FileRange,
     },
+    EvaluationLimit {
+        range: FileRange,
+        steps: u64,
+    },
     /// Unification errors.
     FailedToUnify {
         range: FileRange,
@@ -130,6 +195,17 @@ pub enum Message {
         // type: Doc<_>,
         expr: String,
     },
+    /// An item was defined more than once in the same module.
+    DuplicateItemLabel {
+        range: FileRange,
+        original_range: FileRange,
+        label: StringId,
+    },
+    /// An item has the same name as a primitive, which it shadows.
+    ItemShadowsPrim {
+        range: FileRange,
+        label: StringId,
+    },
     /// A cycle between module items was detected.
     CycleDetected {
         names: Vec<StringId>,
@@ -184,6 +260,95 @@ impl Message {
             Message::UnreachablePattern { range } => Diagnostic::warning()
                 .with_message("unreachable pattern")
                 .with_labels(vec![primary_label(range)]),
+            Message::UnsatisfiableRefinement { range, field_range } => Diagnostic::warning()
+                .with_message("refinement can never hold")
+                .with_labels(vec![
+                    primary_label(range).with_message("always false"),
+                    secondary_label(field_range)
+                        .with_message("no value of this field satisfies the refinement"),
+                ]),
+            Message::UnsupportedPatternGuard {
+                pattern_range,
+                guard_range,
+            } => Diagnostic::error()
+                .with_message("unsupported pattern guard")
+                .with_labels(vec![
+                    primary_label(guard_range).with_message("guard"),
+                    secondary_label(pattern_range).with_message("constant pattern"),
+                ])
+                .with_notes(vec![
+                    "guards are only supported on named and placeholder patterns".to_owned(),
+                ]),
+            Message::FieldAfterGreedyRead {
+                range,
+                greedy_range,
+            } => Diagnostic::warning()
+                .with_message("field will never be read")
+                .with_labels(vec![
+                    primary_label(range).with_message("unreachable field"),
+                    secondary_label(greedy_range).with_message("reads until the end of the input"),
+                ])
+                .with_notes(vec![
+                    "fields after a format that reads until the end of the input are never populated"
+                        .to_owned(),
+                ]),
+            Message::FormatExceedsLimit {
+                format_range,
+                format_size,
+                limit_range,
+                limit,
+            } => Diagnostic::warning()
+                .with_message("format will never fit within its limit")
+                .with_labels(vec![
+                    primary_label(format_range).with_message(format!("reads {format_size} bytes")),
+                    secondary_label(limit_range).with_message(format!("limited to {limit} bytes")),
+                ]),
+            Message::UnknownFormatSize { range } => Diagnostic::error()
+                .with_message("cannot determine the size of format")
+                .with_labels(vec![
+                    primary_label(range).with_message("size depends on the data being read")
+                ])
+                .with_notes(vec![
+                    "`size_of` requires a format that always reads the same number of bytes"
+                        .to_owned(),
+                ]),
+            Message::ZeroAlignment { range } => Diagnostic::error()
+                .with_message("alignment must be greater than zero")
+                .with_labels(vec![primary_label(range).with_message("zero alignment")])
+                .with_notes(vec![
+                    "formats cannot be aligned to a multiple of zero".to_owned(),
+                ]),
+            Message::MixedItemUses {
+                label,
+                format_ranges,
+                type_ranges,
+            } => {
+                let interner = interner.borrow();
+                let label = interner.resolve(*label).unwrap();
+                let type_labels = (type_ranges.iter())
+                    .map(|range| primary_label(range).with_message("used as a type"));
+                let format_labels = (format_ranges.iter())
+                    .map(|range| secondary_label(range).with_message("used as a format"));
+
+                Diagnostic::note()
+                    .with_message(format!("`{label}` is used both as a format and as a type"))
+                    .with_labels(type_labels.chain(format_labels).collect())
+                    .with_notes(vec![
+                        "formats are converted to their representation types when used as types"
+                            .to_owned(),
+                    ])
+            }
+            Message::UnusedItem { range, label } => {
+                let interner = interner.borrow();
+                let label = interner.resolve(*label).unwrap();
+
+                Diagnostic::warning()
+                    .with_message(format!("unused definition `{label}`"))
+                    .with_labels(vec![primary_label(range).with_message("never used")])
+                    .with_notes(vec![
+                        "only the `main` definition is used without being referenced".to_owned(),
+                    ])
+            }
             Message::UnexpectedParameter { param_range } => Diagnostic::error()
                 .with_message("too many parameters in function literal")
                 .with_labels(vec![
@@ -326,6 +491,29 @@ impl Message {
                             .format_with(", ", |label, f| f(&format_args!("`{label}`")))
                     )])
             }
+            Message::ShadowedFormatField {
+                range,
+                shadowed_range,
+                label,
+                is_computed,
+            } => {
+                let interner = interner.borrow();
+                let label = interner.resolve(*label).unwrap();
+                let (kind, shadowed_kind) = match is_computed {
+                    true => ("computed", "format"),
+                    false => ("format", "computed"),
+                };
+
+                Diagnostic::error()
+                    .with_message(format!(
+                        "{kind} field `{label}` shadows {shadowed_kind} field `{label}`"
+                    ))
+                    .with_labels(vec![
+                        primary_label(range).with_message("shadowing field"),
+                        secondary_label(shadowed_range).with_message("shadowed field"),
+                    ])
+                    .with_notes(vec!["labels must be unique within a record format".to_owned()])
+            }
             Message::ArrayLiteralNotSupported {
                 range,
                 expected_type,
@@ -371,6 +559,11 @@ impl Message {
                 .with_labels(vec![
                     primary_label(invalid_range).with_message("non-ASCII character")
                 ]),
+            Message::InvalidStringEscape { invalid_range } => Diagnostic::error()
+                .with_message("invalid escape sequence in string literal")
+                .with_labels(vec![
+                    primary_label(invalid_range).with_message("invalid escape sequence")
+                ]),
             Message::StringLiteralNotSupported {
                 range,
                 expected_type,
@@ -385,9 +578,38 @@ impl Message {
                 .with_labels(vec![
                     primary_label(range).with_message("type annotations needed")
                 ]),
+            Message::AmbiguousCharLiteral { range } => Diagnostic::error()
+                .with_message("ambiguous character literal")
+                .with_labels(vec![
+                    primary_label(range).with_message("type annotations needed")
+                ]),
+            Message::InvalidCharLiteral { range, found_len } => Diagnostic::error()
+                .with_message("character literal must contain exactly one character")
+                .with_labels(vec![
+                    primary_label(range).with_message("invalid character literal")
+                ])
+                .with_notes(vec![format!("found {found_len} characters")]),
+            Message::CharLiteralOutOfRange { range, type_size } => Diagnostic::error()
+                .with_message("character literal out of range")
+                .with_labels(vec![
+                    primary_label(range).with_message(format!("does not fit in {type_size} bytes"))
+                ]),
+            Message::CharLiteralNotSupported {
+                range,
+                expected_type,
+            } => Diagnostic::error()
+                .with_message("character literal not supported")
+                .with_labels(vec![
+                    primary_label(range).with_message(format!("expected `{expected_type}`"))
+                ]),
             Message::InvalidNumericLiteral { range, message } => Diagnostic::error()
                 .with_message("failed to parse numeric literal")
                 .with_labels(vec![(primary_label(range)).with_message(message)]),
+            Message::InexactFloatLiteral { range, rounded } => Diagnostic::warning()
+                .with_message("numeric literal cannot be represented exactly")
+                .with_labels(vec![
+                    primary_label(range).with_message(format!("rounded to `{rounded}`"))
+                ]),
             Message::NumericLiteralNotSupported {
                 range,
                 expected_type,
@@ -476,6 +698,12 @@ impl Message {
                         "hole `?{name}` can be replaced with `{expr}`",
                     )])
             }
+            Message::EvaluationLimit { range, steps } => Diagnostic::error()
+                .with_message("evaluation limit reached")
+                .with_labels(vec![
+                    primary_label(range).with_message("evaluation stopped while elaborating this")
+                ])
+                .with_notes(vec![format!("evaluation is limited to {steps} reduction steps")]),
             Message::UnsolvedMetaVar { source } => {
                 let (range, source_name) = match source {
                     MetaSource::ImplicitArg(range, _) => (range, "implicit argument"),
@@ -486,6 +714,7 @@ impl Message {
                     }
                     MetaSource::NamedPatternType(range, _) => (range, "named pattern type"),
                     MetaSource::MatchExprType(range) => (range, "match expression type"),
+                    MetaSource::FormatDoResultType(range) => (range, "do block result type"),
 
                     // The following should never appear in user-facing output:
                     MetaSource::HoleType(range, _) => (range, "hole type"),
@@ -499,6 +728,35 @@ impl Message {
                         primary_label(range).with_message(format!("unsolved {source_name}"))
                     ])
             }
+            Message::DuplicateItemLabel {
+                range,
+                original_range,
+                label,
+            } => {
+                let interner = interner.borrow();
+                let label = interner.resolve(*label).unwrap();
+
+                Diagnostic::error()
+                    .with_message(format!("item `{label}` is defined more than once"))
+                    .with_labels(vec![
+                        primary_label(range).with_message("redefined here"),
+                        secondary_label(original_range).with_message("first defined here"),
+                    ])
+                    .with_notes(vec![
+                        "references to this item refer to its last definition".to_owned(),
+                    ])
+            }
+            Message::ItemShadowsPrim { range, label } => {
+                let interner = interner.borrow();
+                let label = interner.resolve(*label).unwrap();
+
+                Diagnostic::warning()
+                    .with_message(format!("item `{label}` shadows a builtin definition"))
+                    .with_labels(vec![primary_label(range).with_message("shadowing item")])
+                    .with_notes(vec![format!(
+                        "references to `{label}` in this module refer to this item"
+                    )])
+            }
             Message::CycleDetected { names } => {
                 let interner = interner.borrow();
                 let names: Vec<_> = names
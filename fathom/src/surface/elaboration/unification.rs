@@ -21,7 +21,7 @@ use scoped_arena::Scope;
 
 use crate::alloc::SliceVec;
 use crate::core::semantics::{
-    self, ArcValue, Branches, Closure, Elim, Head, SplitBranches, Telescope, Value,
+    self, ArcValue, Branches, Closure, Elim, Fuel, Head, SplitBranches, Telescope, Value,
 };
 use crate::core::{Prim, Term};
 use crate::env::{EnvLen, Index, Level, SharedEnv, SliceEnv, UniqueEnv};
@@ -168,6 +168,8 @@ pub struct Context<'arena, 'env> {
     local_exprs: EnvLen,
     /// Solutions for metavariables.
     meta_exprs: &'env mut SliceEnv<Option<ArcValue<'arena>>>,
+    /// The reduction steps remaining for the item or term being elaborated.
+    fuel: &'env Fuel,
 }
 
 impl<'arena, 'env> Context<'arena, 'env> {
@@ -177,6 +179,7 @@ impl<'arena, 'env> Context<'arena, 'env> {
         item_exprs: &'env SliceEnv<ArcValue<'arena>>,
         local_exprs: EnvLen,
         meta_exprs: &'env mut SliceEnv<Option<ArcValue<'arena>>>,
+        fuel: &'env Fuel,
     ) -> Context<'arena, 'env> {
         Context {
             scope,
@@ -184,11 +187,12 @@ impl<'arena, 'env> Context<'arena, 'env> {
             item_exprs,
             local_exprs,
             meta_exprs,
+            fuel,
         }
     }
 
     fn elim_env(&self) -> semantics::ElimEnv<'arena, '_> {
-        semantics::ElimEnv::new(self.item_exprs, self.meta_exprs)
+        semantics::ElimEnv::new(self.item_exprs, self.meta_exprs).with_fuel(self.fuel)
     }
 
     /// Unify two values, updating the solution environment if necessary.
@@ -5,7 +5,8 @@ use crate::files::FileId;
 use crate::source::{BytePos, ByteRange, ProgramSource};
 
 pub const KEYWORDS: &[&str] = &[
-    "def", "else", "false", "fun", "if", "let", "match", "overlap", "then", "true", "Type", "where",
+    "def", "do", "else", "false", "fun", "if", "let", "match", "overlap", "then", "true", "Type",
+    "where",
 ];
 
 pub fn is_keyword(word: &str) -> bool {
@@ -22,11 +23,18 @@ pub enum Token<'source> {
     Hole(&'source str),
     #[regex(r#""([^"\\]|\\.)*""#, |lex| &lex.slice()[1..(lex.slice().len() - 1)])]
     StringLiteral(&'source str),
+    #[regex(r#"'([^'\\]|\\.)*'"#, |lex| &lex.slice()[1..(lex.slice().len() - 1)])]
+    CharLiteral(&'source str),
     #[regex(r"[+-]?[0-9][a-zA-Z0-9_]*")]
+    #[regex(r"[+-]?[0-9][0-9_]*(\.[0-9][0-9_]*[eE][+-]?|[eE][+-])[0-9][0-9_]*")]
+    #[regex(r"[+-]?[0-9][0-9_]*\.[0-9][0-9_]*")]
+    #[regex(r"[+-]inf")]
     NumberLiteral(&'source str),
 
     #[token("def")]
     KeywordDef,
+    #[token("do")]
+    KeywordDo,
     #[token("else")]
     KeywordElse,
     #[token("false")]
@@ -74,6 +82,8 @@ pub enum Token<'source> {
     Less,
     #[token(".")]
     FullStop,
+    #[token("#")]
+    Hash,
     #[token("/")]
     ForwardSlash,
     #[token("->")]
@@ -228,8 +238,10 @@ impl<'source> Token<'source> {
             Token::Name(_) => "name",
             Token::Hole(_) => "hole",
             Token::StringLiteral(_) => "string literal",
+            Token::CharLiteral(_) => "character literal",
             Token::NumberLiteral(_) => "number literal",
             Token::KeywordDef => "def",
+            Token::KeywordDo => "do",
             Token::KeywordElse => "else",
             Token::KeywordFalse => "false",
             Token::KeywordFun => "fun",
@@ -248,6 +260,7 @@ impl<'source> Token<'source> {
             Token::EqualsGreater => "=>",
             Token::ForwardSlash => "/",
             Token::FullStop => ".",
+            Token::Hash => "#",
             Token::HyphenGreater => "->",
             Token::LessHyphen => "<-",
             Token::Minus => "-",
@@ -89,6 +89,9 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
             Pattern::Placeholder(_) => self.text("_"),
             Pattern::Name(_, name) => self.ident(*name),
             Pattern::StringLiteral(_, number) => self.string_id(*number),
+            Pattern::CharLiteral(_, number) => {
+                self.concat([self.text("'"), self.string_id(*number), self.text("'")])
+            }
             Pattern::NumberLiteral(_, number) => self.string_id(*number),
             Pattern::BooleanLiteral(_, boolean) => match *boolean {
                 true => self.text("true"),
@@ -209,8 +212,12 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                     .group()
             }
             Term::Match(_, scrut, equations) => {
-                let equations = equations.iter().map(|(pattern, term)| {
+                let equations = equations.iter().map(|(pattern, guard, term)| {
                     self.pattern(pattern)
+                        .append(match guard {
+                            Some(guard) => self.text(" if ").append(self.term(guard)),
+                            None => self.nil(),
+                        })
                         .append(self.text(" => "))
                         .append(self.term(term))
                 });
@@ -294,6 +301,9 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
             Term::StringLiteral(_, number) => {
                 self.concat([self.text("\""), self.string_id(*number), self.text("\"")])
             }
+            Term::CharLiteral(_, number) => {
+                self.concat([self.text("'"), self.string_id(*number), self.text("'")])
+            }
             Term::NumberLiteral(_, number) => self.string_id(*number),
             Term::BooleanLiteral(_, boolean) => match *boolean {
                 true => self.text("true"),
@@ -328,6 +338,19 @@ impl<'interner, 'arena> Context<'interner, 'arena> {
                     self.text("}"),
                 )
             }
+            Term::FormatDo(_, fields, expr) => {
+                let fields = fields.iter().map(|field| {
+                    self.concat([self.format_field(field), self.text(";"), self.line()])
+                });
+                self.concat([
+                    self.text("do {"),
+                    self.concat([self.line(), self.concat(fields), self.term(expr)])
+                        .nest(INDENT),
+                    self.line(),
+                    self.text("}"),
+                ])
+                .group()
+            }
             Term::BinOp(_, lhs, op, rhs) => self.concat([
                 self.term(lhs),
                 self.space(),